@@ -0,0 +1,174 @@
+//! The audit log: one JSON object per line for every builtin and external
+//! command the shell runs, for reviewing afterwards what was run.
+//!
+//! A record holds the time the command started (`timestamp`, in seconds
+//! since the epoch), the working directory, the expanded `argv` and
+//! redirections, the `pid` that ran it, its `status` and, if it was killed,
+//! the `signal`, the `duration` in seconds and its resource usage. Programs
+//! are logged when they are reaped, with the usage wait4(2) reports for
+//! them. Builtins run in the shell process, and their usage is the shell's
+//! own over the time they ran. A command that could not be started, such as
+//! one not found, is logged with its status and no `pid`. Functions are not
+//! logged themselves, but the commands in them are. Forked subshells write
+//! to the same file, and each record is a single appending write, so records
+//! do not interleave.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use nix::libc;
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+use serde_json::{json, Value};
+
+use crate::parser::{RedirOp, Redirect};
+use crate::timing::Usage;
+use crate::Shell;
+
+pub(crate) struct AuditLog {
+    file: File,
+    /// The expanded redirections of the command about to run.
+    redirects: Vec<String>,
+    /// Programs started but not yet reaped.
+    running: HashMap<Pid, Started>,
+}
+
+/// What is known about a command when it starts.
+struct Started {
+    timestamp: SystemTime,
+    instant: Instant,
+    cwd: String,
+    argv: Vec<String>,
+    redirects: Vec<String>,
+}
+
+/// How a command ended.
+pub(crate) enum Outcome {
+    Exited(i32),
+    Signaled(Signal),
+}
+
+impl AuditLog {
+    /// Opens `path` for appending records.
+    pub(crate) fn open(path: &Path) -> Result<AuditLog, String> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        Ok(AuditLog {
+            file,
+            redirects: Vec::new(),
+            running: HashMap::new(),
+        })
+    }
+
+    fn write(&mut self, kind: &str, pid: Option<u32>, started: Started, outcome: Outcome, usage: Usage) {
+        let (status, signal) = match outcome {
+            Outcome::Exited(code) => (code, Value::Null),
+            Outcome::Signaled(signal) => (128 + signal as i32, json!(signal.as_str())),
+        };
+        let timestamp = started.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let record = json!({
+            "timestamp": timestamp.as_secs_f64(),
+            "type": kind,
+            "cwd": started.cwd,
+            "argv": started.argv,
+            "redirections": started.redirects,
+            "pid": pid,
+            "status": status,
+            "signal": signal,
+            "duration": started.instant.elapsed().as_secs_f64(),
+            "rusage": {
+                "user": usage.user.as_secs_f64(),
+                "sys": usage.sys.as_secs_f64(),
+                "maxrss": usage.max_rss,
+                "majflt": usage.major_faults,
+                "minflt": usage.minor_faults,
+                "nvcsw": usage.voluntary_switches,
+                "nivcsw": usage.involuntary_switches,
+            },
+        });
+        let _ = self.file.write_all(format!("{record}\n").as_bytes());
+    }
+}
+
+impl Shell {
+    /// Appends a record of every command the shell runs from now on to the
+    /// file at `path`.
+    pub fn set_audit_log(&mut self, path: &Path) -> Result<(), String> {
+        self.audit = Some(AuditLog::open(path)?);
+        Ok(())
+    }
+
+    /// Notes the redirections just applied for the next command, with their
+    /// targets expanded.
+    pub(crate) fn audit_redirects(&mut self, redirects: Vec<String>) {
+        if let Some(audit) = &mut self.audit {
+            audit.redirects = redirects;
+        }
+    }
+
+    fn audit_start(&mut self, argv: &[String]) -> Option<Started> {
+        let audit = self.audit.as_mut()?;
+        Some(Started {
+            timestamp: SystemTime::now(),
+            instant: Instant::now(),
+            cwd: self.current_dir.display().to_string(),
+            argv: argv.to_vec(),
+            redirects: std::mem::take(&mut audit.redirects),
+        })
+    }
+
+    /// Remembers a program just started as `pid`, to log when it is reaped.
+    pub(crate) fn audit_spawned(&mut self, pid: Pid, argv: &[String]) {
+        if let Some(started) = self.audit_start(argv)
+            && let Some(audit) = &mut self.audit
+        {
+            audit.running.insert(pid, started);
+        }
+    }
+
+    /// Logs a program the shell started, now that it has been reaped.
+    pub(crate) fn audit_reaped(&mut self, pid: Pid, outcome: Outcome, usage: &libc::rusage) {
+        if let Some(audit) = &mut self.audit
+            && let Some(started) = audit.running.remove(&pid)
+        {
+            audit.write("external", Some(pid.as_raw() as u32), started, outcome, Usage::from_rusage(usage));
+        }
+    }
+
+    /// Logs a command that failed with `status` before anything ran it.
+    pub(crate) fn audit_unstarted(&mut self, argv: &[String], status: i32) {
+        if let Some(started) = self.audit_start(argv)
+            && let Some(audit) = &mut self.audit
+        {
+            audit.write("external", None, started, Outcome::Exited(status), Usage::default());
+        }
+    }
+
+    /// Runs a builtin, logging it if there is an audit log.
+    pub(crate) fn run_audited_builtin(&mut self, argv: &[String]) -> i32 {
+        let Some(started) = self.audit_start(argv) else {
+            return self.run_builtin(argv);
+        };
+        let before = Usage::own();
+        let status = self.run_builtin(argv);
+        let usage = Usage::own().since(before);
+        if let Some(audit) = &mut self.audit {
+            audit.write("builtin", Some(std::process::id()), started, Outcome::Exited(status), usage);
+        }
+        status
+    }
+}
+
+/// A redirection as the audit log shows it, such as `2>&1` or `1>out.txt`.
+pub(crate) fn describe_redirect(redirect: &Redirect, fd: i32, target: &str) -> String {
+    match redirect.op {
+        RedirOp::OutErr | RedirOp::AppendOutErr => format!("{}{target}", redirect.op.as_str()),
+        op => format!("{fd}{}{target}", op.as_str()),
+    }
+}
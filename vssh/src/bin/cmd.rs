@@ -0,0 +1,134 @@
+//! A fixture for the shell's tests: a command whose behaviour is chosen by
+//! its arguments.
+//!
+//! With no directive it prints each argument on a line of its own, which is
+//! enough to check word splitting. Otherwise the directives run in order:
+//!
+//! * `--argv ARGS...` prints the remaining arguments as a JSON array.
+//! * `--env [NAME...]` prints the environment, or just the named variables
+//!   (`null` when unset), as a JSON object.
+//! * `--stdin` copies standard input to standard output.
+//! * `--stderr TEXT` prints TEXT on standard error.
+//! * `--sleep SECONDS` sleeps; fractions are allowed.
+//! * `--fds` prints the open descriptors and what they refer to as a JSON
+//!   object.
+//! * `--pgid` prints the pid, parent pid, process group, session and the
+//!   terminal's foreground group as a JSON object.
+//! * `--signal NAME` sends itself a signal, such as `TERM` or `SIGKILL`.
+//! * `--exit N` exits with status N.
+//!
+//! For example, `cmd --stderr oops --exit 3` complains and fails.
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+use std::str::FromStr;
+use std::time::Duration;
+
+use nix::sys::signal::{raise, Signal};
+use nix::unistd::{getpgrp, getpid, getppid, getsid, tcgetpgrp};
+use serde_json::{json, Map, Value};
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.first().is_some_and(|arg| arg.starts_with("--")) {
+        for arg in &args {
+            println!("{arg}");
+        }
+        return;
+    }
+
+    let mut i = 0;
+    while let Some(directive) = args.get(i) {
+        i += 1;
+        let result = match directive.as_str() {
+            "--argv" => {
+                print_json(&json!(args[i..]));
+                i = args.len();
+                Ok(())
+            }
+            "--env" => {
+                let count = args[i..].iter().take_while(|arg| !arg.starts_with("--")).count();
+                print_env(&args[i..i + count]);
+                i += count;
+                Ok(())
+            }
+            "--stdin" => io::copy(&mut io::stdin().lock(), &mut io::stdout().lock())
+                .map(drop)
+                .map_err(|e| format!("--stdin: {e}")),
+            "--stderr" => operand(&args, &mut i).map(|text: String| eprintln!("{text}")),
+            "--sleep" => operand(&args, &mut i).map(|seconds: f64| std::thread::sleep(Duration::from_secs_f64(seconds))),
+            "--fds" => {
+                print_fds();
+                Ok(())
+            }
+            "--pgid" => {
+                print_json(&json!({
+                    "pid": getpid().as_raw(),
+                    "ppid": getppid().as_raw(),
+                    "pgid": getpgrp().as_raw(),
+                    "sid": getsid(None).map(|sid| sid.as_raw()).ok(),
+                    "foreground": tcgetpgrp(0).map(|pgid| pgid.as_raw()).ok(),
+                }));
+                Ok(())
+            }
+            "--signal" => operand(&args, &mut i).and_then(|name: String| {
+                let name = name.to_uppercase();
+                let name = if name.starts_with("SIG") { name } else { format!("SIG{name}") };
+                let signal = Signal::from_str(&name).map_err(|_| format!("--signal: unknown signal {name}"))?;
+                raise(signal).map_err(|e| format!("--signal: {e}"))
+            }),
+            "--exit" => operand(&args, &mut i).map(|status: i32| {
+                let _ = io::stdout().flush();
+                process::exit(status)
+            }),
+            _ => Err(format!("{directive}: unknown directive")),
+        };
+        if let Err(e) = result {
+            eprintln!("cmd: {e}");
+            process::exit(2);
+        }
+    }
+}
+
+/// Takes and parses the argument of the directive just before `args[*i]`.
+fn operand<T: FromStr>(args: &[String], i: &mut usize) -> Result<T, String> {
+    let directive = &args[*i - 1];
+    let arg = args.get(*i).ok_or(format!("{directive}: argument required"))?;
+    *i += 1;
+    arg.parse().map_err(|_| format!("{directive}: invalid argument {arg}"))
+}
+
+fn print_json(value: &Value) {
+    let mut stdout = io::stdout().lock();
+    let _ = writeln!(stdout, "{value}");
+}
+
+fn print_env(names: &[String]) {
+    let vars: Map<String, Value> = if names.is_empty() {
+        env::vars().map(|(name, value)| (name, Value::String(value))).collect()
+    } else {
+        names
+            .iter()
+            .map(|name| (name.clone(), env::var(name).map(Value::String).unwrap_or(Value::Null)))
+            .collect()
+    };
+    print_json(&Value::Object(vars));
+}
+
+/// Lists /proc/self/fd, leaving out the descriptor used to read it.
+fn print_fds() {
+    // Collecting just the numbers closes the directory before the links are read.
+    let fds: Vec<i32> = fs::read_dir("/proc/self/fd")
+        .map(|entries| entries.flatten().filter_map(|entry| entry.file_name().to_str()?.parse().ok()).collect())
+        .unwrap_or_default();
+    let fds: Map<String, Value> = fds
+        .into_iter()
+        .filter_map(|fd| {
+            let target = fs::read_link(format!("/proc/self/fd/{fd}")).ok()?;
+            Some((fd.to_string(), Value::String(target.display().to_string())))
+        })
+        .collect();
+    print_json(&Value::Object(fds));
+}
@@ -0,0 +1,120 @@
+//! The `vssh` binary: option parsing around [`vssh::Shell`].
+
+use std::env;
+use std::path::Path;
+
+use vssh::Shell;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mut shell = Shell::new();
+
+    // vssh [-euxC] [-o option] [--seccomp-profile file] [--audit-log file]
+    //      [-c command [name [args...]] | script [args...]]
+    //      [--listen addr | --connect addr] [--secret-file file]
+    let mut command = None;
+    let mut listen = None;
+    let mut connect = None;
+    let mut secret_file = None;
+    let mut audit_log = env::var("VSSH_AUDIT_LOG").ok().filter(|path| !path.is_empty());
+    let mut i = 1;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        i += 1;
+        let result = match arg {
+            "-c" => match args.get(i) {
+                Some(text) => {
+                    command = Some(text.clone());
+                    i += 1;
+                    break;
+                }
+                None => Err(format!("{arg}: option requires an argument")),
+            },
+            "--" => break,
+            "--listen" | "--connect" | "--secret-file" | "--audit-log" => {
+                let value = args.get(i).cloned();
+                i += 1;
+                let slot = match arg {
+                    "--listen" => &mut listen,
+                    "--connect" => &mut connect,
+                    "--audit-log" => &mut audit_log,
+                    _ => &mut secret_file,
+                };
+                *slot = value;
+                slot.as_ref().map(drop).ok_or(format!("{arg}: option requires an argument"))
+            }
+            "--seccomp-profile" => {
+                let path = args.get(i).cloned().unwrap_or_default();
+                i += 1;
+                shell.set_seccomp_profile(Path::new(&path))
+            }
+            "-o" | "+o" => {
+                let name = args.get(i).cloned().unwrap_or_default();
+                i += 1;
+                shell.set_option(&name, arg == "-o")
+            }
+            _ if arg.len() > 1 && (arg.starts_with('-') || arg.starts_with('+')) => arg[1..]
+                .chars()
+                .try_for_each(|letter| shell.set_option_letter(letter, arg.starts_with('-'))),
+            _ => {
+                i -= 1;
+                break;
+            }
+        };
+        if let Err(e) = result {
+            eprintln!("vssh: {e}");
+            std::process::exit(2);
+        }
+    }
+
+    if let Some(path) = audit_log
+        && let Err(e) = shell.set_audit_log(Path::new(&path))
+    {
+        eprintln!("vssh: audit log: {e}");
+        std::process::exit(2);
+    }
+
+    if listen.is_some() || connect.is_some() {
+        let secret = match secret_file {
+            Some(path) => std::fs::read_to_string(&path).map(|s| s.trim().to_string()).map_err(|e| format!("{path}: {e}")),
+            None => env::var("VSSH_SECRET").map_err(|_| "no shared secret: set VSSH_SECRET or use --secret-file".to_string()),
+        };
+        let secret = secret.and_then(|s| {
+            Some(s)
+                .filter(|s| !s.is_empty())
+                .ok_or_else(|| "the shared secret is empty".to_string())
+        });
+        let secret = match secret {
+            Ok(secret) => secret,
+            Err(e) => {
+                eprintln!("vssh: {e}");
+                std::process::exit(2);
+            }
+        };
+        let status = match (listen, connect) {
+            (Some(addr), _) => shell.serve(&addr, secret),
+            (None, Some(addr)) => vssh::connect(&addr, &secret),
+            (None, None) => unreachable!(),
+        };
+        std::process::exit(status);
+    }
+
+    if let Some(command) = command {
+        if let Some(name) = args.get(i) {
+            shell.set_args(name, &args[i + 1..]);
+        }
+        shell.execute_command(&command);
+    } else if let Some(script) = args.get(i) {
+        shell.set_args(script, &args[i + 1..]);
+        shell.run_script(script);
+    } else {
+        let interactive = nix::unistd::isatty(0).unwrap_or(false);
+        shell.set_interactive(interactive);
+        if interactive {
+            println!("Simple Rust Shell - Type 'exit' to quit");
+        }
+        shell.run();
+    }
+    shell.run_exit_trap();
+    std::process::exit(shell.last_status());
+}
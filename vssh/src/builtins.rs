@@ -0,0 +1,655 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::options::Options;
+use crate::parser::{Assignment, Word, RESERVED_WORDS};
+use crate::signals::{self, Disposition, Trap};
+use crate::vars::{self, ArrayKind};
+use crate::Shell;
+
+pub(crate) const BUILTINS: &[&str] = &[
+    ":", "[", "cd", "command", "declare", "dirs", "echo", "every", "exec", "exit", "export", "false", "fifo", "hash", "isolate", "jobs", "kill",
+    "limit", "popd", "printf", "pushd", "pty", "pwd", "read", "record", "repeat", "return", "sandbox", "set", "shopt", "test", "timeout",
+    "trap", "true", "type", "typeset", "ulimit", "unset", "vsshenv", "wait",
+];
+
+pub fn is_builtin(name: &str) -> bool {
+    BUILTINS.contains(&name)
+}
+
+/// What a command name refers to, as `type` reports it.
+enum CommandKind {
+    Keyword,
+    Function,
+    Builtin,
+    /// A program the hash table remembers, reported only as the first match.
+    Hashed(PathBuf),
+    File(PathBuf),
+}
+
+/// An operand of a declaration builtin: a plain word or an assignment.
+enum DeclArg {
+    Word(String),
+    Assign(Assignment),
+}
+
+impl Shell {
+    pub(crate) fn run_builtin(&mut self, argv: &[String]) -> i32 {
+        match argv[0].as_str() {
+            ":" | "true" => 0,
+            "false" => 1,
+            "[" | "test" => self.builtin_test(argv),
+            "cd" => self.builtin_cd(argv),
+            "command" => self.builtin_command(argv),
+            "dirs" => self.builtin_dirs(argv),
+            "echo" => self.builtin_echo(argv),
+            "exec" => self.builtin_exec(argv),
+            "exit" => self.builtin_exit(argv),
+            "fifo" => self.builtin_fifo(argv),
+            "hash" => self.builtin_hash(argv),
+            "isolate" => self.builtin_isolate(argv),
+            "jobs" => self.builtin_jobs(argv),
+            "kill" => self.builtin_kill(argv),
+            "limit" => self.builtin_limit(argv),
+            "popd" => self.builtin_popd(argv),
+            "printf" => self.builtin_printf(argv),
+            "pushd" => self.builtin_pushd(argv),
+            "pty" => self.builtin_pty(argv),
+            "pwd" => self.builtin_pwd(argv),
+            "read" => self.builtin_read(argv),
+            "record" => self.builtin_record(argv),
+            "repeat" | "every" => self.builtin_repeat(argv),
+            "return" => self.builtin_return(argv),
+            "sandbox" => self.builtin_sandbox(argv),
+            "set" => self.builtin_set(argv),
+            "shopt" => self.builtin_shopt(argv),
+            "timeout" => self.builtin_timeout(argv),
+            "trap" => self.builtin_trap(argv),
+            "type" => self.builtin_type(argv),
+            "ulimit" => self.builtin_ulimit(argv),
+            "unset" => self.builtin_unset(argv),
+            "vsshenv" => self.builtin_vsshenv(argv),
+            "wait" => self.builtin_wait(argv),
+            // Declaration builtins normally go through `run_declaration`, but
+            // may still arrive here when invoked indirectly, e.g. `$cmd x=1`.
+            name @ ("declare" | "typeset" | "export") => {
+                let args = argv[1..].iter().map(|a| DeclArg::Word(a.clone())).collect();
+                self.declare(name, args)
+            }
+            _ => 127,
+        }
+    }
+
+    /// Runs `declare`, `typeset` or `export`, whose assignment operands keep
+    /// their structure instead of being expanded into plain words.
+    pub(crate) fn run_declaration(&mut self, words: &[Word]) -> i32 {
+        let mut args = Vec::new();
+        for word in &words[1..] {
+            match &word.assignment {
+                Some(assignment) => args.push(DeclArg::Assign((**assignment).clone())),
+                None => match self.expand_words(std::slice::from_ref(word)) {
+                    Ok(fields) => args.extend(fields.into_iter().map(DeclArg::Word)),
+                    Err(e) => {
+                        self.report_error(e);
+                        return 1;
+                    }
+                },
+            }
+        }
+        self.declare(&words[0].raw, args)
+    }
+
+    fn declare(&mut self, builtin: &str, args: Vec<DeclArg>) -> i32 {
+        let mut kind = None;
+        let mut export = builtin == "export";
+        let mut unexport = false;
+        let mut print = false;
+        let mut operands = Vec::new();
+        let mut options_done = false;
+
+        for arg in args {
+            match arg {
+                DeclArg::Word(w) if !options_done && w == "--" => options_done = true,
+                DeclArg::Word(w) if !options_done && w.len() > 1 && (w.starts_with('-') || w.starts_with('+')) => {
+                    let on = w.starts_with('-');
+                    for flag in w[1..].chars() {
+                        match (flag, builtin) {
+                            ('a', "declare" | "typeset") => kind = Some(ArrayKind::Indexed),
+                            ('A', "declare" | "typeset") => kind = Some(ArrayKind::Assoc),
+                            ('x', "declare" | "typeset") => {
+                                export = on;
+                                unexport = !on;
+                            }
+                            ('n', "export") => {
+                                export = false;
+                                unexport = true;
+                            }
+                            ('p', _) => print = true,
+                            ('g', "declare" | "typeset") => {}
+                            _ => {
+                                self.report_error(format_args!("{builtin}: {w}: invalid option"));
+                                return 2;
+                            }
+                        }
+                    }
+                }
+                arg => {
+                    options_done = true;
+                    operands.push(arg);
+                }
+            }
+        }
+
+        if operands.is_empty() {
+            let mut out = self.fds.writer(1);
+            for (name, var) in self.env_vars.sorted() {
+                if builtin == "export" && !var.exported {
+                    continue;
+                }
+                if kind == Some(ArrayKind::Indexed) && !matches!(var.value, vars::Value::Indexed(_)) {
+                    continue;
+                }
+                if kind == Some(ArrayKind::Assoc) && !matches!(var.value, vars::Value::Assoc(_)) {
+                    continue;
+                }
+                let _ = writeln!(out, "{}", vars::declaration(name, var));
+            }
+            return 0;
+        }
+
+        let mut status = 0;
+        for operand in operands {
+            let assignment = match operand {
+                DeclArg::Assign(assignment) => assignment,
+                DeclArg::Word(word) => match crate::parser::parse_assignment_text(&word) {
+                    Some(assignment) => assignment,
+                    None => {
+                        if !vars::is_valid_name(&word) {
+                            self.report_error(format_args!("{builtin}: `{word}': not a valid identifier"));
+                            status = 1;
+                            continue;
+                        }
+                        if print {
+                            match self.env_vars.get(&word) {
+                                Some(var) => {
+                                    let line = vars::declaration(&word, var);
+                                    let _ = writeln!(self.fds.writer(1), "{line}");
+                                }
+                                None => {
+                                    self.report_error(format_args!("{builtin}: {word}: not found"));
+                                    status = 1;
+                                }
+                            }
+                            continue;
+                        }
+                        if let Err(e) = self.apply_attributes(&word, kind, export, unexport) {
+                            self.report_error(format_args!("{builtin}: {e}"));
+                            status = 1;
+                        }
+                        continue;
+                    }
+                },
+            };
+            let name = assignment.name.clone();
+            let result = self
+                .apply_attributes(&name, kind, false, false)
+                .and_then(|()| self.assign(&assignment))
+                .and_then(|_| self.apply_attributes(&name, None, export, unexport));
+            if let Err(e) = result {
+                self.report_error(format_args!("{builtin}: {e}"));
+                status = 1;
+            }
+        }
+        status
+    }
+
+    fn apply_attributes(&mut self, name: &str, kind: Option<ArrayKind>, export: bool, unexport: bool) -> Result<(), String> {
+        if let Some(kind) = kind {
+            self.check_restricted_variable(name)?;
+            self.env_vars.declare_array(name, kind)?;
+        }
+        if export {
+            self.env_vars.set_exported(name, true);
+        } else if unexport {
+            self.env_vars.set_exported(name, false);
+        }
+        Ok(())
+    }
+
+    fn builtin_unset(&mut self, argv: &[String]) -> i32 {
+        let mut status = 0;
+        let mut functions = false;
+        for arg in &argv[1..] {
+            match arg.as_str() {
+                "-v" => functions = false,
+                "-f" => functions = true,
+                _ if functions => {
+                    self.functions.remove(arg);
+                }
+                _ => {}
+            }
+            if functions || arg == "-v" {
+                continue;
+            }
+            let name = arg.split('[').next().unwrap_or_default();
+            if let Err(e) = self.check_restricted_variable(name) {
+                self.report_error(format_args!("unset: {e}"));
+                status = 1;
+                continue;
+            }
+            if let Some(open) = arg.find('[').filter(|_| arg.ends_with(']')) {
+                let name = &arg[..open];
+                let index = &arg[open + 1..arg.len() - 1];
+                match self.eval_subscript(name, index) {
+                    Ok(subscript) => self.env_vars.unset_element(name, &subscript),
+                    Err(e) => {
+                        self.report_error(format_args!("unset: {e}"));
+                        status = 1;
+                    }
+                }
+            } else if vars::is_valid_name(arg) {
+                self.env_vars.unset(arg);
+            } else {
+                self.report_error(format_args!("unset: `{arg}': not a valid identifier"));
+                status = 1;
+            }
+        }
+        status
+    }
+
+    /// `set [-euxC] [-o name] [--] [args...]`: toggles options or replaces the
+    /// positional parameters.
+    fn builtin_set(&mut self, argv: &[String]) -> i32 {
+        if argv.len() == 1 {
+            let mut out = self.fds.writer(1);
+            for (name, var) in self.env_vars.sorted() {
+                let line = vars::declaration(name, var);
+                let assignment = line.splitn(3, ' ').nth(2).unwrap_or_default();
+                let _ = writeln!(out, "{assignment}");
+            }
+            return 0;
+        }
+
+        let mut i = 1;
+        while i < argv.len() {
+            let arg = argv[i].as_str();
+            let on = arg.starts_with('-');
+            if arg == "--" {
+                self.positional = argv[i + 1..].to_vec();
+                return 0;
+            }
+            if arg.len() < 2 || !(arg.starts_with('-') || arg.starts_with('+')) {
+                break;
+            }
+            i += 1;
+            let result = if &arg[1..] == "o" {
+                match argv.get(i) {
+                    Some(name) => {
+                        i += 1;
+                        self.options.set(name, on, false)
+                    }
+                    None => {
+                        self.print_options(true, !on);
+                        Ok(())
+                    }
+                }
+            } else {
+                arg[1..].chars().try_for_each(|letter| self.options.set_letter(letter, on))
+            };
+            if let Err(e) = result {
+                self.report_error(format_args!("set: {e}"));
+                return 2;
+            }
+        }
+        if i < argv.len() {
+            self.positional = argv[i..].to_vec();
+        }
+        0
+    }
+
+    /// `shopt [-pqsuo] [name...]`
+    fn builtin_shopt(&mut self, argv: &[String]) -> i32 {
+        let mut enable = None;
+        let mut quiet = false;
+        let mut print = false;
+        let mut set_options = false;
+        let mut names = Vec::new();
+        for arg in &argv[1..] {
+            if names.is_empty() && arg.len() > 1 && arg.starts_with('-') {
+                for flag in arg[1..].chars() {
+                    match flag {
+                        's' => enable = Some(true),
+                        'u' => enable = Some(false),
+                        'q' => quiet = true,
+                        'p' => print = true,
+                        'o' => set_options = true,
+                        _ => {
+                            self.report_error(format_args!("shopt: -{flag}: invalid option"));
+                            return 2;
+                        }
+                    }
+                }
+            } else {
+                names.push(arg.as_str());
+            }
+        }
+
+        if names.is_empty() {
+            if enable.is_none() || print {
+                self.print_options(set_options, print);
+            }
+            return 0;
+        }
+
+        let mut status = 0;
+        for name in names {
+            if !Options::specs(!set_options).any(|spec| spec.name == name) {
+                self.report_error(format_args!("shopt: {name}: invalid shell option name"));
+                status = 1;
+                continue;
+            }
+            match enable {
+                Some(on) => {
+                    if let Err(e) = self.options.set(name, on, !set_options) {
+                        self.report_error(format_args!("shopt: {e}"));
+                        status = 1;
+                    }
+                }
+                None => {
+                    let on = self.options.get(name);
+                    if !quiet {
+                        let _ = writeln!(self.fds.writer(1), "{name:<15}\t{}", if on { "on" } else { "off" });
+                    }
+                    if !on {
+                        status = 1;
+                    }
+                }
+            }
+        }
+        status
+    }
+
+    /// Lists `set -o` or `shopt` options, either as a table or, with
+    /// `as_commands`, as the commands that would restore them.
+    fn print_options(&self, set_options: bool, as_commands: bool) {
+        let mut out = self.fds.writer(1);
+        for spec in Options::specs(!set_options) {
+            let on = self.options.get(spec.name);
+            let _ = match (as_commands, spec.shopt) {
+                (true, false) => writeln!(out, "set {}o {}", if on { '-' } else { '+' }, spec.name),
+                (true, true) => writeln!(out, "shopt -{} {}", if on { 's' } else { 'u' }, spec.name),
+                (false, _) => writeln!(out, "{:<15}\t{}", spec.name, if on { "on" } else { "off" }),
+            };
+        }
+    }
+
+    /// `trap [-lp] [[action] condition...]`
+    fn builtin_trap(&mut self, argv: &[String]) -> i32 {
+        let mut args = &argv[1..];
+        let mut print = false;
+        while let Some(first) = args.first() {
+            match first.as_str() {
+                "-p" => print = true,
+                "-l" => {
+                    let mut out = self.fds.writer(1);
+                    for signal in nix::sys::signal::Signal::iterator() {
+                        let _ = writeln!(out, "{:2}) {}", signal as i32, signal.as_str());
+                    }
+                    return 0;
+                }
+                "--" => {
+                    args = &args[1..];
+                    break;
+                }
+                _ => break,
+            }
+            args = &args[1..];
+        }
+
+        let mut status = 0;
+        let mut conditions = Vec::new();
+        // A lone operand, a leading number or an action of `-` resets the
+        // conditions to their defaults.
+        let (action, specs) = match args {
+            _ if print || args.is_empty() => (None, args),
+            [_] => (None, args),
+            [first, rest @ ..] if first == "-" => (None, rest),
+            [first, ..] if first.parse::<u32>().is_ok() => (None, args),
+            [first, rest @ ..] => (Some(first.as_str()), rest),
+            [] => unreachable!(),
+        };
+        for spec in specs {
+            match Trap::parse(spec) {
+                Some(trap) => conditions.push(trap),
+                None => {
+                    self.report_error(format_args!("trap: {spec}: invalid signal specification"));
+                    status = 1;
+                }
+            }
+        }
+
+        if print || args.is_empty() {
+            if args.is_empty() {
+                conditions = self.traps.keys().copied().collect();
+            }
+            let mut out = self.fds.writer(1);
+            for trap in conditions {
+                if let Some(action) = self.traps.get(&trap) {
+                    let _ = writeln!(out, "trap -- '{}' {}", action.replace('\'', r"'\''"), trap.name());
+                }
+            }
+            return status;
+        }
+
+        for trap in conditions {
+            let disposition = match action {
+                None => Disposition::Default,
+                Some("") => Disposition::Ignore,
+                Some(_) => Disposition::Catch,
+            };
+            if let Trap::Signal(signal) = trap
+                && let Err(e) = signals::set_disposition(signal, disposition)
+            {
+                self.report_error(format_args!("trap: {e}"));
+                status = 1;
+                continue;
+            }
+            match action {
+                Some(action) => self.traps.insert(trap, action.to_string()),
+                None => self.traps.remove(&trap),
+            };
+        }
+        status
+    }
+
+    /// `pwd [-L|-P]`
+    fn builtin_pwd(&mut self, argv: &[String]) -> i32 {
+        let mut physical = self.options.get("physical");
+        for arg in &argv[1..] {
+            match arg.as_str() {
+                "-L" => physical = false,
+                "-P" => physical = true,
+                _ => {
+                    self.report_error(format_args!("pwd: {arg}: invalid option"));
+                    return 2;
+                }
+            }
+        }
+        let dir = if physical {
+            std::fs::canonicalize(&self.current_dir).unwrap_or_else(|_| self.current_dir.clone())
+        } else {
+            self.current_dir.clone()
+        };
+        let _ = writeln!(self.fds.writer(1), "{}", dir.display());
+        0
+    }
+
+    /// Everything `name` could run, in the order the shell would try it.
+    fn command_kinds(&self, name: &str) -> Vec<CommandKind> {
+        let mut kinds = Vec::new();
+        if RESERVED_WORDS.contains(&name) {
+            kinds.push(CommandKind::Keyword);
+        }
+        if self.functions.contains_key(name) {
+            kinds.push(CommandKind::Function);
+        }
+        if is_builtin(name) {
+            kinds.push(CommandKind::Builtin);
+        }
+        if let Some(path) = self.hashed_path(name) {
+            kinds.push(CommandKind::Hashed(path.to_path_buf()));
+        }
+        kinds.extend(self.find_in_path(name).into_iter().map(CommandKind::File));
+        kinds
+    }
+
+    /// `type [-aptP] name...`
+    fn builtin_type(&mut self, argv: &[String]) -> i32 {
+        let (mut all, mut terse, mut path_only, mut force_path) = (false, false, false, false);
+        let mut names = Vec::new();
+        for arg in &argv[1..] {
+            match arg.strip_prefix('-') {
+                Some(flags) if names.is_empty() && !flags.is_empty() => {
+                    for flag in flags.chars() {
+                        match flag {
+                            'a' => all = true,
+                            't' => terse = true,
+                            'p' => path_only = true,
+                            'P' => force_path = true,
+                            _ => {
+                                self.report_error(format_args!("type: -{flag}: invalid option"));
+                                return 2;
+                            }
+                        }
+                    }
+                }
+                _ => names.push(arg.as_str()),
+            }
+        }
+
+        let mut status = 0;
+        let mut out = self.fds.writer(1);
+        for name in names {
+            let mut kinds = self.command_kinds(name);
+            if force_path {
+                kinds.retain(|kind| matches!(kind, CommandKind::File(_)));
+            }
+            if all {
+                // `-a` lists every file along `$PATH` rather than the remembered one.
+                kinds.retain(|kind| !matches!(kind, CommandKind::Hashed(_)));
+            } else {
+                kinds.truncate(1);
+            }
+            if kinds.is_empty() {
+                if !terse && !path_only && !force_path {
+                    self.report_error(format_args!("type: {name}: not found"));
+                }
+                status = 1;
+            }
+            for kind in kinds {
+                let _ = match (kind, terse, path_only || force_path) {
+                    (CommandKind::Keyword, true, _) => writeln!(out, "keyword"),
+                    (CommandKind::Function, true, _) => writeln!(out, "function"),
+                    (CommandKind::Builtin, true, _) => writeln!(out, "builtin"),
+                    (CommandKind::File(_) | CommandKind::Hashed(_), true, _) => writeln!(out, "file"),
+                    (CommandKind::File(path) | CommandKind::Hashed(path), false, true) => {
+                        writeln!(out, "{}", path.display())
+                    }
+                    (_, false, true) => Ok(()),
+                    (CommandKind::Keyword, false, false) => writeln!(out, "{name} is a shell keyword"),
+                    (CommandKind::Function, false, false) => writeln!(out, "{name} is a function"),
+                    (CommandKind::Builtin, false, false) => writeln!(out, "{name} is a shell builtin"),
+                    (CommandKind::Hashed(path), false, false) => writeln!(out, "{name} is hashed ({})", path.display()),
+                    (CommandKind::File(path), false, false) => writeln!(out, "{name} is {}", path.display()),
+                };
+            }
+        }
+        status
+    }
+
+    /// `command [-vV] name [args...]`: runs a builtin or program, or with
+    /// `-v`/`-V` describes it.
+    fn builtin_command(&mut self, argv: &[String]) -> i32 {
+        let describe = match argv.get(1).map(String::as_str) {
+            Some("-v") => Some(false),
+            Some("-V") => Some(true),
+            _ => None,
+        };
+        let Some(verbose) = describe else {
+            let args = match argv.get(1).map(String::as_str) {
+                Some("--") => &argv[2..],
+                _ => &argv[1..],
+            };
+            if args.is_empty() {
+                return 0;
+            }
+            return self.run_argv(args);
+        };
+
+        let mut status = 0;
+        for name in &argv[2..] {
+            match self.command_kinds(name).into_iter().next() {
+                Some(kind) if verbose => {
+                    let _ = match kind {
+                        CommandKind::Keyword => writeln!(self.fds.writer(1), "{name} is a shell keyword"),
+                        CommandKind::Function => writeln!(self.fds.writer(1), "{name} is a function"),
+                        CommandKind::Builtin => writeln!(self.fds.writer(1), "{name} is a shell builtin"),
+                        CommandKind::Hashed(path) => {
+                            writeln!(self.fds.writer(1), "{name} is hashed ({})", path.display())
+                        }
+                        CommandKind::File(path) => writeln!(self.fds.writer(1), "{name} is {}", path.display()),
+                    };
+                }
+                Some(CommandKind::File(path) | CommandKind::Hashed(path)) => {
+                    let _ = writeln!(self.fds.writer(1), "{}", path.display());
+                }
+                Some(_) => {
+                    let _ = writeln!(self.fds.writer(1), "{name}");
+                }
+                None => {
+                    if verbose {
+                        self.report_error(format_args!("command: {name}: not found"));
+                    }
+                    status = 1;
+                }
+            }
+        }
+        status
+    }
+
+    /// `return [n]`: leaves the running function with status `n`, or with
+    /// the status of the last command.
+    fn builtin_return(&mut self, argv: &[String]) -> i32 {
+        if self.function_depth == 0 {
+            self.report_error("return: can only `return' from a function");
+            return 1;
+        }
+        let status = match argv.get(1) {
+            None => self.last_status,
+            Some(code) => match code.parse::<i64>() {
+                Ok(code) => (code & 0xff) as i32,
+                Err(_) => {
+                    self.report_error(format_args!("return: {code}: numeric argument required"));
+                    2
+                }
+            },
+        };
+        self.last_status = status;
+        self.returning = true;
+        status
+    }
+
+    fn builtin_exit(&mut self, argv: &[String]) -> i32 {
+        self.running = false;
+        match argv.get(1) {
+            None => self.last_status,
+            Some(code) => match code.parse::<i64>() {
+                Ok(code) => (code & 0xff) as i32,
+                Err(_) => {
+                    self.report_error(format_args!("exit: {code}: numeric argument required"));
+                    2
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,390 @@
+//! Conditional expressions: the `test` and `[` builtins and `[[ ... ]]`.
+
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fs::Metadata;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::path::Path;
+
+use nix::libc;
+use nix::unistd::{access, AccessFlags};
+
+use crate::arith;
+use crate::expand::Chunk;
+use crate::parser::{Word, WordPart};
+use crate::vars::Value;
+use crate::Shell;
+
+/// One operand of a conditional expression.
+pub struct Arg {
+    pub text: String,
+    /// For `[[`, the text as a glob or regex, with quoted parts escaped.
+    pub pattern: Option<String>,
+    /// Whether the word may act as an operator. Inside `[[` only unquoted
+    /// literal words do; `test` has no way to tell.
+    pub operator: bool,
+}
+
+const UNARY: &[&str] = &[
+    "-a", "-b", "-c", "-d", "-e", "-f", "-g", "-h", "-k", "-L", "-n", "-N", "-o", "-O", "-G", "-p", "-r", "-s", "-S",
+    "-t", "-u", "-v", "-w", "-x", "-z",
+];
+
+const BINARY: &[&str] = &[
+    "=", "==", "!=", "<", ">", "=~", "-eq", "-ne", "-lt", "-le", "-gt", "-ge", "-nt", "-ot", "-ef",
+];
+
+impl Shell {
+    /// `test expr` and `[ expr ]`.
+    pub(crate) fn builtin_test(&mut self, argv: &[String]) -> i32 {
+        let name = argv[0].as_str();
+        let mut operands = &argv[1..];
+        if name == "[" {
+            match operands.split_last() {
+                Some((last, rest)) if last == "]" => operands = rest,
+                _ => {
+                    self.report_error("[: missing `]'");
+                    return 2;
+                }
+            }
+        }
+        let args: Vec<Arg> = operands
+            .iter()
+            .map(|text| Arg {
+                text: text.clone(),
+                pattern: None,
+                operator: true,
+            })
+            .collect();
+        match Eval::new(self, &args, false).run() {
+            Ok(result) => (!result) as i32,
+            Err(e) => {
+                self.report_error(format_args!("{name}: {e}"));
+                2
+            }
+        }
+    }
+
+    /// Runs `[[ words ]]`. Operands are expanded without field splitting, and
+    /// the right-hand side of `==`, `!=` and `=~` keeps track of quoting.
+    pub(crate) fn run_cond(&mut self, words: &[Word]) -> i32 {
+        let mut args = Vec::new();
+        for word in words {
+            let operator = matches!(word.parts.as_slice(), [WordPart::Lit { quoted: false, .. }]);
+            let chunks = match self.expand_variables(&word.parts) {
+                Ok(chunks) => chunks,
+                Err(e) => return self.expansion_failed(e),
+            };
+            let mut text = String::new();
+            let mut pattern = String::new();
+            for chunk in chunks {
+                match chunk {
+                    Chunk::Text { text: t, quoted, .. } => {
+                        text.push_str(&t);
+                        if quoted {
+                            for c in t.chars() {
+                                if "\\.[]()*+?{}|^$".contains(c) {
+                                    pattern.push('\\');
+                                }
+                                pattern.push(c);
+                            }
+                        } else {
+                            pattern.push_str(&t);
+                        }
+                    }
+                    Chunk::Break => {
+                        text.push(' ');
+                        pattern.push(' ');
+                    }
+                }
+            }
+            args.push(Arg {
+                text,
+                pattern: Some(pattern),
+                operator,
+            });
+        }
+        let mut traced = vec!["[[".to_string()];
+        traced.extend(args.iter().map(|arg| arg.text.clone()));
+        traced.push("]]".to_string());
+        self.trace(&[], &traced);
+        match Eval::new(self, &args, true).run() {
+            Ok(result) => (!result) as i32,
+            Err(e) => {
+                self.report_error(format_args!("[[: {e}"));
+                2
+            }
+        }
+    }
+}
+
+/// A recursive-descent evaluator shared by `test` and `[[`.
+struct Eval<'a> {
+    shell: &'a mut Shell,
+    args: &'a [Arg],
+    pos: usize,
+    /// `[[` rather than `test`: `&&`/`||` instead of `-a`/`-o`, patterns,
+    /// regexes and arithmetic operands.
+    extended: bool,
+}
+
+impl<'a> Eval<'a> {
+    fn new(shell: &'a mut Shell, args: &'a [Arg], extended: bool) -> Self {
+        Eval {
+            shell,
+            args,
+            pos: 0,
+            extended,
+        }
+    }
+
+    fn run(&mut self) -> Result<bool, String> {
+        let args = self.args;
+        if !self.extended {
+            // POSIX decides short expressions by argument count alone.
+            match args {
+                [] => return Ok(false),
+                [only] => return Ok(!only.text.is_empty()),
+                [bang, operand] if bang.text == "!" => return Ok(operand.text.is_empty()),
+                [op, operand] if UNARY.contains(&op.text.as_str()) => return self.unary(&op.text, &operand.text),
+                [_, _] => return Err(format!("{}: unary operator expected", args[0].text)),
+                _ => {}
+            }
+        }
+        let result = self.or()?;
+        match self.args.get(self.pos) {
+            None => Ok(result),
+            Some(arg) => Err(format!("syntax error near `{}'", arg.text)),
+        }
+    }
+
+    fn is_op(&self, offset: usize, ops: &[&str]) -> bool {
+        self.args
+            .get(self.pos + offset)
+            .is_some_and(|arg| arg.operator && ops.contains(&arg.text.as_str()))
+    }
+
+    fn is_binary(&self, offset: usize) -> bool {
+        self.is_op(offset, BINARY)
+            && (self.extended || self.args[self.pos + offset].text != "=~")
+            && self.pos + offset + 1 < self.args.len()
+    }
+
+    fn or(&mut self) -> Result<bool, String> {
+        let op = if self.extended { "||" } else { "-o" };
+        let mut result = self.and()?;
+        while self.is_op(0, &[op]) {
+            self.pos += 1;
+            let rhs = self.and()?;
+            result = result || rhs;
+        }
+        Ok(result)
+    }
+
+    fn and(&mut self) -> Result<bool, String> {
+        let op = if self.extended { "&&" } else { "-a" };
+        let mut result = self.not()?;
+        while self.is_op(0, &[op]) {
+            self.pos += 1;
+            let rhs = self.not()?;
+            result = result && rhs;
+        }
+        Ok(result)
+    }
+
+    fn not(&mut self) -> Result<bool, String> {
+        // `! = x` compares the string `!`, as POSIX asks.
+        if self.is_op(0, &["!"]) && !self.is_binary(1) {
+            self.pos += 1;
+            return Ok(!self.not()?);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<bool, String> {
+        let args = self.args;
+        let Some(arg) = args.get(self.pos) else {
+            return Err("argument expected".to_string());
+        };
+        if self.is_binary(1) {
+            let (lhs, op, rhs) = (&args[self.pos], &args[self.pos + 1], &args[self.pos + 2]);
+            self.pos += 3;
+            return self.binary(lhs, &op.text, rhs);
+        }
+        if self.is_op(0, &["("]) {
+            self.pos += 1;
+            let result = self.or()?;
+            if !self.is_op(0, &[")"]) {
+                return Err("`)' expected".to_string());
+            }
+            self.pos += 1;
+            return Ok(result);
+        }
+        let unary = UNARY.contains(&arg.text.as_str())
+            && arg.operator
+            && (self.extended || !matches!(arg.text.as_str(), "-a" | "-o"))
+            && self.pos + 1 < self.args.len();
+        if unary {
+            let operand = &args[self.pos + 1].text;
+            self.pos += 2;
+            return self.unary(&arg.text, operand);
+        }
+        self.pos += 1;
+        Ok(!arg.text.is_empty())
+    }
+
+    fn unary(&mut self, op: &str, operand: &str) -> Result<bool, String> {
+        let path = Path::new(operand);
+        let meta = || path.metadata().ok();
+        let mode = |bit: u32| meta().is_some_and(|m| m.permissions().mode() & bit != 0);
+        let file_type = |check: fn(&Metadata) -> bool| meta().is_some_and(|m| check(&m));
+        Ok(match op {
+            "-n" => !operand.is_empty(),
+            "-z" => operand.is_empty(),
+            "-a" | "-e" => meta().is_some(),
+            "-f" => file_type(|m| m.is_file()),
+            "-d" => file_type(|m| m.is_dir()),
+            "-b" => file_type(|m| m.file_type().is_block_device()),
+            "-c" => file_type(|m| m.file_type().is_char_device()),
+            "-p" => file_type(|m| m.file_type().is_fifo()),
+            "-S" => file_type(|m| m.file_type().is_socket()),
+            "-h" | "-L" => path.symlink_metadata().is_ok_and(|m| m.file_type().is_symlink()),
+            "-s" => meta().is_some_and(|m| m.len() > 0),
+            "-g" => mode(0o2000),
+            "-u" => mode(0o4000),
+            "-k" => mode(0o1000),
+            "-r" => access(path, AccessFlags::R_OK).is_ok(),
+            "-w" => access(path, AccessFlags::W_OK).is_ok(),
+            "-x" => access(path, AccessFlags::X_OK).is_ok(),
+            "-O" => meta().is_some_and(|m| m.uid() == nix::unistd::geteuid().as_raw()),
+            "-G" => meta().is_some_and(|m| m.gid() == nix::unistd::getegid().as_raw()),
+            "-N" => meta().is_some_and(|m| m.mtime() > m.atime()),
+            "-t" => operand.parse::<i32>().is_ok_and(|fd| nix::unistd::isatty(fd).unwrap_or(false)),
+            "-o" => self.shell.options.get(operand),
+            "-v" => self.is_set(operand),
+            _ => return Err(format!("{op}: unary operator expected")),
+        })
+    }
+
+    fn binary(&mut self, lhs: &Arg, op: &str, rhs: &Arg) -> Result<bool, String> {
+        let (a, b) = (lhs.text.as_str(), rhs.text.as_str());
+        Ok(match op {
+            "=" | "==" | "!=" => {
+                let matched = match &rhs.pattern {
+                    Some(pattern) => fnmatch(pattern, a),
+                    None => a == b,
+                };
+                matched == (op != "!=")
+            }
+            "<" => a < b,
+            ">" => a > b,
+            "=~" => self.regex(a, rhs.pattern.as_deref().unwrap_or(b))?,
+            "-nt" | "-ot" => {
+                let mtime = |p: &str| Path::new(p).metadata().and_then(|m| m.modified()).ok();
+                match (mtime(a), mtime(b), op) {
+                    (Some(x), Some(y), "-nt") => x > y,
+                    (Some(x), Some(y), _) => x < y,
+                    (Some(_), None, "-nt") | (None, Some(_), "-ot") => true,
+                    _ => false,
+                }
+            }
+            "-ef" => match (Path::new(a).metadata(), Path::new(b).metadata()) {
+                (Ok(x), Ok(y)) => x.dev() == y.dev() && x.ino() == y.ino(),
+                _ => false,
+            },
+            _ => {
+                let (x, y) = (self.integer(a)?, self.integer(b)?);
+                match op {
+                    "-eq" => x == y,
+                    "-ne" => x != y,
+                    "-lt" => x < y,
+                    "-le" => x <= y,
+                    "-gt" => x > y,
+                    _ => x >= y,
+                }
+            }
+        })
+    }
+
+    /// An operand of `-eq` and friends: an arithmetic expression inside
+    /// `[[`, a plain integer for `test`.
+    fn integer(&self, text: &str) -> Result<i64, String> {
+        if self.extended {
+            let vars = &self.shell.env_vars;
+            return arith::eval(text, &|name| vars.scalar(name).map(str::to_string));
+        }
+        text.trim()
+            .parse()
+            .map_err(|_| format!("{text}: integer expression expected"))
+    }
+
+    fn is_set(&self, name: &str) -> bool {
+        match name.split_once('[') {
+            Some((base, rest)) => {
+                let key = rest.trim_end_matches(']');
+                match self.shell.env_vars.get(base).map(|var| &var.value) {
+                    Some(Value::Assoc(map)) => map.contains_key(key),
+                    Some(Value::Indexed(map)) => key.parse().is_ok_and(|i: usize| map.contains_key(&i)),
+                    Some(Value::Scalar(_)) => key == "0",
+                    None => false,
+                }
+            }
+            None => self.shell.env_vars.get(name).is_some(),
+        }
+    }
+
+    /// Matches `text` against an extended regular expression and records the
+    /// match and its groups in `BASH_REMATCH`.
+    fn regex(&mut self, text: &str, pattern: &str) -> Result<bool, String> {
+        let groups = regex_match(pattern, text)?;
+        let matched = groups.is_some();
+        let map: BTreeMap<usize, String> = groups.unwrap_or_default().into_iter().enumerate().collect();
+        self.shell.env_vars.set_value("BASH_REMATCH", Value::Indexed(map));
+        Ok(matched)
+    }
+}
+
+fn fnmatch(pattern: &str, text: &str) -> bool {
+    let (Ok(pattern), Ok(text)) = (CString::new(pattern), CString::new(text)) else {
+        return false;
+    };
+    // SAFETY: both arguments are valid NUL-terminated strings.
+    unsafe { libc::fnmatch(pattern.as_ptr(), text.as_ptr(), 0) == 0 }
+}
+
+/// Runs a POSIX extended regex over `text`, returning the whole match and
+/// every group on success.
+fn regex_match(pattern: &str, text: &str) -> Result<Option<Vec<String>>, String> {
+    let c_pattern = CString::new(pattern).map_err(|_| "invalid regular expression".to_string())?;
+    let Ok(c_text) = CString::new(text) else {
+        return Ok(None);
+    };
+    // SAFETY: `regex` is initialised by regcomp before use and freed exactly
+    // once; `matches` has room for every group regcomp reported.
+    unsafe {
+        let mut regex: libc::regex_t = std::mem::zeroed();
+        if libc::regcomp(&mut regex, c_pattern.as_ptr(), libc::REG_EXTENDED) != 0 {
+            return Err(format!("{pattern}: invalid regular expression"));
+        }
+        // The group count is private to libc's regex_t, but there can be no
+        // more groups than opening parentheses.
+        let groups = pattern.matches('(').count();
+        let mut matches = vec![libc::regmatch_t { rm_so: -1, rm_eo: -1 }; groups + 1];
+        let result = libc::regexec(&regex, c_text.as_ptr(), matches.len(), matches.as_mut_ptr(), 0);
+        libc::regfree(&mut regex);
+        if result != 0 {
+            return Ok(None);
+        }
+        let bytes = text.as_bytes();
+        let used = matches.iter().rposition(|m| m.rm_so >= 0).map_or(0, |last| last + 1);
+        Ok(Some(
+            matches[..used]
+                .iter()
+                .map(|m| match (usize::try_from(m.rm_so), usize::try_from(m.rm_eo)) {
+                    (Ok(start), Ok(end)) => String::from_utf8_lossy(&bytes[start..end]).into_owned(),
+                    _ => String::new(),
+                })
+                .collect(),
+        ))
+    }
+}
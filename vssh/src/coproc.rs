@@ -0,0 +1,71 @@
+//! Coprocesses: `coproc NAME { ...; }` runs a command in the background with
+//! its standard input and output connected to the shell by pipes.
+//!
+//! The shell's ends go on free descriptors of its own, numbered from 10 up,
+//! which the commands and subshells it starts do not inherit. `${NAME[0]}`
+//! and `${NAME[1]}` hold the numbers to read the command's output from and
+//! to write its input to, as in `read line <&${NAME[0]}`.
+//! `$NAME_PID` is the process, which is also a job. The descriptors stay
+//! open after the command exits, so that its last output can still be read.
+//! To send end of file, close the input through a variable holding its
+//! number: `fd=${NAME[1]}; exec {fd}>&-`.
+
+use std::os::fd::AsRawFd;
+
+use crate::io;
+use crate::parser::Command;
+use crate::vars::Value;
+use crate::Shell;
+
+/// The lowest descriptor the shell hands out for coprocesses and `{var}`
+/// redirections, leaving 0-9 to scripts.
+pub(crate) const FIRST_SHELL_FD: i32 = 10;
+
+impl Shell {
+    /// Starts `body` as the coprocess `name` and returns 0, or the status of
+    /// the failure.
+    pub(crate) fn start_coproc(&mut self, name: &str, body: &Command) -> i32 {
+        if let Err(e) = self.check_restricted_variable(name) {
+            self.report_error(format_args!("coproc: {e}"));
+            return 1;
+        }
+        let pipes = io::pipe().and_then(|input| io::pipe().map(|output| (input, output)));
+        let ((coproc_stdin, shell_write), (shell_read, coproc_stdout)) = match pipes {
+            Ok(pipes) => pipes,
+            Err(e) => {
+                self.report_error(format_args!("Failed to create pipe: {}", e));
+                return 1;
+            }
+        };
+
+        let mut fds = self.fds.clone();
+        fds.set(0, coproc_stdin);
+        fds.set(1, coproc_stdout);
+        let saved = std::mem::replace(&mut self.fds, fds);
+        let shell_ends = [shell_read.as_raw_fd(), shell_write.as_raw_fd()];
+        let started = self.fork_subshell(None, |shell| {
+            // The command must not hold the shell's ends, or it would never
+            // see the end of its input. Earlier coprocesses' ends are closed
+            // by fork_subshell.
+            for fd in shell_ends {
+                let _ = nix::unistd::close(fd);
+            }
+            shell.run_command(body)
+        });
+        // Dropping the command's table closes its ends in the shell.
+        self.fds = saved;
+        let Ok(pid) = started else {
+            return 1;
+        };
+
+        let read_fd = self.fds.lowest_free(FIRST_SHELL_FD);
+        self.fds.set_private(read_fd, shell_read);
+        let write_fd = self.fds.lowest_free(FIRST_SHELL_FD);
+        self.fds.set_private(write_fd, shell_write);
+        let numbers = [(0, read_fd.to_string()), (1, write_fd.to_string())];
+        self.env_vars.set_value(name, Value::Indexed(numbers.into_iter().collect()));
+        self.env_vars.set_scalar(&format!("{name}_PID"), pid.to_string());
+        self.note_background(vec![pid], &format!("coproc {name}"));
+        0
+    }
+}
@@ -0,0 +1,256 @@
+//! Per-directory environments: variables a `.vsshenv` file exports while the
+//! working directory is inside the directory that holds it.
+//!
+//! Whenever an interactive shell changes directory, it unloads the files of
+//! directories it has left and loads those of the directories it is now in,
+//! outermost first, reporting which variables were added (`+`), changed
+//! (`~`) or removed (`-`). A file runs in a subshell, so that only its
+//! exports reach the shell, and only once `vsshenv allow` has recorded its
+//! hash in `~/.local/share/vssh/allowed`. Editing the file revokes that until
+//! it is allowed again, and `vsshenv deny` unloads it at once. Leaving the
+//! directory puts every variable the file touched back the way it was.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::io;
+use crate::vars::Variable;
+use crate::Shell;
+
+const FILE_NAME: &str = ".vsshenv";
+
+/// A `.vsshenv` file whose exports are in effect.
+pub(crate) struct LoadedEnv {
+    dir: PathBuf,
+    /// The variables the file changed, as they were before it ran.
+    saved: Vec<(String, Option<Variable>)>,
+}
+
+impl Shell {
+    /// Unloads the environments of directories the shell has left and loads
+    /// those of the directories it has entered.
+    pub(crate) fn sync_dir_envs(&mut self) {
+        if !self.interactive || self.restricted() {
+            return;
+        }
+        while let Some(loaded) = self.dir_envs.last() {
+            if self.current_dir.starts_with(&loaded.dir) {
+                break;
+            }
+            let loaded = self.dir_envs.pop().expect("checked above");
+            self.unload_dir_env(loaded);
+        }
+        let mut dirs: Vec<PathBuf> = self.current_dir.ancestors().map(Path::to_path_buf).collect();
+        dirs.reverse();
+        if let Some(deepest) = self.dir_envs.last() {
+            dirs.retain(|dir| dir.starts_with(&deepest.dir) && *dir != deepest.dir);
+        }
+        for dir in dirs {
+            if dir.join(FILE_NAME).is_file() {
+                self.load_dir_env(dir);
+            }
+        }
+    }
+
+    fn load_dir_env(&mut self, dir: PathBuf) {
+        let file = dir.join(FILE_NAME);
+        let contents = match std::fs::read(&file) {
+            Ok(contents) => contents,
+            Err(e) => {
+                self.report_error(format_args!("vssh: {}: {e}", file.display()));
+                return;
+            }
+        };
+        if !self.is_allowed(&file, &contents) {
+            self.report_error(format_args!(
+                "vssh: {} is not allowed; run `vsshenv allow` to load it",
+                file.display()
+            ));
+            return;
+        }
+        let Some(exports) = self.exports_of(&file, &contents) else {
+            return;
+        };
+
+        let before = exported_map(self);
+        let mut saved = Vec::new();
+        for (name, _) in before.iter().filter(|(name, _)| !exports.contains_key(*name)) {
+            saved.push((name.clone(), self.env_vars.get(name).cloned()));
+            self.env_vars.unset(name);
+        }
+        for (name, value) in &exports {
+            if before.get(name) != Some(value) {
+                saved.push((name.clone(), self.env_vars.get(name).cloned()));
+                self.env_vars.set_scalar(name, value.clone());
+                self.env_vars.set_exported(name, true);
+            }
+        }
+        self.report_error(format_args!("vssh: loading {}", file.display()));
+        self.report_env_diff(&before);
+        self.dir_envs.push(LoadedEnv { dir, saved });
+    }
+
+    fn unload_dir_env(&mut self, loaded: LoadedEnv) {
+        let before = exported_map(self);
+        for (name, var) in loaded.saved.into_iter().rev() {
+            self.env_vars.restore(&name, var);
+        }
+        self.report_error(format_args!("vssh: unloading {}", loaded.dir.join(FILE_NAME).display()));
+        self.report_env_diff(&before);
+    }
+
+    /// Runs `contents`, as read from `file`, in a subshell and returns the
+    /// variables exported at its end, or `None` if it failed. The contents
+    /// are the ones whose hash was checked, rather than whatever the file
+    /// holds by the time the subshell would read it.
+    fn exports_of(&mut self, file: &Path, contents: &[u8]) -> Option<BTreeMap<String, String>> {
+        let (read_end, write_end) = match io::pipe() {
+            Ok(pipe) => pipe,
+            Err(e) => {
+                self.report_error(format_args!("Failed to create pipe: {}", e));
+                return None;
+            }
+        };
+        let script = String::from_utf8_lossy(contents).into_owned();
+        let child = self.fork_subshell(None, move |shell| {
+            let status = shell.execute_command(&script);
+            let mut out = File::from(write_end);
+            for (name, value) in shell.env_vars.exported() {
+                let _ = write!(out, "{name}={value}\0");
+            }
+            status
+        });
+        let Ok(pid) = child else {
+            return None;
+        };
+        let mut output = Vec::new();
+        let _ = File::from(read_end).read_to_end(&mut output);
+        let status = self.wait_for(pid);
+        if status != 0 {
+            self.report_error(format_args!("vssh: {}: exited with status {status}; not loaded", file.display()));
+            return None;
+        }
+        let output = String::from_utf8_lossy(&output);
+        let exports = output
+            .split_terminator('\0')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        Some(exports)
+    }
+
+    /// Reports how the exported variables differ from `before`.
+    fn report_env_diff(&self, before: &BTreeMap<String, String>) {
+        let after = exported_map(self);
+        let mut changes = Vec::new();
+        for (name, value) in &after {
+            match before.get(name) {
+                None => changes.push(format!("+{name}")),
+                Some(old) if old != value => changes.push(format!("~{name}")),
+                Some(_) => {}
+            }
+        }
+        changes.extend(before.keys().filter(|name| !after.contains_key(*name)).map(|name| format!("-{name}")));
+        if !changes.is_empty() {
+            self.report_error(format_args!("vssh: export {}", changes.join(" ")));
+        }
+    }
+
+    /// The list of allowed files, `~/.local/share/vssh/allowed`.
+    fn allowed_list(&self) -> Option<PathBuf> {
+        self.home_dir().map(|home| home.join(".local/share/vssh/allowed"))
+    }
+
+    /// Whether `file` has been allowed with exactly `contents`.
+    fn is_allowed(&self, file: &Path, contents: &[u8]) -> bool {
+        let Some(list) = self.allowed_list() else {
+            return false;
+        };
+        let entry = allowed_entry(file, contents);
+        std::fs::read_to_string(list).is_ok_and(|text| text.lines().any(|line| line == entry))
+    }
+
+    /// `vsshenv allow|deny [dir]`
+    pub(crate) fn builtin_vsshenv(&mut self, argv: &[String]) -> i32 {
+        let allow = match argv.get(1).map(String::as_str) {
+            Some("allow") => true,
+            Some("deny") => false,
+            _ => {
+                self.report_error("vsshenv: usage: vsshenv allow|deny [dir]");
+                return 2;
+            }
+        };
+        let dir = argv.get(2).map_or_else(|| self.current_dir.clone(), |dir| self.current_dir.join(dir));
+        let file = dir.join(FILE_NAME);
+        let Some(list) = self.allowed_list() else {
+            self.report_error("vsshenv: no home directory");
+            return 1;
+        };
+        match self.update_allowed(&list, &file, allow) {
+            Ok(()) => {
+                if allow {
+                    self.sync_dir_envs();
+                } else {
+                    self.unload_denied(&dir);
+                }
+                0
+            }
+            Err(e) => {
+                self.report_error(format_args!("vsshenv: {e}"));
+                1
+            }
+        }
+    }
+
+    /// Unloads the environment of `dir` if it is loaded, along with those
+    /// loaded after it from below it.
+    fn unload_denied(&mut self, dir: &Path) {
+        let Ok(dir) = std::fs::canonicalize(dir) else {
+            return;
+        };
+        let Some(index) = self
+            .dir_envs
+            .iter()
+            .position(|loaded| std::fs::canonicalize(&loaded.dir).is_ok_and(|loaded| loaded == dir))
+        else {
+            return;
+        };
+        for loaded in self.dir_envs.split_off(index).into_iter().rev() {
+            self.unload_dir_env(loaded);
+        }
+    }
+
+    /// Replaces the entry for `file` in `list` with one for its current
+    /// contents, or just removes it.
+    fn update_allowed(&self, list: &Path, file: &Path, allow: bool) -> Result<(), String> {
+        let file = std::fs::canonicalize(file).map_err(|e| format!("{}: {e}", file.display()))?;
+        let suffix = format!(" {}", file.display());
+        let text = std::fs::read_to_string(list).unwrap_or_default();
+        let mut lines: Vec<String> = text.lines().filter(|line| !line.ends_with(&suffix)).map(str::to_string).collect();
+        if allow {
+            let contents = std::fs::read(&file).map_err(|e| format!("{}: {e}", file.display()))?;
+            lines.push(allowed_entry(&file, &contents));
+        }
+        if let Some(parent) = list.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("{}: {e}", parent.display()))?;
+        }
+        let text: String = lines.iter().map(|line| format!("{line}\n")).collect();
+        std::fs::write(list, text).map_err(|e| format!("{}: {e}", list.display()))
+    }
+}
+
+/// The line of the allowed list that allows `file` with `contents`: the
+/// SHA-256 of the contents, then the file's canonical path.
+fn allowed_entry(file: &Path, contents: &[u8]) -> String {
+    let file = std::fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+    let digest: String = Sha256::digest(contents).iter().map(|byte| format!("{byte:02x}")).collect();
+    format!("{digest} {}", file.display())
+}
+
+fn exported_map(shell: &Shell) -> BTreeMap<String, String> {
+    shell.env_vars.exported().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+}
@@ -0,0 +1,292 @@
+//! The working directory: logical paths, `$PWD`/`$OLDPWD`, CDPATH and the
+//! `pushd`/`popd`/`dirs` stack.
+
+use std::env;
+use std::io::Write;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+
+use crate::Shell;
+
+/// The directory to start in: `$PWD` when it names the process's actual
+/// working directory, so that a path through a symlink survives, and the
+/// physical path otherwise.
+pub fn initial_dir() -> PathBuf {
+    let physical = env::current_dir().unwrap_or_else(|_| PathBuf::from("/"));
+    if let Some(pwd) = env::var_os("PWD").map(PathBuf::from)
+        && pwd.is_absolute()
+        && same_file(&pwd, &physical)
+    {
+        return pwd;
+    }
+    physical
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.metadata(), b.metadata()) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// Resolves `.` and `..` in `path` textually, the way `cd -L` does.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+impl Shell {
+    /// `cd [-L|-P] [dir | - | +N | -N]`
+    pub(crate) fn builtin_cd(&mut self, argv: &[String]) -> i32 {
+        let mut physical = self.options.get("physical");
+        let mut args = &argv[1..];
+        while let Some(first) = args.first() {
+            match first.as_str() {
+                "-L" => physical = false,
+                "-P" => physical = true,
+                "--" => {
+                    args = &args[1..];
+                    break;
+                }
+                _ => break,
+            }
+            args = &args[1..];
+        }
+
+        match args.first().map(String::as_str) {
+            Some(arg) if is_stack_index(arg) => {
+                let index = match self.stack_index("cd", arg) {
+                    Ok(index) => index,
+                    Err(status) => return status,
+                };
+                if index == 0 {
+                    return 0;
+                }
+                let target = self.dir_stack[index - 1].clone();
+                match self.enter_directory(&target, physical) {
+                    Ok(()) => {
+                        self.dir_stack.remove(index - 1);
+                        0
+                    }
+                    Err(e) => {
+                        self.report_error(e);
+                        1
+                    }
+                }
+            }
+            dir => self.change_directory(dir, physical),
+        }
+    }
+
+    /// Finds `dir` along `$CDPATH`. The second value says whether the search
+    /// picked a non-empty CDPATH entry, in which case `cd` shows where it went.
+    pub(crate) fn search_cdpath(&self, dir: &str) -> (PathBuf, bool) {
+        let path = Path::new(dir);
+        let relative_to_cwd = path.is_absolute() || matches!(path.components().next(), Some(Component::CurDir | Component::ParentDir));
+        if let Some(cdpath) = self.env_vars.scalar("CDPATH")
+            && !relative_to_cwd
+        {
+            for entry in cdpath.split(':') {
+                let base = if entry.is_empty() { Path::new(".") } else { Path::new(entry) };
+                let candidate = self.current_dir.join(base).join(path);
+                if candidate.is_dir() {
+                    return (candidate, !entry.is_empty());
+                }
+            }
+        }
+        (self.current_dir.join(path), false)
+    }
+
+    /// Makes `path` the working directory and updates `$PWD` and `$OLDPWD`.
+    /// A logical change keeps symlinks in the path; a physical one resolves them.
+    pub(crate) fn enter_directory(&mut self, path: &Path, physical: bool) -> Result<(), String> {
+        let path = self.current_dir.join(path);
+        let logical = normalize(&path);
+        let target = if physical || !logical.is_dir() {
+            // `..` after a symlink can point somewhere the textual path does
+            // not, so fall back to the physical path before giving up.
+            match std::fs::canonicalize(&path) {
+                Ok(canonical) => canonical,
+                Err(_) => return Err(format!("Invalid path: {}", path.display())),
+            }
+        } else {
+            logical
+        };
+        if !target.is_dir() {
+            return Err(format!("Not a directory: {}", path.display()));
+        }
+        env::set_current_dir(&target).map_err(|e| format!("Failed to change directory: {}", e))?;
+
+        let previous = std::mem::replace(&mut self.current_dir, target);
+        self.env_vars.set_scalar("OLDPWD", previous.display().to_string());
+        self.env_vars.set_exported("OLDPWD", true);
+        self.update_pwd();
+        self.sync_dir_envs();
+        Ok(())
+    }
+
+    /// Publishes the working directory as an exported `$PWD`.
+    pub(crate) fn update_pwd(&mut self) {
+        self.env_vars.set_scalar("PWD", self.current_dir.display().to_string());
+        self.env_vars.set_exported("PWD", true);
+    }
+
+    /// `pushd [dir | +N | -N]`
+    pub(crate) fn builtin_pushd(&mut self, argv: &[String]) -> i32 {
+        let physical = self.options.get("physical");
+        match argv.get(1).map(String::as_str) {
+            None => {
+                let Some(target) = self.dir_stack.first().cloned() else {
+                    self.report_error("pushd: no other directory");
+                    return 1;
+                };
+                let previous = self.current_dir.clone();
+                if let Err(e) = self.enter_directory(&target, physical) {
+                    self.report_error(e);
+                    return 1;
+                }
+                self.dir_stack[0] = previous;
+            }
+            Some(arg) if is_stack_index(arg) => {
+                let index = match self.stack_index("pushd", arg) {
+                    Ok(index) => index,
+                    Err(status) => return status,
+                };
+                let mut entries = self.stack_entries();
+                entries.rotate_left(index);
+                if let Err(e) = self.enter_directory(&entries[0], physical) {
+                    self.report_error(e);
+                    return 1;
+                }
+                self.dir_stack = entries.split_off(1);
+            }
+            Some(dir) => {
+                let (target, _) = self.search_cdpath(dir);
+                let previous = self.current_dir.clone();
+                if let Err(e) = self.enter_directory(&target, physical) {
+                    self.report_error(e);
+                    return 1;
+                }
+                self.dir_stack.insert(0, previous);
+            }
+        }
+        self.print_dir_stack(false, false, false);
+        0
+    }
+
+    /// `popd [+N | -N]`
+    pub(crate) fn builtin_popd(&mut self, argv: &[String]) -> i32 {
+        if self.dir_stack.is_empty() {
+            self.report_error("popd: directory stack empty");
+            return 1;
+        }
+        let index = match argv.get(1) {
+            Some(arg) if is_stack_index(arg) => match self.stack_index("popd", arg) {
+                Ok(index) => index,
+                Err(status) => return status,
+            },
+            Some(arg) => {
+                self.report_error(format_args!("popd: {arg}: invalid argument"));
+                return 2;
+            }
+            None => 0,
+        };
+        if index == 0 {
+            let target = self.dir_stack[0].clone();
+            if let Err(e) = self.enter_directory(&target, self.options.get("physical")) {
+                self.report_error(e);
+                return 1;
+            }
+            self.dir_stack.remove(0);
+        } else {
+            self.dir_stack.remove(index - 1);
+        }
+        self.print_dir_stack(false, false, false);
+        0
+    }
+
+    /// `dirs [-clpv] [+N | -N]`
+    pub(crate) fn builtin_dirs(&mut self, argv: &[String]) -> i32 {
+        let (mut long, mut per_line, mut numbered) = (false, false, false);
+        for arg in &argv[1..] {
+            if is_stack_index(arg) {
+                let index = match self.stack_index("dirs", arg) {
+                    Ok(index) => index,
+                    Err(status) => return status,
+                };
+                let entry = &self.stack_entries()[index];
+                let entry = if long { entry.display().to_string() } else { self.abbreviate_home(entry) };
+                let _ = writeln!(self.fds.writer(1), "{entry}");
+                return 0;
+            }
+            match arg.as_str() {
+                "-c" => {
+                    self.dir_stack.clear();
+                    return 0;
+                }
+                "-l" => long = true,
+                "-p" => per_line = true,
+                "-v" => numbered = true,
+                _ => {
+                    self.report_error(format_args!("dirs: {arg}: invalid option"));
+                    return 2;
+                }
+            }
+        }
+        self.print_dir_stack(long, per_line, numbered);
+        0
+    }
+
+    /// The whole stack as `dirs` numbers it, the working directory first.
+    fn stack_entries(&self) -> Vec<PathBuf> {
+        std::iter::once(self.current_dir.clone()).chain(self.dir_stack.iter().cloned()).collect()
+    }
+
+    /// Turns `+N` (from the left) or `-N` (from the right) into a position in
+    /// `stack_entries`, reporting out-of-range indexes for `builtin`.
+    fn stack_index(&self, builtin: &str, arg: &str) -> Result<usize, i32> {
+        let len = self.dir_stack.len() + 1;
+        let n: usize = arg[1..].parse().unwrap_or(usize::MAX);
+        let index = match arg.as_bytes()[0] {
+            b'+' => Some(n),
+            _ => len.checked_sub(1).and_then(|last| last.checked_sub(n)),
+        };
+        match index.filter(|&i| i < len) {
+            Some(index) => Ok(index),
+            None => {
+                self.report_error(format_args!("{builtin}: {arg}: directory stack index out of range"));
+                Err(1)
+            }
+        }
+    }
+
+    fn print_dir_stack(&self, long: bool, per_line: bool, numbered: bool) {
+        let entries: Vec<String> = self
+            .stack_entries()
+            .iter()
+            .map(|entry| if long { entry.display().to_string() } else { self.abbreviate_home(entry) })
+            .collect();
+        let mut out = self.fds.writer(1);
+        let _ = if numbered {
+            entries.iter().enumerate().try_for_each(|(i, entry)| writeln!(out, "{i:2}  {entry}"))
+        } else if per_line {
+            entries.iter().try_for_each(|entry| writeln!(out, "{entry}"))
+        } else {
+            writeln!(out, "{}", entries.join(" "))
+        };
+    }
+}
+
+/// Whether `arg` has the `+N`/`-N` form that selects a directory stack entry.
+fn is_stack_index(arg: &str) -> bool {
+    arg.len() > 1 && (arg.starts_with('+') || arg.starts_with('-')) && arg[1..].bytes().all(|b| b.is_ascii_digit())
+}
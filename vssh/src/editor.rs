@@ -0,0 +1,219 @@
+//! Line editing for interactive shells, with live syntax highlighting and
+//! suggestions from history.
+//!
+//! Highlighting comes from [`parser::spans`]: command names are green when
+//! they name a function, builtin or executable and red otherwise, and quoted
+//! strings, expansions, redirections, operators and comments get colours of
+//! their own. As you type, the most recent history entry that starts with
+//! the line is shown greyed out after the cursor; the right arrow accepts it.
+//!
+//! History is kept in `$HISTFILE`, or `~/.vssh_history` by default, and holds
+//! `$HISTSIZE` entries (1000 by default).
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::{Hinter, HistoryHinter};
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{Config, Context, Editor};
+
+use crate::builtins::is_builtin;
+use crate::exec::{self, DEFAULT_PATH};
+use crate::parser::{self, SpanKind, WordPart, RESERVED_WORDS};
+use crate::Shell;
+
+const DEFAULT_HISTSIZE: usize = 1000;
+
+/// What reading a line gave.
+pub enum Line {
+    Text(String),
+    /// Ctrl-C was pressed.
+    Interrupted,
+    /// Reading failed; the line so far is dropped.
+    Failed,
+    Eof,
+}
+
+pub struct LineEditor {
+    editor: Editor<ShellHelper, FileHistory>,
+    history_file: Option<PathBuf>,
+}
+
+impl LineEditor {
+    /// Sets up an editor for `shell` and loads its history.
+    pub fn new(shell: &Shell) -> Result<Self, String> {
+        let size = shell
+            .env_vars
+            .scalar("HISTSIZE")
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(DEFAULT_HISTSIZE);
+        let config = Config::builder()
+            .max_history_size(size)
+            .and_then(|config| config.history_ignore_dups(true))
+            .map_err(|e| e.to_string())?
+            .history_ignore_space(true)
+            .build();
+        let mut editor = Editor::with_config(config).map_err(|e| e.to_string())?;
+        editor.set_helper(Some(ShellHelper::default()));
+
+        let history_file = match shell.env_vars.scalar("HISTFILE") {
+            Some("") => None,
+            Some(path) => Some(PathBuf::from(path)),
+            None => shell.home_dir().map(|home| home.join(".vssh_history")),
+        };
+        if let Some(path) = &history_file
+            && path.exists()
+        {
+            // A history file we cannot read should not keep the shell from starting.
+            let _ = editor.load_history(path);
+        }
+        Ok(LineEditor { editor, history_file })
+    }
+
+    /// Reads a line, refreshing what the highlighter knows about commands
+    /// from `shell` first.
+    pub fn read_line(&mut self, shell: &Shell, prompt: &str) -> Line {
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.refresh(shell);
+        }
+        match self.editor.readline(prompt) {
+            Ok(line) => Line::Text(line),
+            Err(ReadlineError::Interrupted) => Line::Interrupted,
+            Err(_) => Line::Eof,
+        }
+    }
+
+    /// Adds a command line to the history and appends it to the history file.
+    pub fn remember(&mut self, input: &str) {
+        if !self.editor.add_history_entry(input).unwrap_or(false) {
+            return;
+        }
+        if let Some(path) = &self.history_file {
+            let _ = self.editor.append_history(path);
+        }
+    }
+}
+
+/// Highlights and hints for the editor. It cannot borrow the shell while a
+/// line is being read, so it keeps a copy of what command lookup needs.
+#[derive(Default)]
+struct ShellHelper {
+    hinter: HistoryHinter,
+    path: String,
+    current_dir: PathBuf,
+    autocd: bool,
+    functions: BTreeSet<String>,
+    /// Whether each command name seen while reading this line exists.
+    known: RefCell<HashMap<String, bool>>,
+}
+
+impl ShellHelper {
+    fn refresh(&mut self, shell: &Shell) {
+        self.path = shell.env_vars.scalar("PATH").unwrap_or(DEFAULT_PATH).to_string();
+        self.current_dir = shell.current_dir.clone();
+        self.autocd = shell.options.get("autocd");
+        self.functions = shell.functions.keys().cloned().collect();
+        self.known.get_mut().clear();
+    }
+
+    /// Whether running `word` as a command would find something. Words with
+    /// expansions in them cannot be judged before they run.
+    fn command_exists(&self, word: &str) -> Option<bool> {
+        let parsed = parser::parse_word(word).ok()?;
+        let mut name = String::new();
+        for part in &parsed.parts {
+            match part {
+                WordPart::Lit { text, .. } => name.push_str(text),
+                WordPart::Param { .. } => return None,
+            }
+        }
+        if let Some(&known) = self.known.borrow().get(&name) {
+            return Some(known);
+        }
+        let exists = is_builtin(&name)
+            || self.functions.contains(&name)
+            || RESERVED_WORDS.contains(&name.as_str())
+            || !exec::search_path(&self.path, &self.current_dir, &name).is_empty()
+            || (self.autocd && self.current_dir.join(&name).is_dir());
+        self.known.borrow_mut().insert(name, exists);
+        Some(exists)
+    }
+
+    fn colour(&self, kind: SpanKind, text: &str) -> Option<&'static str> {
+        Some(match kind {
+            SpanKind::Command => match self.command_exists(text)? {
+                true => "32",
+                false => "31",
+            },
+            SpanKind::Keyword => "34",
+            SpanKind::Assignment | SpanKind::Variable => "35",
+            SpanKind::Quoted => "33",
+            SpanKind::Redirect => "36",
+            SpanKind::Operator => "1",
+            SpanKind::Comment => "90",
+        })
+    }
+}
+
+impl Highlighter for ShellHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        let spans = parser::spans(line);
+        if spans.is_empty() {
+            return Cow::Borrowed(line);
+        }
+        // Paint each byte, letting later (inner) spans win, then emit an
+        // escape wherever the colour changes.
+        let mut colours: Vec<Option<&str>> = vec![None; line.len()];
+        for span in &spans {
+            let colour = self.colour(span.kind, &line[span.range.clone()]);
+            colours[span.range.clone()].fill(colour);
+        }
+        let mut out = String::with_capacity(line.len() * 2);
+        let mut current = None;
+        for (i, c) in line.char_indices() {
+            if colours[i] != current {
+                out.push_str("\x1b[0m");
+                if let Some(colour) = colours[i] {
+                    out.push_str(&format!("\x1b[{colour}m"));
+                }
+                current = colours[i];
+            }
+            out.push(c);
+        }
+        if current.is_some() {
+            out.push_str("\x1b[0m");
+        }
+        Cow::Owned(out)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(format!("\x1b[90m{hint}\x1b[0m"))
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _forced: bool) -> bool {
+        // Any edit can change how the whole line parses.
+        true
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, ctx: &Context<'_>) -> Option<String> {
+        self.hinter.hint(line, pos, ctx)
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = String;
+}
+
+impl Validator for ShellHelper {}
+
+impl rustyline::Helper for ShellHelper {}
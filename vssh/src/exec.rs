@@ -0,0 +1,1085 @@
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::os::unix::process::CommandExt;
+use std::process::Command as Process;
+
+use nix::errno::Errno;
+use nix::libc;
+use nix::sys::wait::WaitStatus;
+use nix::unistd::{fork, ForkResult, Pid};
+
+use crate::audit::{describe_redirect, Outcome};
+use crate::builtins::is_builtin;
+use crate::coproc::FIRST_SHELL_FD;
+use crate::hash::NotRunnable;
+use crate::io::{self, Fds};
+use crate::jobs::Jobs;
+use crate::parser::{
+    AndOr, Assignment, AssignValue, Command, CompoundCommand, Connector, List, Pipeline, RedirOp, Redirect,
+    SimpleCommand, DECLARATION_BUILTINS,
+};
+use crate::signals::{self, Disposition, Trap};
+use crate::vars::{Value, Variable};
+use crate::Shell;
+
+impl Shell {
+    pub(crate) fn run_list(&mut self, list: &List) {
+        for item in &list.items {
+            if self.stopped() {
+                break;
+            }
+            self.last_status = if item.background {
+                self.run_background(&item.and_or)
+            } else {
+                self.run_and_or(&item.and_or)
+            };
+            self.handle_signals();
+        }
+    }
+
+    /// Whether the rest of the current input should be skipped, because the
+    /// shell is exiting, Ctrl-C interrupted it or a function returned.
+    fn stopped(&self) -> bool {
+        !self.running || self.interrupted || self.returning
+    }
+
+    /// Runs the traps for signals that arrived since the last check.
+    pub(crate) fn handle_signals(&mut self) {
+        if self.in_trap {
+            return;
+        }
+        for signal in signals::take_pending() {
+            let trap = Trap::Signal(signal);
+            if self.traps.contains_key(&trap) {
+                self.run_trap(trap);
+            } else if signal == nix::sys::signal::Signal::SIGINT {
+                self.interrupted = true;
+            }
+        }
+    }
+
+    /// Runs the action set for `trap`, if any. The action sees the status of
+    /// the command that triggered it and leaves `$?` untouched unless it exits.
+    pub(crate) fn run_trap(&mut self, trap: Trap) {
+        if self.in_trap {
+            return;
+        }
+        let Some(action) = self.traps.get(&trap).filter(|action| !action.is_empty()).cloned() else {
+            return;
+        };
+        let status = self.last_status;
+        self.in_trap = true;
+        self.execute_command(&action);
+        self.in_trap = false;
+        if self.running {
+            self.last_status = status;
+        }
+    }
+
+    /// Runs the EXIT trap once the shell is done, whether through `exit` or
+    /// by reaching the end of its input, and then removes its named pipes.
+    pub fn run_exit_trap(&mut self) {
+        self.handle_signals();
+        if let Some(action) = self.traps.remove(&Trap::Exit) {
+            let status = self.last_status;
+            self.running = true;
+            self.interrupted = false;
+            self.in_trap = true;
+            self.execute_command(&action);
+            self.in_trap = false;
+            if self.running {
+                self.last_status = status;
+            }
+            self.running = false;
+        }
+        self.remove_fifo_dir();
+    }
+
+    fn run_background(&mut self, and_or: &AndOr) -> i32 {
+        // Without job control, an asynchronous list reads from /dev/null
+        // unless it redirects its input itself.
+        let saved = self.fds.clone();
+        if let Ok(null) = std::fs::File::open("/dev/null") {
+            self.fds.set(0, null.into());
+        }
+        let stages = if and_or.rest.is_empty() && and_or.first.time.is_none() {
+            self.spawn_pipeline(&and_or.first.commands)
+        } else {
+            vec![self.fork_subshell(None, |shell| shell.run_and_or(and_or))]
+        };
+        self.fds = saved;
+        let pids: Vec<Pid> = stages.iter().filter_map(|stage| stage.ok()).collect();
+        if pids.is_empty() {
+            return stages.into_iter().find_map(Result::err).unwrap_or(1);
+        }
+        self.note_background(pids, &and_or.text);
+        0
+    }
+
+    /// Runs `a && b || c`. Every pipeline but the last one run is exempt from
+    /// errexit, since its status is being tested.
+    fn run_and_or(&mut self, and_or: &AndOr) -> i32 {
+        let mut status = self.run_pipeline_checked(&and_or.first, !and_or.rest.is_empty());
+        for (i, (connector, pipeline)) in and_or.rest.iter().enumerate() {
+            if self.stopped() {
+                break;
+            }
+            let run = match connector {
+                Connector::And => status == 0,
+                Connector::Or => status != 0,
+            };
+            if run {
+                status = self.run_pipeline_checked(pipeline, i + 1 < and_or.rest.len());
+            }
+        }
+        status
+    }
+
+    fn run_pipeline_checked(&mut self, pipeline: &Pipeline, tested: bool) -> i32 {
+        let exempt = tested || pipeline.negated;
+        if exempt {
+            self.errexit_exempt += 1;
+        }
+        let status = self.run_pipeline(pipeline);
+        if exempt {
+            self.errexit_exempt -= 1;
+        }
+        self.last_status = status;
+        if !exempt {
+            self.check_errexit(status);
+        }
+        status
+    }
+
+    /// Runs the ERR trap after a failed command, then stops the shell if
+    /// `set -e` is in effect.
+    fn check_errexit(&mut self, status: i32) {
+        if status == 0 || self.errexit_exempt > 0 || self.returning {
+            return;
+        }
+        self.run_trap(Trap::Err);
+        if self.options.get("errexit") {
+            self.running = false;
+        }
+    }
+
+    fn run_pipeline(&mut self, pipeline: &Pipeline) -> i32 {
+        match pipeline.time {
+            Some(format) => self.run_timed(pipeline, format),
+            None => self.run_untimed(pipeline),
+        }
+    }
+
+    pub(crate) fn run_untimed(&mut self, pipeline: &Pipeline) -> i32 {
+        let status = match pipeline.commands.as_slice() {
+            [command] => {
+                let status = self.run_command(command);
+                self.set_pipestatus(&[status]);
+                status
+            }
+            commands => self.process_piped_commands(commands),
+        };
+        if pipeline.negated {
+            (status == 0) as i32
+        } else {
+            status
+        }
+    }
+
+    pub(crate) fn run_command(&mut self, command: &Command) -> i32 {
+        match command {
+            Command::Simple(simple) => self.process_command(simple),
+            Command::Compound(compound, redirects) => {
+                self.run_with_redirections(redirects, |shell| shell.run_compound(compound))
+            }
+            Command::Function { name, body } => {
+                self.functions.insert(name.clone(), body.clone());
+                0
+            }
+            Command::Coproc { name, body } => self.start_coproc(name, body),
+        }
+    }
+
+    /// Runs a function body with `argv[1..]` as the positional parameters.
+    pub(crate) fn call_function(&mut self, body: &Command, argv: &[String]) -> i32 {
+        let saved = std::mem::replace(&mut self.positional, argv[1..].to_vec());
+        self.function_depth += 1;
+        let status = self.run_command(body);
+        self.function_depth -= 1;
+        self.positional = saved;
+        if std::mem::take(&mut self.returning) {
+            self.last_status
+        } else {
+            status
+        }
+    }
+
+    fn run_compound(&mut self, compound: &CompoundCommand) -> i32 {
+        match compound {
+            CompoundCommand::Group(body) => {
+                self.run_list(body);
+                self.last_status
+            }
+            CompoundCommand::If { branches, otherwise } => {
+                for (condition, body) in branches {
+                    if self.run_condition(condition) {
+                        self.run_list(body);
+                        return self.last_status;
+                    }
+                    if self.stopped() {
+                        return self.last_status;
+                    }
+                }
+                match otherwise {
+                    Some(body) => {
+                        self.run_list(body);
+                        self.last_status
+                    }
+                    None => 0,
+                }
+            }
+            CompoundCommand::Cond(words) => self.run_cond(words),
+            CompoundCommand::While { condition, body, until } => {
+                let mut status = 0;
+                while !self.stopped() && self.run_condition(condition) != *until && !self.stopped() {
+                    self.run_list(body);
+                    status = self.last_status;
+                }
+                status
+            }
+        }
+    }
+
+    /// Runs the condition of an `if` or loop, which errexit never applies to.
+    fn run_condition(&mut self, condition: &List) -> bool {
+        self.errexit_exempt += 1;
+        self.run_list(condition);
+        self.errexit_exempt -= 1;
+        self.last_status == 0
+    }
+
+    fn process_command(&mut self, command: &SimpleCommand) -> i32 {
+        self.run_trap(Trap::Debug);
+        if let Some(first) = command.words.first()
+            && DECLARATION_BUILTINS.contains(&first.raw.as_str())
+        {
+            return self.run_with_redirections(&command.redirects, |shell| shell.run_declaration(&command.words));
+        }
+
+        let argv = match self.expand_words(&command.words) {
+            Ok(argv) => argv,
+            Err(e) => return self.expansion_failed(e),
+        };
+
+        if argv.is_empty() {
+            return match self.parse_redirections(&command.redirects) {
+                Ok(_) => match self.perform_assignments(&command.assignments) {
+                    Ok(shown) => {
+                        // Each assignment is traced on a line of its own.
+                        for assignment in shown {
+                            self.trace_words(vec![assignment]);
+                        }
+                        0
+                    }
+                    Err(e) => self.expansion_failed(e),
+                },
+                Err(e) => {
+                    self.report_error(e);
+                    1
+                }
+            };
+        }
+
+        if let [dir] = argv.as_slice()
+            && self.autocd_applies(dir)
+        {
+            let _ = writeln!(self.fds.writer(1), "cd -- {dir}");
+            return self.change_directory(Some(dir), self.options.get("physical"));
+        }
+        self.run_simple(command, argv)
+    }
+
+    /// Runs an expanded simple command as a function, builtin or program.
+    fn run_simple(&mut self, command: &SimpleCommand, mut argv: Vec<String>) -> i32 {
+        if let Err(e) = self.check_restricted_command(&argv[0]) {
+            self.report_error(e);
+            return 1;
+        }
+
+        if argv[0] == "exec" {
+            return self.run_exec(command, &argv);
+        }
+
+        let function = self.functions.get(&argv[0]).cloned();
+        if function.is_some() || is_builtin(&argv[0]) {
+            return self.run_with_redirections(&command.redirects, |shell| {
+                // Prefix assignments only last as long as the command runs.
+                let saved: Vec<(String, Option<Variable>)> = command
+                    .assignments
+                    .iter()
+                    .map(|a| (a.name.clone(), shell.env_vars.get(&a.name).cloned()))
+                    .collect();
+                let status = match shell.perform_assignments(&command.assignments) {
+                    Ok(shown) => {
+                        shell.trace_words(shown.into_iter().chain(argv.iter().map(|arg| quote_for_trace(arg))).collect());
+                        match &function {
+                            Some(body) => shell.call_function(body, &argv),
+                            None => shell.run_audited_builtin(&argv),
+                        }
+                    }
+                    Err(e) => shell.expansion_failed(e),
+                };
+                for (name, var) in saved {
+                    shell.env_vars.restore(&name, var);
+                }
+                status
+            });
+        }
+
+        let program = match self.resolve_command(&argv[0]) {
+            Ok(program) => program,
+            Err(NotRunnable::NotFound) => {
+                if let Some(name) = self.offer_correction(&argv[0]) {
+                    argv[0] = name;
+                    return self.run_simple(command, argv);
+                }
+                return self.run_with_redirections(&command.redirects, |shell| shell.command_not_found(&argv));
+            }
+            Err(NotRunnable::Failed { status, message }) => {
+                return self.run_with_redirections(&command.redirects, |shell| {
+                    shell.report_error(message);
+                    shell.audit_unstarted(&argv, status);
+                    status
+                });
+            }
+        };
+
+        let env = match self.assignment_env(&command.assignments) {
+            Ok(env) => env,
+            Err(e) => return self.expansion_failed(e),
+        };
+        self.trace(&env, &argv);
+        match self.parse_redirections(&command.redirects) {
+            Ok(fds) => self.execute_external_command(&program, &argv, &env, &fds),
+            Err(e) => {
+                self.report_error(e);
+                1
+            }
+        }
+    }
+
+    /// With `shopt -s autocd`, an interactive shell treats a lone directory
+    /// name as `cd dir`.
+    fn autocd_applies(&self, word: &str) -> bool {
+        self.interactive
+            && self.options.get("autocd")
+            && !self.restricted()
+            && !is_builtin(word)
+            && !self.functions.contains_key(word)
+            && self.current_dir.join(word).is_dir()
+    }
+
+    /// Reports a failed expansion. Like other shells, a non-interactive vssh
+    /// gives up on the script at that point.
+    pub(crate) fn expansion_failed(&mut self, message: String) -> i32 {
+        self.report_error(message);
+        if !self.interactive {
+            self.running = false;
+        }
+        1
+    }
+
+    /// Prints an expanded command for `set -x`, prefixed by `$PS4`.
+    pub(crate) fn trace(&mut self, env: &[(String, String)], argv: &[String]) {
+        if !self.options.get("xtrace") {
+            return;
+        }
+        let words = env
+            .iter()
+            .map(|(name, value)| format!("{name}={}", quote_for_trace(value)))
+            .chain(argv.iter().map(|arg| quote_for_trace(arg)));
+        self.trace_words(words.collect());
+    }
+
+    /// Prints words already quoted for `set -x`, prefixed by `$PS4`.
+    pub(crate) fn trace_words(&mut self, words: Vec<String>) {
+        if !self.options.get("xtrace") {
+            return;
+        }
+        let mut line = self.prompt("PS4", "+ ");
+        line.push_str(&words.join(" "));
+        self.report_error(line);
+    }
+
+    /// Runs `body` with the command's redirections applied to the shell's fd table.
+    fn run_with_redirections(&mut self, redirects: &[Redirect], body: impl FnOnce(&mut Shell) -> i32) -> i32 {
+        let fds = match self.parse_redirections(redirects) {
+            Ok(fds) => fds,
+            Err(e) => {
+                self.report_error(e);
+                return 1;
+            }
+        };
+        let saved = std::mem::replace(&mut self.fds, fds);
+        let status = body(self);
+        self.fds = saved;
+        status
+    }
+
+    /// Opens the files named by `redirects` and returns the fd table the
+    /// command should run with.
+    fn parse_redirections(&mut self, redirects: &[Redirect]) -> Result<Fds, String> {
+        let mut fds = self.fds.clone();
+        let mut described = Vec::new();
+        for redirect in redirects {
+            let target = self.expand_word(&redirect.target)?;
+            self.check_restricted_redirect(redirect.op, &target)?;
+            let fd = match &redirect.fd_var {
+                Some(name) => self.named_fd(name, redirect.op, &target, &fds)?,
+                None => redirect.default_fd(),
+            };
+            if self.audit.is_some() {
+                described.push(describe_redirect(redirect, fd, &target));
+            }
+            let path = self.resolve_path(&target);
+            let mut options = OpenOptions::new();
+            match redirect.op {
+                RedirOp::In => {
+                    let file = options
+                        .read(true)
+                        .open(&path)
+                        .map_err(|_| format!("Failed to open input file: {target}"))?;
+                    fds.set(fd, file.into());
+                }
+                RedirOp::DupIn | RedirOp::DupOut => {
+                    if target == "-" {
+                        fds.close(fd);
+                    } else if let Ok(source) = target.parse::<i32>() {
+                        fds.duplicate(fd, source)?;
+                    } else if redirect.op == RedirOp::DupOut && redirect.fd.is_none() {
+                        // `>&file` is an old spelling of `&>file`.
+                        let file = open_output(&path, &target, false)?;
+                        fds.set(1, file);
+                        fds.duplicate(2, 1)?;
+                    } else {
+                        return Err(format!("{target}: ambiguous redirect"));
+                    }
+                }
+                RedirOp::ReadWrite => {
+                    let file = options
+                        .read(true)
+                        .write(true)
+                        .create(true)
+                        .truncate(false)
+                        .open(&path)
+                        .map_err(|_| format!("Failed to open file: {target}"))?;
+                    fds.set(fd, file.into());
+                }
+                RedirOp::Out => {
+                    self.check_clobber(&path, &target)?;
+                    fds.set(fd, open_output(&path, &target, false)?);
+                }
+                RedirOp::Clobber => fds.set(fd, open_output(&path, &target, false)?),
+                RedirOp::Append => fds.set(fd, open_output(&path, &target, true)?),
+                RedirOp::OutErr | RedirOp::AppendOutErr => {
+                    let append = redirect.op == RedirOp::AppendOutErr;
+                    if !append {
+                        self.check_clobber(&path, &target)?;
+                    }
+                    fds.set(1, open_output(&path, &target, append)?);
+                    fds.duplicate(2, 1)?;
+                }
+            }
+        }
+        self.audit_redirects(described);
+        Ok(fds)
+    }
+
+    /// The descriptor for a `{name}` redirection: the one `$name` holds when
+    /// closing it, or else a free one, which is stored in `$name`.
+    fn named_fd(&mut self, name: &str, op: RedirOp, target: &str, fds: &Fds) -> Result<i32, String> {
+        if matches!(op, RedirOp::DupIn | RedirOp::DupOut) && target == "-" {
+            return self
+                .env_vars
+                .scalar(name)
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(|| format!("{name}: ambiguous redirect"));
+        }
+        self.check_restricted_variable(name)?;
+        let fd = fds.lowest_free(FIRST_SHELL_FD);
+        self.env_vars.set_scalar(name, fd.to_string());
+        Ok(fd)
+    }
+
+    /// With `set -o noclobber`, `>` refuses to truncate an existing regular file.
+    fn check_clobber(&self, path: &Path, target: &str) -> Result<(), String> {
+        if self.options.get("noclobber") && path.metadata().is_ok_and(|m| m.is_file()) {
+            return Err(format!("{target}: cannot overwrite existing file"));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn resolve_path(&self, target: &str) -> PathBuf {
+        self.current_dir.join(target)
+    }
+
+    /// Performs `assignments` in order, and returns them as `set -x` shows
+    /// them.
+    pub(crate) fn perform_assignments(&mut self, assignments: &[Assignment]) -> Result<Vec<String>, String> {
+        assignments.iter().map(|assignment| self.assign(assignment)).collect()
+    }
+
+    /// Performs `assignment`, and returns it with its value expanded, as
+    /// `set -x` shows it.
+    pub(crate) fn assign(&mut self, assignment: &Assignment) -> Result<String, String> {
+        let name = assignment.name.as_str();
+        self.check_restricted_variable(name)?;
+        let op = if assignment.append { "+=" } else { "=" };
+        match (&assignment.index, &assignment.value) {
+            (None, AssignValue::Scalar(word)) => {
+                let mut value = self.expand_word(word)?;
+                let shown = format!("{name}{op}{}", quote_for_trace(&value));
+                if assignment.append {
+                    value.insert_str(0, self.env_vars.scalar(name).unwrap_or(""));
+                }
+                self.env_vars.set_scalar(name, value);
+                Ok(shown)
+            }
+            (Some(index), AssignValue::Scalar(word)) => {
+                let subscript = self.eval_subscript(name, index)?;
+                let mut value = self.expand_word(word)?;
+                let shown = format!("{name}[{index}]{op}{}", quote_for_trace(&value));
+                if assignment.append {
+                    value.insert_str(0, self.env_vars.element(name, &subscript).unwrap_or(""));
+                }
+                self.env_vars.set_element(name, subscript, value)?;
+                Ok(shown)
+            }
+            (None, AssignValue::Array(elements)) => {
+                let assoc = self.env_vars.is_assoc(name);
+                if !assignment.append {
+                    let empty = if assoc {
+                        Value::Assoc(BTreeMap::new())
+                    } else {
+                        Value::Indexed(BTreeMap::new())
+                    };
+                    self.env_vars.set_value(name, empty);
+                }
+                let mut pending = Vec::new();
+                let mut shown = Vec::new();
+                for (key, word) in elements {
+                    match key {
+                        Some(key) => {
+                            self.env_vars.append_elements(name, std::mem::take(&mut pending));
+                            let subscript = self.eval_subscript(name, key)?;
+                            let value = self.expand_word(word)?;
+                            shown.push(format!("[{key}]={}", quote_for_trace(&value)));
+                            self.env_vars.set_element(name, subscript, value)?;
+                        }
+                        None if assoc => {
+                            return Err(format!("{name}: {}: must use subscript when assigning associative array", word.raw));
+                        }
+                        None => {
+                            let values = self.expand_words(std::slice::from_ref(word))?;
+                            shown.extend(values.iter().map(|value| quote_for_trace(value)));
+                            pending.extend(values);
+                        }
+                    }
+                }
+                self.env_vars.append_elements(name, pending);
+                Ok(format!("{name}{op}({})", shown.join(" ")))
+            }
+            (Some(index), AssignValue::Array(_)) => Err(format!("{name}[{index}]: cannot assign list to array member")),
+        }
+    }
+
+    /// The extra environment a command's prefix assignments give it.
+    fn assignment_env(&mut self, assignments: &[Assignment]) -> Result<Vec<(String, String)>, String> {
+        let mut env = Vec::new();
+        for assignment in assignments {
+            self.check_restricted_variable(&assignment.name)?;
+            if let (None, AssignValue::Scalar(word)) = (&assignment.index, &assignment.value) {
+                env.push((assignment.name.clone(), self.expand_word(word)?));
+            }
+        }
+        Ok(env)
+    }
+
+    /// Every executable `name` resolves to along `$PATH`, or `name` itself
+    /// when it contains a slash.
+    pub(crate) fn find_in_path(&self, name: &str) -> Vec<PathBuf> {
+        let path_var = self.env_vars.scalar("PATH").unwrap_or(DEFAULT_PATH);
+        search_path(path_var, &self.current_dir, name)
+    }
+
+    /// Runs an already expanded command line with the current descriptors,
+    /// as `command` does.
+    pub(crate) fn run_argv(&mut self, argv: &[String]) -> i32 {
+        if let Err(e) = self.check_restricted_command(&argv[0]) {
+            self.report_error(e);
+            return 1;
+        }
+        if is_builtin(&argv[0]) {
+            return self.run_builtin(argv);
+        }
+        let program = match self.resolve_command(&argv[0]) {
+            Ok(program) => program,
+            Err(NotRunnable::NotFound) => return self.command_not_found(argv),
+            Err(NotRunnable::Failed { status, message }) => {
+                self.report_error(message);
+                self.audit_unstarted(argv, status);
+                return status;
+            }
+        };
+        let fds = self.fds.clone();
+        self.execute_external_command(&program, argv, &[], &fds)
+    }
+
+    /// Replaces the current process with `argv`. Builtins have no program to
+    /// exec, so they run here instead and their status is returned, as it is
+    /// when the exec fails.
+    pub(crate) fn exec_argv(&mut self, argv: &[String]) -> i32 {
+        if let Err(e) = self.check_restricted_command(&argv[0]) {
+            self.report_error(e);
+            return 1;
+        }
+        if is_builtin(&argv[0]) {
+            return self.run_builtin(argv);
+        }
+        match self.resolve_command(&argv[0]) {
+            Ok(program) => self.exec_program(&program, argv, &[], &ExecOptions::default()),
+            Err(NotRunnable::NotFound) => self.command_not_found(argv),
+            Err(NotRunnable::Failed { status, message }) => {
+                self.report_error(message);
+                self.audit_unstarted(argv, status);
+                status
+            }
+        }
+    }
+
+    /// `exec [-cl] [-a name] [command [args...]]` with its redirections and
+    /// assignments. Without a command they outlast it, changing the shell's
+    /// own descriptors and variables for everything it runs afterwards.
+    fn run_exec(&mut self, command: &SimpleCommand, argv: &[String]) -> i32 {
+        let (options, start) = match parse_exec_options(argv) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                self.report_error(e);
+                return 2;
+            }
+        };
+        let fds = match self.parse_redirections(&command.redirects) {
+            Ok(fds) => fds,
+            Err(e) => {
+                self.report_error(e);
+                return 1;
+            }
+        };
+        if start == argv.len() {
+            self.fds = fds;
+            return match self.perform_assignments(&command.assignments) {
+                Ok(shown) => {
+                    self.trace_words(shown.into_iter().chain(argv.iter().map(|arg| quote_for_trace(arg))).collect());
+                    0
+                }
+                Err(e) => self.expansion_failed(e),
+            };
+        }
+
+        let env = match self.assignment_env(&command.assignments) {
+            Ok(env) => env,
+            Err(e) => return self.expansion_failed(e),
+        };
+        self.trace(&env, argv);
+        // The redirections only stay if the exec fails in an interactive shell.
+        let saved = std::mem::replace(&mut self.fds, fds);
+        let status = self.exec_replacing_shell(&argv[start..], &env, &options);
+        self.fds = saved;
+        status
+    }
+
+    /// `exec` reached without its redirections, as in `command exec ls`.
+    pub(crate) fn builtin_exec(&mut self, argv: &[String]) -> i32 {
+        match parse_exec_options(argv) {
+            Ok((_, start)) if start == argv.len() => 0,
+            Ok((options, start)) => self.exec_replacing_shell(&argv[start..], &[], &options),
+            Err(e) => {
+                self.report_error(e);
+                2
+            }
+        }
+    }
+
+    /// Execs `argv` for the `exec` builtin. A shell that cannot exec its
+    /// replacement exits with the failure status, unless it is interactive.
+    fn exec_replacing_shell(&mut self, argv: &[String], env: &[(String, String)], options: &ExecOptions) -> i32 {
+        let status = match self.resolve_command(&argv[0]) {
+            Ok(program) => self.exec_program(&program, argv, env, options),
+            Err(NotRunnable::NotFound) => {
+                self.report_error(format_args!("vssh: exec: {}: not found", argv[0]));
+                127
+            }
+            Err(NotRunnable::Failed { status, message }) => {
+                self.report_error(message);
+                status
+            }
+        };
+        if !self.interactive {
+            self.running = false;
+        }
+        status
+    }
+
+    /// Replaces the current process with `program`, with `env` on top of the
+    /// exported variables, and returns the status if that fails.
+    fn exec_program(&mut self, program: &Path, argv: &[String], env: &[(String, String)], options: &ExecOptions) -> i32 {
+        let mut cmd = self.build_command(program, argv, env);
+        if options.clear_env {
+            cmd.env_clear();
+            cmd.envs(env.iter().map(|(k, v)| (k, v)));
+        }
+        let name = options.name.as_deref().unwrap_or(&argv[0]);
+        if options.login {
+            cmd.arg0(format!("-{name}"));
+        } else {
+            cmd.arg0(name);
+        }
+        let error = match self.fds.configure(&mut cmd) {
+            Ok(()) => {
+                self.apply_seccomp(&mut cmd);
+                // Nothing is left to remove the named pipes once the shell
+                // is replaced.
+                self.remove_fifo_dir();
+                cmd.exec()
+            }
+            Err(e) => e,
+        };
+        self.report_error(format_args!("Failed to execute command: {}", error));
+        exec_failure_status(&error)
+    }
+
+    /// A command that runs `program` with `argv`, keeping `argv[0]` as the
+    /// name the program sees.
+    fn build_command(&self, program: &Path, argv: &[String], env: &[(String, String)]) -> Process {
+        let mut cmd = Process::new(program);
+        cmd.arg0(&argv[0]);
+        cmd.args(&argv[1..]);
+        cmd.current_dir(&self.current_dir);
+        cmd.env_clear();
+        cmd.envs(self.env_vars.exported());
+        cmd.envs(env.iter().map(|(k, v)| (k, v)));
+        cmd
+    }
+
+    fn process_piped_commands(&mut self, commands: &[Command]) -> i32 {
+        let statuses: Vec<i32> = self
+            .spawn_pipeline(commands)
+            .into_iter()
+            .map(|stage| match stage {
+                Ok(pid) => self.wait_for(pid),
+                Err(status) => status,
+            })
+            .collect();
+        self.set_pipestatus(&statuses);
+        if self.options.get("pipefail") {
+            // The rightmost failure decides the status of the whole pipeline.
+            statuses.iter().rev().find(|s| **s != 0).copied().unwrap_or(0)
+        } else {
+            statuses.last().copied().unwrap_or(0)
+        }
+    }
+
+    /// Starts every stage of a pipeline, connected by pipes, without waiting.
+    fn spawn_pipeline(&mut self, commands: &[Command]) -> Vec<Result<Pid, i32>> {
+        let mut previous_stdout: Option<OwnedFd> = None;
+        let mut stages = Vec::new();
+
+        for (i, command) in commands.iter().enumerate() {
+            let last = i == commands.len() - 1;
+            let (next_stdin, stdout) = if last {
+                (None, None)
+            } else {
+                match io::pipe() {
+                    Ok((read, write)) => (Some(read), Some(write)),
+                    Err(e) => {
+                        self.report_error(format_args!("Failed to create pipe: {}", e));
+                        stages.push(Err(1));
+                        break;
+                    }
+                }
+            };
+
+            let mut fds = self.fds.clone();
+            if let Some(stdin) = previous_stdout.take() {
+                fds.set(0, stdin);
+            }
+            if let Some(stdout) = stdout {
+                fds.set(1, stdout);
+            }
+            stages.push(self.spawn_stage(command, fds, next_stdin.as_ref().map(AsRawFd::as_raw_fd)));
+            previous_stdout = next_stdin;
+        }
+        stages
+    }
+
+    /// Starts one pipeline stage with `fds` as its descriptors, without
+    /// waiting for it. Builtins run in a forked copy of the shell.
+    /// `close_in_child` is the parent's end of the next pipe, which a forked
+    /// stage must not keep open.
+    fn spawn_stage(&mut self, command: &Command, fds: Fds, close_in_child: Option<RawFd>) -> Result<Pid, i32> {
+        let saved = std::mem::replace(&mut self.fds, fds);
+        let result = self.spawn_stage_inner(command, close_in_child);
+        self.fds = saved;
+        result
+    }
+
+    fn spawn_stage_inner(&mut self, command: &Command, close_in_child: Option<RawFd>) -> Result<Pid, i32> {
+        if let Command::Simple(simple) = command
+            && simple
+                .words
+                .first()
+                .is_some_and(|w| !DECLARATION_BUILTINS.contains(&w.raw.as_str()))
+        {
+            let argv = self.expand_words(&simple.words).map_err(|e| self.expansion_failed(e))?;
+            // Anything but a program that can run goes to a forked copy of the
+            // shell, which also reports why a program cannot, or why a
+            // restricted shell refuses it.
+            if let Some(name) = argv.first()
+                && self.check_restricted_command(name).is_ok()
+                && !is_builtin(name)
+                && !self.functions.contains_key(name)
+                && let Ok(program) = self.resolve_command(name)
+            {
+                let env = self
+                    .assignment_env(&simple.assignments)
+                    .map_err(|e| self.expansion_failed(e))?;
+                self.trace(&env, &argv);
+                let fds = self.parse_redirections(&simple.redirects).map_err(|e| {
+                    self.report_error(e);
+                    1
+                })?;
+                return self.spawn_external(&program, &argv, &env, &fds);
+            }
+        }
+        self.fork_subshell(close_in_child, |shell| shell.run_command(command))
+    }
+
+    /// Runs `body` in a forked child of the shell and returns the child's pid.
+    pub(crate) fn fork_subshell(&mut self, close_in_child: Option<RawFd>, body: impl FnOnce(&mut Shell) -> i32) -> Result<Pid, i32> {
+        // SAFETY: the child only runs shell code and then exits without
+        // returning into the parent's stack.
+        match unsafe { fork() } {
+            Ok(ForkResult::Child) => {
+                if let Some(fd) = close_in_child {
+                    let _ = nix::unistd::close(fd);
+                }
+                self.fds.close_private();
+                self.jobs = Jobs::default();
+                self.interactive = false;
+                // Subshells keep ignored signals but not trap actions.
+                signals::leave_interactive();
+                for (trap, action) in std::mem::take(&mut self.traps) {
+                    match trap {
+                        Trap::Signal(signal) if action.is_empty() => {
+                            let _ = signals::set_disposition(signal, Disposition::Ignore);
+                            self.traps.insert(trap, action);
+                        }
+                        Trap::Signal(signal) => {
+                            let _ = signals::set_disposition(signal, Disposition::Default);
+                        }
+                        _ if action.is_empty() => {
+                            self.traps.insert(trap, action);
+                        }
+                        _ => {}
+                    }
+                }
+                let status = body(self);
+                std::process::exit(status);
+            }
+            Ok(ForkResult::Parent { child }) => Ok(child),
+            Err(e) => {
+                self.report_error(format_args!("Failed to fork: {}", e));
+                Err(1)
+            }
+        }
+    }
+
+    fn execute_external_command(&mut self, program: &Path, argv: &[String], env: &[(String, String)], fds: &Fds) -> i32 {
+        match self.spawn_external(program, argv, env, fds) {
+            Ok(pid) => self.wait_for(pid),
+            Err(status) => status,
+        }
+    }
+
+    fn spawn_external(&mut self, program: &Path, argv: &[String], env: &[(String, String)], fds: &Fds) -> Result<Pid, i32> {
+        let mut cmd = self.build_command(program, argv, env);
+        if let Err(e) = fds.configure(&mut cmd) {
+            self.report_error(format_args!("Failed to execute command: {}", e));
+            self.audit_unstarted(argv, 126);
+            return Err(126);
+        }
+        self.apply_seccomp(&mut cmd);
+        match cmd.spawn() {
+            Ok(child) => {
+                let pid = Pid::from_raw(child.id() as i32);
+                self.audit_spawned(pid, argv);
+                Ok(pid)
+            }
+            Err(e) => {
+                self.report_error(format_args!("Failed to execute command: {}", e));
+                let status = exec_failure_status(&e);
+                self.audit_unstarted(argv, status);
+                Err(status)
+            }
+        }
+    }
+
+    pub(crate) fn note_background(&mut self, pids: Vec<Pid>, text: &str) {
+        if let Some(last) = pids.last() {
+            self.last_background_pid = Some(last.as_raw() as u32);
+        }
+        let id = self.jobs.add(pids, text);
+        if self.interactive {
+            let _ = writeln!(self.fds.writer(2), "[{id}] {}", self.last_background_pid.unwrap_or(0));
+        }
+    }
+
+    /// Waits for `pid` and returns its exit status, `128 + signal` if it was killed.
+    pub(crate) fn wait_for(&mut self, pid: Pid) -> i32 {
+        loop {
+            let mut status = 0;
+            // SAFETY: wait4 only writes into the status and usage it is given.
+            let (reaped, usage) = unsafe {
+                let mut usage: libc::rusage = std::mem::zeroed();
+                (libc::wait4(pid.as_raw(), &mut status, 0, &mut usage), usage)
+            };
+            if reaped > 0 {
+                self.record_max_rss(&usage);
+            }
+            let result = if reaped < 0 {
+                Err(Errno::last())
+            } else {
+                WaitStatus::from_raw(pid, status)
+            };
+            match result {
+                Ok(WaitStatus::Exited(_, code)) => {
+                    self.audit_reaped(pid, Outcome::Exited(code), &usage);
+                    return code;
+                }
+                Ok(WaitStatus::Signaled(_, signal, _)) => {
+                    self.audit_reaped(pid, Outcome::Signaled(signal), &usage);
+                    if signal == nix::sys::signal::Signal::SIGSYS
+                        && let Some(profile) = &self.seccomp
+                    {
+                        let message = format!("Command killed for a system call forbidden by {}", profile.name);
+                        self.report_error(message);
+                    }
+                    return 128 + signal as i32;
+                }
+                Ok(_) | Err(Errno::EINTR) => continue,
+                Err(e) => {
+                    self.report_error(format_args!("Failed to wait for command: {}", e));
+                    return 1;
+                }
+            }
+        }
+    }
+
+    fn set_pipestatus(&mut self, statuses: &[i32]) {
+        let map = statuses.iter().enumerate().map(|(i, s)| (i, s.to_string())).collect();
+        self.env_vars.set_value("PIPESTATUS", Value::Indexed(map));
+    }
+}
+
+fn open_output(path: &Path, target: &str, append: bool) -> Result<OwnedFd, String> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true);
+    if append {
+        options.append(true);
+    } else {
+        options.truncate(true);
+    }
+    options
+        .open(path)
+        .map(OwnedFd::from)
+        .map_err(|_| format!("Failed to open output file: {target}"))
+}
+
+/// Quotes a word for `set -x` output so that it reads back as the same word.
+pub(crate) fn quote_for_trace(s: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_./=:,@%+-".contains(c);
+    if !s.is_empty() && s.chars().all(plain) {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', r"'\''"))
+    }
+}
+
+/// How `exec` runs its command: `-a name` sets the name the program sees,
+/// `-l` puts a dash in front of it as login shells expect, and `-c` runs it
+/// with only the command's own assignments in its environment.
+#[derive(Default)]
+struct ExecOptions {
+    name: Option<String>,
+    login: bool,
+    clear_env: bool,
+}
+
+/// Reads the options of `exec` and returns them with the index of the command.
+fn parse_exec_options(argv: &[String]) -> Result<(ExecOptions, usize), String> {
+    let mut options = ExecOptions::default();
+    let mut i = 1;
+    while let Some(arg) = argv.get(i).filter(|a| a.len() > 1 && a.starts_with('-')) {
+        i += 1;
+        if arg == "--" {
+            break;
+        }
+        for flag in arg[1..].chars() {
+            match flag {
+                'c' => options.clear_env = true,
+                'l' => options.login = true,
+                'a' => {
+                    let name = argv.get(i).ok_or("exec: -a: option requires an argument")?;
+                    options.name = Some(name.clone());
+                    i += 1;
+                }
+                _ => return Err(format!("exec: -{flag}: invalid option")),
+            }
+        }
+    }
+    Ok((options, i))
+}
+
+/// The status for a program that could not be started: 127 if it is not
+/// there, 126 if it is but cannot run.
+fn exec_failure_status(error: &std::io::Error) -> i32 {
+    if error.kind() == std::io::ErrorKind::NotFound { 127 } else { 126 }
+}
+
+/// The search path used when `PATH` is unset.
+pub(crate) const DEFAULT_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+
+/// The executables that `name` could run, in `path_var` order. A name with a
+/// slash is taken relative to `cwd` instead of being searched for.
+pub(crate) fn search_path(path_var: &str, cwd: &Path, name: &str) -> Vec<PathBuf> {
+    if name.contains('/') {
+        let path = cwd.join(name);
+        return if is_executable(&path) { vec![PathBuf::from(name)] } else { Vec::new() };
+    }
+    if name.is_empty() {
+        return Vec::new();
+    }
+    path_var
+        .split(':')
+        .map(|dir| if dir.is_empty() { cwd.join(name) } else { Path::new(dir).join(name) })
+        .filter(|candidate| is_executable(candidate))
+        .collect()
+}
+
+/// Whether `path` is a regular file we may execute.
+pub(crate) fn is_executable(path: &Path) -> bool {
+    path.is_file() && nix::unistd::access(path, nix::unistd::AccessFlags::X_OK).is_ok()
+}
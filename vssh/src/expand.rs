@@ -0,0 +1,345 @@
+use crate::arith;
+use crate::parser::{self, Param, ParamOp, TestKind, Word, WordPart};
+use crate::Shell;
+use crate::vars::Subscript;
+
+/// What field splitting uses when `IFS` is unset.
+const DEFAULT_IFS: &str = " \t\n";
+
+/// A piece of an expanded word, remembering where its text came from.
+#[derive(Debug)]
+pub enum Chunk {
+    Text {
+        text: String,
+        /// Unquoted expansion results are subject to field splitting.
+        split: bool,
+        /// Quoted text keeps its field alive even when empty.
+        quoted: bool,
+    },
+    /// A hard field boundary, as between the elements of `"${arr[@]}"`.
+    Break,
+}
+
+/// What a parameter expands to before it is placed into a word.
+enum Expansion {
+    Unset,
+    Scalar(String),
+    /// `$@`, `${arr[@]}` and friends; `star` marks the `*` forms.
+    List { items: Vec<String>, star: bool },
+}
+
+impl Shell {
+    /// Expands `words` into the fields that make up a command's argv.
+    pub fn expand_words(&mut self, words: &[Word]) -> Result<Vec<String>, String> {
+        let ifs = self.env_vars.scalar("IFS").unwrap_or(DEFAULT_IFS).to_string();
+        let mut fields = Vec::new();
+        for word in words {
+            let chunks = self.expand_variables(&word.parts)?;
+            split_fields(chunks, &ifs, &mut fields);
+        }
+        Ok(fields)
+    }
+
+    /// Expands a word to a single string without field splitting, as for
+    /// assignment values and redirection targets.
+    pub fn expand_word(&mut self, word: &Word) -> Result<String, String> {
+        let chunks = self.expand_variables(&word.parts)?;
+        let mut out = String::new();
+        for chunk in chunks {
+            match chunk {
+                Chunk::Text { text, .. } => out.push_str(&text),
+                Chunk::Break => out.push(' '),
+            }
+        }
+        Ok(out)
+    }
+
+    /// Parses and expands a fragment of source text, such as an array subscript.
+    pub fn expand_str(&mut self, raw: &str) -> Result<String, String> {
+        let word = parser::parse_word(raw).map_err(|e| e.to_string())?;
+        self.expand_word(&word)
+    }
+
+    /// Substitutes parameters in `parts`, keeping track of quoting.
+    pub(crate) fn expand_variables(&mut self, parts: &[WordPart]) -> Result<Vec<Chunk>, String> {
+        let mut chunks = Vec::new();
+        for part in parts {
+            match part {
+                WordPart::Lit { text, quoted } => chunks.push(Chunk::Text {
+                    text: text.clone(),
+                    split: false,
+                    quoted: *quoted,
+                }),
+                WordPart::Param { param, quoted } => {
+                    let quoted = *quoted;
+                    match self.lookup_param(param)? {
+                        Expansion::Unset => chunks.push(Chunk::Text {
+                            text: String::new(),
+                            split: !quoted,
+                            quoted,
+                        }),
+                        Expansion::Scalar(text) => chunks.push(Chunk::Text {
+                            text,
+                            split: !quoted,
+                            quoted,
+                        }),
+                        Expansion::List { items, star: true } if quoted => chunks.push(Chunk::Text {
+                            text: items.join(&self.ifs_separator()),
+                            split: false,
+                            quoted: true,
+                        }),
+                        Expansion::List { items, .. } => {
+                            for (i, item) in items.into_iter().enumerate() {
+                                if i > 0 {
+                                    chunks.push(Chunk::Break);
+                                }
+                                chunks.push(Chunk::Text {
+                                    text: item,
+                                    split: !quoted,
+                                    quoted,
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(chunks)
+    }
+
+    fn lookup_param(&mut self, param: &Param) -> Result<Expansion, String> {
+        if let ParamOp::Test { colon, kind, word } = &param.op {
+            return self.test_param(param, *colon, *kind, word);
+        }
+        let value = self.lookup_value(param)?;
+        if matches!(value, Expansion::Unset) && self.options.get("nounset") {
+            let name = match &param.index {
+                Some(index) => format!("{}[{index}]", param.name),
+                None => param.name.clone(),
+            };
+            return Err(format!("{name}: unbound variable"));
+        }
+        if matches!(value, Expansion::Unset) && param.op == ParamOp::Length {
+            return Ok(Expansion::Scalar("0".to_string()));
+        }
+        if matches!(value, Expansion::Unset) && matches!(param.index.as_deref(), Some("@" | "*")) {
+            // An unset array expands to no words at all, even when quoted.
+            return Ok(Expansion::List {
+                items: Vec::new(),
+                star: param.index.as_deref() == Some("*"),
+            });
+        }
+        Ok(value)
+    }
+
+    /// Handles `${x-w}`, `${x:=w}`, `${x:+w}` and `${x:?w}`.
+    fn test_param(&mut self, param: &Param, colon: bool, kind: TestKind, word: &str) -> Result<Expansion, String> {
+        let value = self.lookup_value(&Param {
+            op: ParamOp::Value,
+            ..param.clone()
+        })?;
+        let set = match &value {
+            Expansion::Unset => false,
+            Expansion::Scalar(s) => !(colon && s.is_empty()),
+            Expansion::List { items, .. } => !(colon && items.iter().all(String::is_empty)),
+        };
+        match kind {
+            TestKind::Default if !set => Ok(Expansion::Scalar(self.expand_str(word)?)),
+            TestKind::Assign if !set => {
+                if !crate::vars::is_valid_name(&param.name) {
+                    return Err(format!("${}: cannot assign in this way", param.name));
+                }
+                self.check_restricted_variable(&param.name)?;
+                let text = self.expand_str(word)?;
+                match &param.index {
+                    Some(raw) => {
+                        let subscript = self.eval_subscript(&param.name, raw)?;
+                        self.env_vars.set_element(&param.name, subscript, text.clone())?;
+                    }
+                    None => self.env_vars.set_scalar(&param.name, text.clone()),
+                }
+                Ok(Expansion::Scalar(text))
+            }
+            TestKind::Alternate if set => Ok(Expansion::Scalar(self.expand_str(word)?)),
+            TestKind::Alternate => Ok(Expansion::Scalar(String::new())),
+            TestKind::Error if !set => {
+                let message = if word.is_empty() {
+                    "parameter null or not set".to_string()
+                } else {
+                    self.expand_str(word)?
+                };
+                Err(format!("{}: {message}", param.name))
+            }
+            _ => Ok(value),
+        }
+    }
+
+    /// Looks a parameter up without applying `set -u`.
+    fn lookup_value(&mut self, param: &Param) -> Result<Expansion, String> {
+        let name = param.name.as_str();
+        let all = matches!(param.index.as_deref(), Some("@" | "*"));
+        let star = param.index.as_deref() == Some("*") || name == "*";
+
+        match param.op {
+            ParamOp::Keys => {
+                let items = self.env_vars.get(name).map(|v| v.value.keys()).unwrap_or_default();
+                Ok(Expansion::List { items, star })
+            }
+            ParamOp::Length => {
+                let len = if name == "@" || name == "*" {
+                    self.positional.len()
+                } else if all {
+                    match self.env_vars.get(name) {
+                        Some(var) => var.value.len(),
+                        None => return Ok(Expansion::Unset),
+                    }
+                } else {
+                    match self.lookup_value(&Param {
+                        op: ParamOp::Value,
+                        ..param.clone()
+                    })? {
+                        Expansion::Scalar(s) => s.chars().count(),
+                        Expansion::Unset => return Ok(Expansion::Unset),
+                        Expansion::List { .. } => 0,
+                    }
+                };
+                Ok(Expansion::Scalar(len.to_string()))
+            }
+            ParamOp::Test { .. } => unreachable!("handled by test_param"),
+            ParamOp::Value => {
+                if let Some(value) = self.special_param(name) {
+                    return Ok(value);
+                }
+                if all {
+                    return Ok(match self.env_vars.get(name) {
+                        Some(var) => Expansion::List {
+                            items: var.value.values(),
+                            star,
+                        },
+                        None => Expansion::Unset,
+                    });
+                }
+                let value = match &param.index {
+                    Some(raw) => {
+                        let subscript = self.eval_subscript(name, raw)?;
+                        self.env_vars.element(name, &subscript).map(str::to_string)
+                    }
+                    None => self.env_vars.scalar(name).map(str::to_string),
+                };
+                Ok(value.map_or(Expansion::Unset, Expansion::Scalar))
+            }
+        }
+    }
+
+    fn special_param(&self, name: &str) -> Option<Expansion> {
+        let scalar = |s: String| Some(Expansion::Scalar(s));
+        match name {
+            "?" => scalar(self.last_status.to_string()),
+            "#" => scalar(self.positional.len().to_string()),
+            "$" => scalar(std::process::id().to_string()),
+            "0" => scalar(self.script_name.clone()),
+            "-" => {
+                let mut flags = self.options.flags();
+                if self.interactive {
+                    flags.push('i');
+                }
+                scalar(flags)
+            }
+            "!" => Some(
+                self.last_background_pid
+                    .map_or(Expansion::Unset, |pid| Expansion::Scalar(pid.to_string())),
+            ),
+            "@" | "*" => Some(Expansion::List {
+                items: self.positional.clone(),
+                star: name == "*",
+            }),
+            _ if name.starts_with(|c: char| c.is_ascii_digit()) => {
+                let n: usize = name.parse().ok()?;
+                Some(
+                    self.positional
+                        .get(n.checked_sub(1)?)
+                        .map_or(Expansion::Unset, |s| Expansion::Scalar(s.clone())),
+                )
+            }
+            _ => None,
+        }
+    }
+
+    /// Resolves `raw` to a subscript of `name`: a string key for associative
+    /// arrays, an arithmetic index for everything else.
+    pub(crate) fn eval_subscript(&mut self, name: &str, raw: &str) -> Result<Subscript, String> {
+        let text = self.expand_str(raw)?;
+        if self.env_vars.is_assoc(name) {
+            return Ok(Subscript::Key(text));
+        }
+        let vars = &self.env_vars;
+        arith::eval(&text, &|n| vars.scalar(n).map(str::to_string))
+            .map(Subscript::Index)
+            .map_err(|e| format!("{name}[{raw}]: {e}"))
+    }
+
+    /// The separator `"$*"` and `"${arr[*]}"` join with: the first character of IFS.
+    fn ifs_separator(&self) -> String {
+        match self.env_vars.scalar("IFS") {
+            Some(ifs) => ifs.chars().next().map(String::from).unwrap_or_default(),
+            None => " ".to_string(),
+        }
+    }
+}
+
+/// Performs field splitting on unquoted expansion results and appends the
+/// resulting fields to `out`. Runs of IFS whitespace separate fields and
+/// vanish at either end; every other IFS character ends exactly one field,
+/// taking the whitespace around it along, so that two in a row delimit an
+/// empty field.
+fn split_fields(chunks: Vec<Chunk>, ifs: &str, out: &mut Vec<String>) {
+    let mut field = String::new();
+    let mut exists = false;
+    // Set when IFS whitespace just ended a field, so that a non-whitespace
+    // IFS character right after it belongs to the same delimiter.
+    let mut after_space = false;
+    for chunk in chunks {
+        match chunk {
+            Chunk::Break => {
+                if exists {
+                    out.push(std::mem::take(&mut field));
+                }
+                exists = false;
+                after_space = false;
+            }
+            Chunk::Text {
+                text,
+                split: false,
+                quoted,
+            } => {
+                exists |= quoted || !text.is_empty();
+                after_space &= text.is_empty();
+                field.push_str(&text);
+            }
+            Chunk::Text { text, split: true, .. } => {
+                for c in text.chars() {
+                    if !ifs.contains(c) {
+                        field.push(c);
+                        exists = true;
+                        after_space = false;
+                    } else if matches!(c, ' ' | '\t' | '\n') {
+                        if exists {
+                            out.push(std::mem::take(&mut field));
+                            after_space = true;
+                        }
+                        exists = false;
+                    } else {
+                        if exists || !after_space {
+                            out.push(std::mem::take(&mut field));
+                        }
+                        exists = false;
+                        after_space = false;
+                    }
+                }
+            }
+        }
+    }
+    if exists {
+        out.push(field);
+    }
+}
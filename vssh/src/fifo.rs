@@ -0,0 +1,117 @@
+//! The `fifo` builtin: named pipes for commands that only take file names,
+//! such as a program's `--log-file`, or for connecting two coprocesses.
+//!
+//! `fifo NAME...` creates a named pipe for each NAME in a directory private
+//! to the shell, created on first use under `$TMPDIR` (or `/tmp`) with mode
+//! 0700, and sets `$NAME` to its path. `fifo -d NAME...` removes the pipe
+//! and unsets the variable. When the shell exits, or `exec` replaces it,
+//! the directory is removed with whatever is left in it. Forked subshells
+//! share the directory but leave removing it to the shell that created it.
+
+use std::ffi::{CString, OsString};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+use nix::errno::Errno;
+use nix::libc;
+use nix::sys::stat::Mode;
+
+use crate::vars::is_valid_name;
+use crate::Shell;
+
+/// The directory holding the shell's named pipes.
+pub(crate) struct FifoDir {
+    path: PathBuf,
+    /// The process that created it, and removes it on exit.
+    owner: u32,
+}
+
+impl Shell {
+    /// `fifo [-d] name...`
+    pub(crate) fn builtin_fifo(&mut self, argv: &[String]) -> i32 {
+        let (remove, names) = match argv.get(1).map(String::as_str) {
+            Some("-d") => (true, &argv[2..]),
+            _ => (false, &argv[1..]),
+        };
+        if names.is_empty() {
+            self.report_error("fifo: usage: fifo [-d] name...");
+            return 2;
+        }
+        let mut status = 0;
+        for name in names {
+            let result = if !is_valid_name(name) {
+                Err(format!("`{name}': not a valid identifier"))
+            } else if let Err(e) = self.check_restricted_variable(name) {
+                Err(e)
+            } else if remove {
+                self.remove_fifo(name)
+            } else {
+                self.create_fifo(name)
+            };
+            if let Err(e) = result {
+                self.report_error(format_args!("fifo: {e}"));
+                status = 1;
+            }
+        }
+        status
+    }
+
+    fn create_fifo(&mut self, name: &str) -> Result<(), String> {
+        let dir = self.fifo_dir()?;
+        let path = dir.join(name);
+        nix::unistd::mkfifo(&path, Mode::S_IRUSR | Mode::S_IWUSR)
+            .map_err(|e| format!("{}: cannot create named pipe: {}", path.display(), e.desc()))?;
+        self.env_vars.set_scalar(name, path.display().to_string());
+        Ok(())
+    }
+
+    /// Removes the pipe `$name` names, which must be one of the shell's.
+    fn remove_fifo(&mut self, name: &str) -> Result<(), String> {
+        let path = self.env_vars.scalar(name).map(PathBuf::from);
+        let ours = self.fifo_dir.as_ref().zip(path.as_ref()).is_some_and(|(dir, path)| path.parent() == Some(&dir.path));
+        let Some(path) = path.filter(|_| ours) else {
+            return Err(format!("{name}: not a named pipe made by fifo"));
+        };
+        std::fs::remove_file(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+        self.env_vars.unset(name);
+        Ok(())
+    }
+
+    /// The shell's directory for named pipes, created if need be.
+    fn fifo_dir(&mut self) -> Result<PathBuf, String> {
+        if let Some(dir) = &self.fifo_dir {
+            return Ok(dir.path.clone());
+        }
+        let tmp = self.env_vars.scalar("TMPDIR").filter(|dir| !dir.is_empty()).unwrap_or("/tmp");
+        let template = Path::new(tmp).join("vssh-fifo-XXXXXX");
+        let path = make_temp_dir(&template).map_err(|e| format!("{}: {}", template.display(), e.desc()))?;
+        self.fifo_dir = Some(FifoDir {
+            path: path.clone(),
+            owner: std::process::id(),
+        });
+        Ok(path)
+    }
+
+    /// Removes the shell's named pipes when it exits.
+    pub(crate) fn remove_fifo_dir(&mut self) {
+        if let Some(dir) = self.fifo_dir.take()
+            && dir.owner == std::process::id()
+        {
+            let _ = std::fs::remove_dir_all(&dir.path);
+        }
+    }
+}
+
+/// Creates a directory with mode 0700 named by `template`, whose trailing
+/// `XXXXXX` is replaced to make the name unique, as mkdtemp(3) does.
+fn make_temp_dir(template: &Path) -> nix::Result<PathBuf> {
+    let template = CString::new(template.as_os_str().as_bytes()).map_err(|_| Errno::EINVAL)?;
+    let mut bytes = template.into_bytes_with_nul();
+    // SAFETY: `bytes` is a NUL-terminated template that mkdtemp rewrites in
+    // place.
+    if unsafe { libc::mkdtemp(bytes.as_mut_ptr().cast()) }.is_null() {
+        return Err(Errno::last());
+    }
+    bytes.pop();
+    Ok(PathBuf::from(OsString::from_vec(bytes)))
+}
@@ -0,0 +1,243 @@
+//! Remembered locations of programs, and the `hash` builtin.
+//!
+//! The first time a name is run the shell searches `$PATH` for it and keeps
+//! the result, so later runs skip the search. The table belongs to the value
+//! of `$PATH` it was filled under and is emptied when `$PATH` changes. An
+//! entry whose file has gone away is searched for again.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::builtins::is_builtin;
+use crate::exec::{is_executable, DEFAULT_PATH};
+use crate::Shell;
+
+#[derive(Default)]
+pub struct HashTable {
+    /// The `$PATH` the entries were found along.
+    path_var: String,
+    entries: BTreeMap<String, Entry>,
+}
+
+struct Entry {
+    path: PathBuf,
+    /// How many times the entry has been used to run a command.
+    hits: usize,
+}
+
+/// Why a command name cannot be run as a program.
+pub(crate) enum NotRunnable {
+    /// Nothing by that name along `$PATH`.
+    NotFound,
+    /// A file that cannot be run, or a path to nothing, with the status to
+    /// exit with: 126 or 127.
+    Failed { status: i32, message: String },
+}
+
+impl Shell {
+    /// Finds the program `name` runs, from the hash table or by searching
+    /// `$PATH`, and counts the hit.
+    pub(crate) fn resolve_command(&mut self, name: &str) -> Result<PathBuf, NotRunnable> {
+        if name.contains('/') {
+            let path = self.current_dir.join(name);
+            return match std::fs::metadata(&path) {
+                Err(e) => Err(NotRunnable::Failed {
+                    status: 127,
+                    message: format!("vssh: {name}: {}", describe(&e)),
+                }),
+                Ok(meta) if meta.is_dir() => Err(NotRunnable::Failed {
+                    status: 126,
+                    message: format!("vssh: {name}: Is a directory"),
+                }),
+                Ok(_) if !is_executable(&path) => Err(NotRunnable::Failed {
+                    status: 126,
+                    message: format!("vssh: {name}: Permission denied"),
+                }),
+                Ok(_) => Ok(PathBuf::from(name)),
+            };
+        }
+
+        self.sync_hash_table();
+        if let Some(entry) = self.hashed.entries.get_mut(name) {
+            if is_executable(&entry.path) {
+                entry.hits += 1;
+                return Ok(entry.path.clone());
+            }
+            self.hashed.entries.remove(name);
+        }
+        match self.find_in_path(name).into_iter().next() {
+            Some(path) => {
+                let path = self.current_dir.join(path);
+                self.remember_command(name, path.clone(), 1);
+                Ok(path)
+            }
+            // Like bash, fall back to a file that is there but cannot be run,
+            // so that the status says which.
+            None if self.find_file_in_path(name) => Err(NotRunnable::Failed {
+                status: 126,
+                message: format!("vssh: {name}: Permission denied"),
+            }),
+            None => Err(NotRunnable::NotFound),
+        }
+    }
+
+    /// Empties the hash table if `$PATH` has changed since it was filled.
+    fn sync_hash_table(&mut self) {
+        let path_var = self.env_vars.scalar("PATH").unwrap_or(DEFAULT_PATH);
+        if self.hashed.path_var != path_var {
+            self.hashed = HashTable {
+                path_var: path_var.to_string(),
+                entries: BTreeMap::new(),
+            };
+        }
+    }
+
+    fn remember_command(&mut self, name: &str, path: PathBuf, hits: usize) {
+        self.hashed.entries.insert(name.to_string(), Entry { path, hits });
+    }
+
+    /// Where the hash table says `name` is, if it has an entry for it.
+    pub(crate) fn hashed_path(&self, name: &str) -> Option<&Path> {
+        let path_var = self.env_vars.scalar("PATH").unwrap_or(DEFAULT_PATH);
+        if self.hashed.path_var != path_var {
+            return None;
+        }
+        self.hashed.entries.get(name).map(|entry| entry.path.as_path())
+    }
+
+    /// Whether some directory along `$PATH` holds a regular file `name`,
+    /// executable or not.
+    fn find_file_in_path(&self, name: &str) -> bool {
+        let path_var = self.env_vars.scalar("PATH").unwrap_or(DEFAULT_PATH);
+        path_var
+            .split(':')
+            .map(|dir| self.current_dir.join(dir).join(name))
+            .any(|candidate| candidate.is_file())
+    }
+
+    /// `hash [-lr] [-p path] [-dt] [name...]`
+    pub(crate) fn builtin_hash(&mut self, argv: &[String]) -> i32 {
+        let (mut reset, mut delete, mut print_path, mut reusable) = (false, false, false, false);
+        let mut explicit = None;
+        let mut i = 1;
+        while let Some(arg) = argv.get(i).filter(|a| a.len() > 1 && a.starts_with('-')) {
+            i += 1;
+            if arg == "--" {
+                break;
+            }
+            for flag in arg[1..].chars() {
+                match flag {
+                    'r' => reset = true,
+                    'd' => delete = true,
+                    't' => print_path = true,
+                    'l' => reusable = true,
+                    'p' => match argv.get(i) {
+                        Some(path) => {
+                            explicit = Some(PathBuf::from(path));
+                            i += 1;
+                        }
+                        None => {
+                            self.report_error("hash: -p: option requires an argument");
+                            return 2;
+                        }
+                    },
+                    _ => {
+                        self.report_error(format_args!("hash: -{flag}: invalid option"));
+                        return 2;
+                    }
+                }
+            }
+        }
+        let names = &argv[i..];
+
+        self.sync_hash_table();
+        if reset {
+            self.hashed.entries.clear();
+        }
+        if let Some(path) = explicit {
+            if self.restricted() && path.to_string_lossy().contains('/') {
+                self.report_error(format_args!("hash: {}: restricted", path.display()));
+                return 1;
+            }
+            for name in names {
+                self.remember_command(name, path.clone(), 0);
+            }
+            return 0;
+        }
+        if names.is_empty() {
+            if !reset {
+                self.print_hash_table(reusable);
+            }
+            return 0;
+        }
+
+        let mut status = 0;
+        let mut out = self.fds.writer(1);
+        for name in names {
+            if delete {
+                if self.hashed.entries.remove(name).is_none() {
+                    self.report_error(format_args!("hash: {name}: not found"));
+                    status = 1;
+                }
+                continue;
+            }
+            if print_path {
+                match self.hashed.entries.get(name) {
+                    Some(entry) if names.len() > 1 => {
+                        let _ = writeln!(out, "{name}\t{}", entry.path.display());
+                    }
+                    Some(entry) => {
+                        let _ = writeln!(out, "{}", entry.path.display());
+                    }
+                    None => {
+                        self.report_error(format_args!("hash: {name}: not found"));
+                        status = 1;
+                    }
+                }
+                continue;
+            }
+            if name.contains('/') || is_builtin(name) || self.functions.contains_key(name) {
+                continue;
+            }
+            match self.find_in_path(name).into_iter().next() {
+                Some(path) => {
+                    let path = self.current_dir.join(path);
+                    self.remember_command(name, path, 0);
+                }
+                None => {
+                    self.report_error(format_args!("hash: {name}: not found"));
+                    status = 1;
+                }
+            }
+        }
+        status
+    }
+
+    fn print_hash_table(&self, reusable: bool) {
+        let mut out = self.fds.writer(1);
+        if self.hashed.entries.is_empty() {
+            self.report_error("hash: hash table empty");
+            return;
+        }
+        if !reusable {
+            let _ = writeln!(out, "hits\tcommand");
+        }
+        for (name, entry) in &self.hashed.entries {
+            let _ = if reusable {
+                writeln!(out, "hash -p {} {name}", entry.path.display())
+            } else {
+                writeln!(out, "{:4}\t{}", entry.hits, entry.path.display())
+            };
+        }
+    }
+}
+
+/// An error message without the `(os error N)` that `io::Error` appends.
+fn describe(error: &std::io::Error) -> String {
+    let text = error.to_string();
+    match text.find(" (os error") {
+        Some(end) => text[..end].to_string(),
+        None => text,
+    }
+}
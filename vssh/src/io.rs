@@ -0,0 +1,176 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+
+use nix::fcntl::{fcntl, FcntlArg};
+
+/// The shell's view of its file descriptors.
+///
+/// Builtins write through this table instead of the process's own fd 0-2, so
+/// redirecting a builtin only means swapping an entry, and every spawned
+/// command receives exactly the descriptors listed here.
+#[derive(Clone)]
+pub struct Fds {
+    map: BTreeMap<i32, Rc<OwnedFd>>,
+    /// Entries kept for the shell alone, such as its ends of coprocesses,
+    /// which spawned commands and subshells do not inherit.
+    private: BTreeSet<i32>,
+}
+
+impl Fds {
+    /// Private duplicates of the process's stdin, stdout and stderr.
+    pub fn inherit() -> Self {
+        let mut map = BTreeMap::new();
+        for fd in 0..3 {
+            if let Ok(copy) = dup_cloexec(fd) {
+                map.insert(fd, Rc::new(copy));
+            }
+        }
+        Fds {
+            map,
+            private: BTreeSet::new(),
+        }
+    }
+
+    pub fn get(&self, fd: i32) -> Option<RawFd> {
+        self.map.get(&fd).map(|f| f.as_raw_fd())
+    }
+
+    pub fn set(&mut self, fd: i32, file: OwnedFd) {
+        self.private.remove(&fd);
+        self.map.insert(fd, Rc::new(file));
+    }
+
+    /// Like [`Fds::set`], but keeps `fd` out of spawned commands and subshells.
+    pub fn set_private(&mut self, fd: i32, file: OwnedFd) {
+        self.map.insert(fd, Rc::new(file));
+        self.private.insert(fd);
+    }
+
+    /// Makes `fd` refer to whatever `source` refers to, as `fd>&source` does.
+    pub fn duplicate(&mut self, fd: i32, source: i32) -> Result<(), String> {
+        let file = self
+            .map
+            .get(&source)
+            .cloned()
+            .ok_or_else(|| format!("{source}: Bad file descriptor"))?;
+        self.private.remove(&fd);
+        self.map.insert(fd, file);
+        Ok(())
+    }
+
+    /// The lowest descriptor from `from` up that the table has no entry for.
+    pub fn lowest_free(&self, from: i32) -> i32 {
+        (from..).find(|fd| !self.map.contains_key(fd)).unwrap_or(from)
+    }
+
+    pub fn close(&mut self, fd: i32) {
+        self.private.remove(&fd);
+        self.map.remove(&fd);
+    }
+
+    /// Drops the private entries in a freshly forked subshell, and closes
+    /// their descriptors unless another entry still refers to them. Tables
+    /// saved further up the parent's stack share them too, but a subshell
+    /// never returns to drop those.
+    pub fn close_private(&mut self) {
+        for fd in std::mem::take(&mut self.private) {
+            let Some(file) = self.map.remove(&fd) else {
+                continue;
+            };
+            if !self.map.values().any(|other| Rc::ptr_eq(other, &file)) {
+                let _ = nix::unistd::close(file.as_raw_fd());
+            }
+        }
+    }
+
+    pub fn writer(&self, fd: i32) -> FdWriter {
+        FdWriter(self.get(fd))
+    }
+
+    /// Hands the table to a command about to be spawned.
+    pub fn configure(&self, cmd: &mut Command) -> io::Result<()> {
+        let mut closed = Vec::new();
+        for fd in 0..3 {
+            match self.map.get(&fd) {
+                Some(file) => {
+                    let stdio = Stdio::from(file.try_clone()?);
+                    match fd {
+                        0 => cmd.stdin(stdio),
+                        1 => cmd.stdout(stdio),
+                        _ => cmd.stderr(stdio),
+                    };
+                }
+                None => closed.push(fd),
+            }
+        }
+        let extra: Vec<(i32, RawFd)> = self
+            .map
+            .iter()
+            .filter(|(fd, _)| **fd > 2 && !self.private.contains(fd))
+            .map(|(fd, file)| (*fd, file.as_raw_fd()))
+            .collect();
+        if closed.is_empty() && extra.is_empty() {
+            return Ok(());
+        }
+        // Copies of the sources are parked above every target, so that a
+        // mapping like 3->5, 5->3 cannot clobber itself.
+        let park_from = extra.last().map_or(0, |&(target, _)| target + 1);
+        let mut parked = vec![0; extra.len()];
+        // SAFETY: only async-signal-safe fcntl/dup2/close calls run in the
+        // child, and nothing is allocated between fork and exec.
+        unsafe {
+            cmd.pre_exec(move || {
+                for (slot, &(_, source)) in parked.iter_mut().zip(&extra) {
+                    *slot = fcntl(source, FcntlArg::F_DUPFD_CLOEXEC(park_from))?;
+                }
+                for (&park, &(target, _)) in parked.iter().zip(&extra) {
+                    nix::unistd::dup2(park, target)?;
+                    let _ = nix::unistd::close(park);
+                }
+                for fd in &closed {
+                    let _ = nix::unistd::close(*fd);
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Duplicates `fd` with close-on-exec set, so the copy never leaks into children.
+pub fn dup_cloexec(fd: RawFd) -> io::Result<OwnedFd> {
+    let copy = fcntl(fd, FcntlArg::F_DUPFD_CLOEXEC(3)).map_err(io::Error::from)?;
+    // SAFETY: fcntl just returned this descriptor and nothing else owns it.
+    Ok(unsafe { OwnedFd::from_raw_fd(copy) })
+}
+
+/// A pipe whose ends are both close-on-exec.
+pub fn pipe() -> io::Result<(OwnedFd, OwnedFd)> {
+    let (read, write) = nix::unistd::pipe2(nix::fcntl::OFlag::O_CLOEXEC).map_err(io::Error::from)?;
+    // SAFETY: pipe2 just returned these descriptors and nothing else owns them.
+    Ok(unsafe { (OwnedFd::from_raw_fd(read), OwnedFd::from_raw_fd(write)) })
+}
+
+/// Unbuffered writes to a descriptor borrowed from an [`Fds`] table.
+pub struct FdWriter(Option<RawFd>);
+
+impl Write for FdWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let fd = self.0.ok_or_else(|| io::Error::from_raw_os_error(nix::libc::EBADF))?;
+        loop {
+            match nix::unistd::write(fd, buf) {
+                Ok(n) => return Ok(n),
+                Err(nix::errno::Errno::EINTR) => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
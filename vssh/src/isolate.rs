@@ -0,0 +1,161 @@
+//! The `isolate` builtin: runs a command in fresh Linux namespaces.
+//!
+//! The shell forks a child that unshares user, PID, mount, UTS, IPC and
+//! network namespaces and maps the caller's uid and gid to root, which needs
+//! no privileges. Because a new PID namespace only applies to processes
+//! created afterwards, that child forks again; the grandchild is pid 1 in the
+//! sandbox, mounts its own /proc, optionally chroots, and runs the command.
+
+use std::fs;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::{Path, PathBuf};
+
+use nix::libc;
+use nix::mount::{mount, MsFlags};
+use nix::sched::{unshare, CloneFlags};
+use nix::sys::socket::{socket, AddressFamily, SockFlag, SockType};
+use nix::unistd::{chdir, chroot, getgid, getuid, sethostname, Gid, Uid};
+
+use crate::Shell;
+
+struct Sandbox {
+    root: Option<PathBuf>,
+    hostname: Option<String>,
+}
+
+impl Shell {
+    /// `isolate [-r root] [-h hostname] [--] command [arg...]`
+    pub(crate) fn builtin_isolate(&mut self, argv: &[String]) -> i32 {
+        let mut sandbox = Sandbox {
+            root: None,
+            hostname: None,
+        };
+        let mut i = 1;
+        while let Some(arg) = argv.get(i).filter(|a| a.starts_with('-')) {
+            i += 1;
+            if arg == "--" {
+                break;
+            }
+            let Some(value) = argv.get(i) else {
+                self.report_error(format_args!("isolate: {arg}: option requires an argument"));
+                return 2;
+            };
+            i += 1;
+            match arg.as_str() {
+                "-r" => sandbox.root = Some(self.resolve_path(value)),
+                "-h" => sandbox.hostname = Some(value.clone()),
+                _ => {
+                    self.report_error(format_args!("isolate: {arg}: invalid option"));
+                    return 2;
+                }
+            }
+        }
+        let command = argv[i..].to_vec();
+        if command.is_empty() {
+            self.report_error("isolate: usage: isolate [-r root] [-h hostname] command [arg...]");
+            return 2;
+        }
+        if let Some(root) = &sandbox.root
+            && !root.join("proc").is_dir()
+        {
+            self.report_error(format_args!("isolate: {}: no proc directory to mount on", root.display()));
+            return 1;
+        }
+
+        let (uid, gid) = (getuid(), getgid());
+        let child = self.fork_subshell(None, move |shell| {
+            if let Err(e) = enter_namespaces(uid, gid) {
+                shell.report_error(format_args!("isolate: {e}"));
+                return 1;
+            }
+            match shell.fork_subshell(None, move |shell| shell.run_sandboxed(&sandbox, &command)) {
+                Ok(pid) => shell.wait_for(pid),
+                Err(status) => status,
+            }
+        });
+        match child {
+            Ok(pid) => self.wait_for(pid),
+            Err(status) => status,
+        }
+    }
+
+    /// Runs as pid 1 of the new PID namespace.
+    fn run_sandboxed(&mut self, sandbox: &Sandbox, command: &[String]) -> i32 {
+        if let Err(e) = prepare_filesystem(sandbox) {
+            self.report_error(format_args!("isolate: {e}"));
+            return 1;
+        }
+        if let Some(name) = &sandbox.hostname
+            && let Err(e) = sethostname(name)
+        {
+            self.report_error(format_args!("isolate: cannot set hostname: {}", e.desc()));
+            return 1;
+        }
+        // The namespace starts with its loopback interface down; without it
+        // even local servers cannot be reached.
+        let _ = bring_up_loopback();
+        if sandbox.root.is_some() {
+            self.current_dir = PathBuf::from("/");
+            self.update_pwd();
+        }
+        self.exec_argv(command)
+    }
+}
+
+/// Unshares the namespaces and maps the caller to root inside them.
+fn enter_namespaces(uid: Uid, gid: Gid) -> Result<(), String> {
+    let flags = CloneFlags::CLONE_NEWUSER
+        | CloneFlags::CLONE_NEWPID
+        | CloneFlags::CLONE_NEWNS
+        | CloneFlags::CLONE_NEWUTS
+        | CloneFlags::CLONE_NEWIPC
+        | CloneFlags::CLONE_NEWNET;
+    unshare(flags).map_err(|e| format!("cannot create namespaces: {}", e.desc()))?;
+    // An unprivileged process may only write its gid map once setgroups(2)
+    // has been disabled.
+    let write = |file: &str, contents: String| {
+        fs::write(Path::new("/proc/self").join(file), contents).map_err(|e| format!("cannot write {file}: {e}"))
+    };
+    write("setgroups", "deny".to_string())?;
+    write("uid_map", format!("0 {uid} 1"))?;
+    write("gid_map", format!("0 {gid} 1"))
+}
+
+/// Detaches the sandbox's mounts from the host, mounts a /proc that shows
+/// only the sandbox's processes, and chroots if asked to.
+fn prepare_filesystem(sandbox: &Sandbox) -> Result<(), String> {
+    mount(None::<&str>, "/", None::<&str>, MsFlags::MS_REC | MsFlags::MS_PRIVATE, None::<&str>)
+        .map_err(|e| format!("cannot make mounts private: {}", e.desc()))?;
+    let root = sandbox.root.as_deref().unwrap_or(Path::new("/"));
+    let proc = root.join("proc");
+    let flags = MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_NOEXEC;
+    mount(Some("proc"), &proc, Some("proc"), flags, None::<&str>)
+        .map_err(|e| format!("cannot mount {}: {}", proc.display(), e.desc()))?;
+    if sandbox.root.is_some() {
+        chroot(root).map_err(|e| format!("cannot chroot to {}: {}", root.display(), e.desc()))?;
+        chdir("/").map_err(|e| format!("cannot change to /: {}", e.desc()))?;
+    }
+    Ok(())
+}
+
+fn bring_up_loopback() -> nix::Result<()> {
+    let sock = socket(AddressFamily::Inet, SockType::Datagram, SockFlag::SOCK_CLOEXEC, None)?;
+    // SAFETY: `socket` just returned this descriptor and nothing else owns it.
+    let sock = unsafe { OwnedFd::from_raw_fd(sock) };
+    // SAFETY: `request` is a zeroed ifreq naming "lo", which is what both
+    // ioctls expect.
+    unsafe {
+        let mut request: libc::ifreq = std::mem::zeroed();
+        for (dst, src) in request.ifr_name.iter_mut().zip(b"lo\0") {
+            *dst = *src as libc::c_char;
+        }
+        if libc::ioctl(sock.as_raw_fd(), libc::SIOCGIFFLAGS, &mut request) < 0 {
+            return Err(nix::errno::Errno::last());
+        }
+        request.ifr_ifru.ifru_flags |= libc::IFF_UP as libc::c_short;
+        if libc::ioctl(sock.as_raw_fd(), libc::SIOCSIFFLAGS, &request) < 0 {
+            return Err(nix::errno::Errno::last());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,411 @@
+//! Background jobs and the `jobs`, `wait` and `kill` builtins.
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use nix::errno::Errno;
+use nix::libc;
+use nix::sys::signal::{kill, Signal};
+use nix::sys::wait::WaitStatus;
+use nix::unistd::Pid;
+
+use crate::audit::Outcome;
+use crate::signals;
+use crate::Shell;
+
+/// A pipeline started with `&`.
+#[derive(Debug)]
+pub struct Job {
+    pub id: usize,
+    pub pids: Vec<Pid>,
+    /// Exit statuses, filled in as each process is reaped.
+    statuses: Vec<Option<i32>>,
+    pub text: String,
+}
+
+impl Job {
+    pub fn done(&self) -> bool {
+        self.statuses.iter().all(Option::is_some)
+    }
+
+    /// The status of the job's last process, as for a foreground pipeline.
+    pub fn status(&self) -> Option<i32> {
+        self.statuses.last().copied().flatten()
+    }
+
+    fn state(&self) -> String {
+        match self.status() {
+            _ if !self.done() => "Running".to_string(),
+            Some(0) => "Done".to_string(),
+            Some(status) if status > 128 => Signal::try_from(status - 128)
+                .map(|signal| signal.as_str().trim_start_matches("SIG").to_string())
+                .unwrap_or_else(|_| format!("Exit {status}")),
+            Some(status) => format!("Exit {status}"),
+            None => "Running".to_string(),
+        }
+    }
+
+    fn record(&mut self, pid: Pid, status: i32) {
+        if let Some(i) = self.pids.iter().position(|&p| p == pid) {
+            self.statuses[i] = Some(status);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Jobs {
+    jobs: Vec<Job>,
+    /// Statuses of processes from jobs that were already removed, so that
+    /// `wait $pid` still works after the job was reported.
+    remembered: HashMap<Pid, i32>,
+}
+
+impl Jobs {
+    pub fn add(&mut self, pids: Vec<Pid>, text: &str) -> usize {
+        let id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        self.jobs.push(Job {
+            id,
+            statuses: vec![None; pids.len()],
+            pids,
+            text: text.to_string(),
+        });
+        id
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    /// Forgets finished jobs once they have been reported.
+    pub fn take_done(&mut self) {
+        let (done, running) = std::mem::take(&mut self.jobs).into_iter().partition(Job::done);
+        self.jobs = running;
+        for job in done {
+            self.forget(job);
+        }
+    }
+
+    fn remove(&mut self, index: usize) {
+        let job = self.jobs.remove(index);
+        self.forget(job);
+    }
+
+    fn forget(&mut self, job: Job) {
+        for (pid, status) in job.pids.into_iter().zip(job.statuses) {
+            if let Some(status) = status {
+                self.remembered.insert(pid, status);
+            }
+        }
+    }
+
+    /// Resolves a job spec: `%N`, `%%`, `%+`, `%-`, `%prefix` or `%?text`.
+    pub fn find(&self, spec: &str) -> Result<usize, String> {
+        let body = spec.strip_prefix('%').unwrap_or(spec);
+        let found = match body {
+            "" | "%" | "+" => self.jobs.len().checked_sub(1),
+            "-" => self.jobs.len().checked_sub(2).or(self.jobs.len().checked_sub(1)),
+            _ if body.bytes().all(|b| b.is_ascii_digit()) => {
+                let id: usize = body.parse().unwrap_or(0);
+                self.jobs.iter().position(|job| job.id == id)
+            }
+            _ => {
+                let matches: Vec<usize> = match body.strip_prefix('?') {
+                    Some(text) => self.positions(|job| job.text.contains(text)),
+                    None => self.positions(|job| job.text.starts_with(body)),
+                };
+                if matches.len() > 1 {
+                    return Err(format!("{spec}: ambiguous job spec"));
+                }
+                matches.first().copied()
+            }
+        };
+        found.ok_or_else(|| format!("{spec}: no such job"))
+    }
+
+    fn positions(&self, pred: impl Fn(&Job) -> bool) -> Vec<usize> {
+        (0..self.jobs.len()).filter(|&i| pred(&self.jobs[i])).collect()
+    }
+
+    fn by_pid(&self, pid: Pid) -> Option<usize> {
+        self.jobs.iter().position(|job| job.pids.contains(&pid))
+    }
+
+    /// The `+`/`-` marker `jobs` shows next to the current and previous job.
+    fn marker(&self, index: usize) -> char {
+        match self.jobs.len() - index {
+            1 => '+',
+            2 => '-',
+            _ => ' ',
+        }
+    }
+
+    fn describe(&self, index: usize) -> String {
+        let job = &self.jobs[index];
+        format!("[{}]{}  {:<24}{}", job.id, self.marker(index), job.state(), job.text)
+    }
+}
+
+impl Shell {
+    /// Collects the status of every job process that has exited, without blocking.
+    fn reap_jobs(&mut self) {
+        for index in 0..self.jobs.len() {
+            for i in 0..self.jobs.jobs[index].pids.len() {
+                let job = &self.jobs.jobs[index];
+                if job.statuses[i].is_some() {
+                    continue;
+                }
+                let pid = job.pids[i];
+                if let Some(status) = self.reap_job_process(pid, false) {
+                    self.jobs.jobs[index].statuses[i] = Some(status);
+                }
+            }
+        }
+    }
+
+    /// Blocks until every process of the job at `index` has exited.
+    fn wait_job(&mut self, index: usize) -> i32 {
+        for i in 0..self.jobs.jobs[index].pids.len() {
+            let job = &self.jobs.jobs[index];
+            if job.statuses[i].is_none() {
+                let pid = job.pids[i];
+                let status = self.reap_job_process(pid, true);
+                self.jobs.jobs[index].statuses[i] = status;
+            }
+        }
+        self.jobs.jobs[index].status().unwrap_or(0)
+    }
+
+    /// Waits for the job `id`, which a builtin started and waits on in the
+    /// foreground, and forgets it.
+    pub(crate) fn wait_foreground_job(&mut self, id: usize) -> i32 {
+        let Some(index) = self.jobs.jobs.iter().position(|job| job.id == id) else {
+            return 0;
+        };
+        let status = self.wait_job(index);
+        self.jobs.remove(index);
+        status
+    }
+
+    /// Reaps the job process `pid` once it has exited, waiting for that if
+    /// `block` is set, and logs it to the audit log. Returns its exit status,
+    /// `128 + signal` if it was killed, or `None` if it is still running.
+    fn reap_job_process(&mut self, pid: Pid, block: bool) -> Option<i32> {
+        let flags = if block { 0 } else { libc::WNOHANG };
+        loop {
+            let mut status = 0;
+            // SAFETY: wait4 only writes into the status and usage it is given.
+            let (reaped, usage) = unsafe {
+                let mut usage: libc::rusage = std::mem::zeroed();
+                (libc::wait4(pid.as_raw(), &mut status, flags, &mut usage), usage)
+            };
+            match reaped {
+                0 => return None,
+                _ if reaped < 0 && Errno::last() == Errno::EINTR => continue,
+                // Someone else reaped it; there is no status to report.
+                _ if reaped < 0 => return Some(127),
+                _ => self.record_max_rss(&usage),
+            }
+            match WaitStatus::from_raw(pid, status) {
+                Ok(WaitStatus::Exited(_, code)) => {
+                    self.audit_reaped(pid, Outcome::Exited(code), &usage);
+                    return Some(code);
+                }
+                Ok(WaitStatus::Signaled(_, signal, _)) => {
+                    self.audit_reaped(pid, Outcome::Signaled(signal), &usage);
+                    return Some(128 + signal as i32);
+                }
+                _ if block => continue,
+                _ => return None,
+            }
+        }
+    }
+
+    /// Prints `[1]+  Done  cmd` for jobs that finished since the last prompt.
+    pub(crate) fn notify_jobs(&mut self) {
+        self.reap_jobs();
+        // A non-interactive shell keeps finished jobs around for `wait`.
+        if !self.interactive {
+            return;
+        }
+        let mut out = self.fds.writer(2);
+        for i in (0..self.jobs.len()).filter(|&i| self.jobs.jobs[i].done()) {
+            let _ = writeln!(out, "{}", self.jobs.describe(i));
+        }
+        self.jobs.take_done();
+    }
+
+    /// `jobs [-lp]`
+    pub(crate) fn builtin_jobs(&mut self, argv: &[String]) -> i32 {
+        self.reap_jobs();
+        let mut out = self.fds.writer(1);
+        for (i, job) in self.jobs.jobs.iter().enumerate() {
+            let _ = match argv.get(1).map(String::as_str) {
+                Some("-p") => writeln!(out, "{}", job.pids[0]),
+                Some("-l") => writeln!(out, "[{}]{} {} {:<24}{}", job.id, self.jobs.marker(i), job.pids[0], job.state(), job.text),
+                _ => writeln!(out, "{}", self.jobs.describe(i)),
+            };
+        }
+        self.jobs.take_done();
+        0
+    }
+
+    /// `wait [%job | pid ...]`: with no operands, waits for every job.
+    pub(crate) fn builtin_wait(&mut self, argv: &[String]) -> i32 {
+        if argv.len() == 1 {
+            for i in 0..self.jobs.len() {
+                self.wait_job(i);
+            }
+            self.jobs.take_done();
+            return 0;
+        }
+
+        let mut status = 0;
+        for arg in &argv[1..] {
+            let index = if arg.starts_with('%') {
+                self.jobs.find(arg)
+            } else {
+                match arg.parse::<i32>() {
+                    Ok(pid) => {
+                        if let Some(code) = self.jobs.remembered.remove(&Pid::from_raw(pid)) {
+                            status = code;
+                            continue;
+                        }
+                        self.jobs
+                            .by_pid(Pid::from_raw(pid))
+                            .ok_or_else(|| format!("pid {pid} is not a child of this shell"))
+                    }
+                    Err(_) => Err(format!("`{arg}': not a pid or valid job spec")),
+                }
+            };
+            status = match index {
+                Ok(index) if arg.starts_with('%') => {
+                    let status = self.wait_job(index);
+                    self.jobs.remove(index);
+                    status
+                }
+                Ok(index) => {
+                    // Waiting on one process of a pipeline only reports that process.
+                    let pid = Pid::from_raw(arg.parse().unwrap_or(0));
+                    let job = &self.jobs.jobs[index];
+                    let i = job.pids.iter().position(|&p| p == pid).unwrap_or(0);
+                    let code = match job.statuses[i] {
+                        Some(code) => code,
+                        None => self.reap_job_process(pid, true).unwrap_or(127),
+                    };
+                    let job = &mut self.jobs.jobs[index];
+                    job.record(pid, code);
+                    if job.done() {
+                        self.jobs.remove(index);
+                        self.jobs.remembered.remove(&pid);
+                    }
+                    code
+                }
+                Err(e) => {
+                    self.report_error(format_args!("wait: {e}"));
+                    127
+                }
+            };
+        }
+        status
+    }
+
+    /// `kill [-s SIG | -SIG] %job|pid...` and `kill -l [status]`.
+    pub(crate) fn builtin_kill(&mut self, argv: &[String]) -> i32 {
+        let mut signal = Some(Signal::SIGTERM);
+        let mut args = &argv[1..];
+        match args.first().map(String::as_str) {
+            Some("-l" | "-L") => return self.list_signals(&args[1..]),
+            Some("-s" | "-n") => {
+                let Some(spec) = args.get(1) else {
+                    self.report_error("kill: option requires an argument");
+                    return 2;
+                };
+                signal = match signals::parse_signal(spec) {
+                    Some(parsed) => parsed,
+                    None => {
+                        self.report_error(format_args!("kill: {spec}: invalid signal specification"));
+                        return 1;
+                    }
+                };
+                args = &args[2..];
+            }
+            Some("--") => args = &args[1..],
+            Some(spec) if spec.len() > 1 && spec.starts_with('-') => {
+                signal = match signals::parse_signal(&spec[1..]) {
+                    Some(parsed) => parsed,
+                    None => {
+                        self.report_error(format_args!("kill: {}: invalid signal specification", &spec[1..]));
+                        return 1;
+                    }
+                };
+                args = &args[1..];
+            }
+            _ => {}
+        }
+        if args.is_empty() {
+            self.report_error("kill: usage: kill [-s sigspec | -sigspec] pid | jobspec ... or kill -l [sigspec]");
+            return 2;
+        }
+
+        let mut status = 0;
+        for arg in args {
+            let pids = if arg.starts_with('%') {
+                match self.jobs.find(arg) {
+                    Ok(index) => self.jobs.jobs[index].pids.clone(),
+                    Err(e) => {
+                        self.report_error(format_args!("kill: {e}"));
+                        status = 1;
+                        continue;
+                    }
+                }
+            } else {
+                match arg.parse::<i32>() {
+                    Ok(pid) => vec![Pid::from_raw(pid)],
+                    Err(_) => {
+                        self.report_error(format_args!("kill: {arg}: arguments must be process or job IDs"));
+                        status = 1;
+                        continue;
+                    }
+                }
+            };
+            for pid in pids {
+                if let Err(e) = kill(pid, signal) {
+                    self.report_error(format_args!("kill: ({pid}) - {}", e.desc()));
+                    status = 1;
+                }
+            }
+        }
+        status
+    }
+
+    /// `kill -l`: every signal name, or the name for each given number or
+    /// exit status.
+    fn list_signals(&self, args: &[String]) -> i32 {
+        let mut out = self.fds.writer(1);
+        if args.is_empty() {
+            for signal in Signal::iterator() {
+                let _ = writeln!(out, "{:2}) {}", signal as i32, signal.as_str());
+            }
+            return 0;
+        }
+        let mut status = 0;
+        for arg in args {
+            let number = arg.parse::<i32>().map(|n| if n > 128 { n - 128 } else { n });
+            match number.ok().and_then(|n| Signal::try_from(n).ok()) {
+                Some(signal) => {
+                    let _ = writeln!(out, "{}", signal.as_str().trim_start_matches("SIG"));
+                }
+                None => match signals::parse_signal(arg) {
+                    Some(Some(signal)) => {
+                        let _ = writeln!(out, "{}", signal as i32);
+                    }
+                    _ => {
+                        self.report_error(format_args!("kill: {arg}: invalid signal specification"));
+                        status = 1;
+                    }
+                },
+            }
+        }
+        status
+    }
+}
@@ -0,0 +1,387 @@
+//! vssh as a library: the parser, the expander and the executor behind the
+//! `vssh` binary, driven through [`Shell`].
+//!
+//! A shell writes through its own file descriptor table rather than the
+//! process's, so an embedder can point fds 0, 1 and 2 (or any other) at files
+//! or pipes with [`Shell::set_fd`], and start from an environment of its
+//! choosing with [`Shell::with_env`]. Commands are run in this process, and
+//! external commands and subshells are forked from it.
+//!
+//! ```no_run
+//! let mut shell = vssh::Shell::with_env([("PATH".to_string(), "/bin:/usr/bin".to_string())]);
+//! let status = shell.execute_command("greeting=hello; echo $greeting >&2");
+//! assert_eq!(status, 0);
+//! ```
+
+mod arith;
+mod audit;
+mod builtins;
+mod cond;
+mod coproc;
+mod direnv;
+mod dirstack;
+mod editor;
+mod exec;
+mod expand;
+mod fifo;
+mod hash;
+mod io;
+mod isolate;
+mod jobs;
+mod limits;
+mod notfound;
+mod options;
+pub mod parser;
+mod printf;
+mod prompt;
+mod pty;
+mod read;
+mod remote;
+mod repeat;
+mod restricted;
+mod seccomp;
+mod signals;
+mod timeout;
+mod timing;
+mod vars;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Display;
+use std::io::{self as stdio, Write};
+use std::os::fd::OwnedFd;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::audit::AuditLog;
+use crate::direnv::LoadedEnv;
+use crate::editor::{Line, LineEditor};
+use crate::fifo::FifoDir;
+use crate::hash::HashTable;
+use crate::io::Fds;
+use crate::jobs::Jobs;
+use crate::options::Options;
+use crate::parser::{Command, List};
+use crate::seccomp::Profile;
+use crate::signals::Trap;
+use crate::vars::Variables;
+
+pub use crate::remote::connect;
+
+/// A shell: its variables, options, jobs, traps and file descriptors.
+pub struct Shell {
+    /// The logical working directory, which may go through symlinks.
+    current_dir: PathBuf,
+    /// `pushd` entries below the working directory, most recent first.
+    dir_stack: Vec<PathBuf>,
+    /// The `.vsshenv` files in effect, outermost directory first.
+    dir_envs: Vec<LoadedEnv>,
+    env_vars: Variables,
+    running: bool,
+    jobs: Jobs,
+    last_status: i32,
+    last_background_pid: Option<u32>,
+    positional: Vec<String>,
+    script_name: String,
+    fds: Fds,
+    options: Options,
+    /// Nesting depth of contexts where a failure is being tested, such as an
+    /// `if` condition, in which errexit does not apply.
+    errexit_exempt: usize,
+    interactive: bool,
+    traps: BTreeMap<Trap, String>,
+    /// Shell functions by name, each a compound command.
+    functions: BTreeMap<String, Rc<Command>>,
+    /// Where programs run so far were found along `$PATH`.
+    hashed: HashTable,
+    /// How many function calls are running, for `return`.
+    function_depth: usize,
+    /// Set by `return` to abandon the rest of the function body.
+    returning: bool,
+    /// Set while a trap action runs, so that it cannot trigger further traps.
+    in_trap: bool,
+    /// Set by an untrapped Ctrl-C to abandon the rest of the current input.
+    interrupted: bool,
+    /// How long the last interactive command line took, for the prompt.
+    last_duration: Option<Duration>,
+    /// The peak memory in kilobytes of processes reaped while a `time`
+    /// pipeline runs.
+    timed_max_rss: Option<i64>,
+    /// The seccomp profile applied to every command the shell starts.
+    seccomp: Option<Arc<Profile>>,
+    /// Where every command run is recorded, if anywhere.
+    audit: Option<AuditLog>,
+    /// Where `fifo` makes named pipes, once it has made one.
+    fifo_dir: Option<FifoDir>,
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Shell::new()
+    }
+}
+
+impl Shell {
+    /// A shell that inherits the process's environment, working directory
+    /// and file descriptors.
+    pub fn new() -> Self {
+        Shell::with_variables(Variables::from_env())
+    }
+
+    /// A shell whose variables are exactly `vars`, all exported, instead of
+    /// the process's environment.
+    pub fn with_env(vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        Shell::with_variables(Variables::from_pairs(vars))
+    }
+
+    fn with_variables(env_vars: Variables) -> Self {
+        let mut shell = Shell {
+            current_dir: dirstack::initial_dir(),
+            dir_stack: Vec::new(),
+            dir_envs: Vec::new(),
+            env_vars,
+            running: true,
+            jobs: Jobs::default(),
+            last_status: 0,
+            last_background_pid: None,
+            positional: Vec::new(),
+            script_name: env::args().next().unwrap_or_else(|| "vssh".to_string()),
+            fds: Fds::inherit(),
+            options: Options::default(),
+            errexit_exempt: 0,
+            interactive: false,
+            traps: BTreeMap::new(),
+            functions: BTreeMap::new(),
+            hashed: HashTable::default(),
+            function_depth: 0,
+            returning: false,
+            in_trap: false,
+            interrupted: false,
+            last_duration: None,
+            timed_max_rss: None,
+            seccomp: None,
+            audit: None,
+            fifo_dir: None,
+        };
+        shell.update_pwd();
+        shell
+    }
+
+    /// Makes `file` the shell's file descriptor `fd`, for builtins and for
+    /// every command it starts.
+    pub fn set_fd(&mut self, fd: i32, file: impl Into<OwnedFd>) {
+        self.fds.set(fd, file.into());
+    }
+
+    /// Changes the working directory, as `cd -P` would but without touching
+    /// `OLDPWD`.
+    pub fn set_current_dir(&mut self, dir: impl AsRef<Path>) -> Result<(), String> {
+        self.enter_directory(dir.as_ref(), true)
+    }
+
+    /// Sets `$0` and the positional parameters.
+    pub fn set_args(&mut self, name: &str, args: &[String]) {
+        self.script_name = name.to_string();
+        self.positional = args.to_vec();
+    }
+
+    /// Turns an option on or off by its `set -o` name.
+    pub fn set_option(&mut self, name: &str, on: bool) -> Result<(), String> {
+        self.options.set(name, on, false)
+    }
+
+    /// Turns an option on or off by its `set` letter.
+    pub fn set_option_letter(&mut self, letter: char, on: bool) -> Result<(), String> {
+        self.options.set_letter(letter, on)
+    }
+
+    /// Applies the seccomp profile in `path` to every command the shell starts.
+    pub fn set_seccomp_profile(&mut self, path: &Path) -> Result<(), String> {
+        self.seccomp = Some(Arc::new(Profile::load(path)?));
+        Ok(())
+    }
+
+    /// Marks the shell interactive, which enables prompts, job control
+    /// messages and the startup of [`Shell::run`].
+    pub fn set_interactive(&mut self, interactive: bool) {
+        self.interactive = interactive;
+    }
+
+    /// The value of a scalar variable, or the first element of an array.
+    pub fn var(&self, name: &str) -> Option<&str> {
+        self.env_vars.scalar(name)
+    }
+
+    /// Sets a scalar variable, exporting it if `export` is set.
+    pub fn set_var(&mut self, name: &str, value: &str, export: bool) {
+        self.env_vars.set_scalar(name, value.to_string());
+        if export {
+            self.env_vars.set_exported(name, true);
+        }
+    }
+
+    /// The exit status of the last command, `$?`.
+    pub fn last_status(&self) -> i32 {
+        self.last_status
+    }
+
+    /// Whether the shell is still running, that is `exit` has not been run.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Reads and runs commands from standard input until `exit` or end of
+    /// input, prompting if the shell is interactive. An interactive shell on
+    /// a terminal edits lines with highlighting and history.
+    pub fn run(&mut self) {
+        signals::install_interrupt_handler();
+        // The directory the shell starts in counts as entered.
+        self.sync_dir_envs();
+        let mut editor = None;
+        if self.interactive && nix::unistd::isatty(0).unwrap_or(false) {
+            match LineEditor::new(self) {
+                Ok(e) => editor = Some(e),
+                Err(e) => self.report_error(format_args!("vssh: line editing unavailable: {e}")),
+            }
+        }
+
+        while self.running {
+            self.notify_jobs();
+            let mut prompt = self.prompt("PS1", "");
+
+            // Keep reading while the input so far ends inside a quote, an
+            // unfinished compound command or after a trailing backslash.
+            let mut input = String::new();
+            let eof = loop {
+                match self.read_line(editor.as_mut(), &prompt) {
+                    Line::Text(line) => input.push_str(&line),
+                    Line::Eof => break true,
+                    Line::Interrupted => {
+                        if !signals::interrupt_from_editor() {
+                            println!("Type 'exit' to quit.");
+                        }
+                        input.clear();
+                        break false;
+                    }
+                    Line::Failed => {
+                        eprintln!("Failed to read line");
+                        input.clear();
+                        break false;
+                    }
+                }
+                if !parser::is_incomplete(&input) {
+                    break false;
+                }
+                prompt = self.prompt("PS2", "> ");
+            };
+            if eof && input.trim().is_empty() {
+                break;
+            }
+            // A Ctrl-C pressed at the prompt must not cancel the next command.
+            self.handle_signals();
+            self.interrupted = false;
+
+            if input.trim().is_empty() {
+                continue;
+            }
+            if let Some(editor) = editor.as_mut() {
+                editor.remember(input.trim());
+            }
+
+            // Keep the final newline, which ends a continuation cut short by
+            // the end of input.
+            let started = Instant::now();
+            self.execute_command(&input);
+            self.last_duration = Some(started.elapsed());
+        }
+    }
+
+    /// Reads one line, newline included, through the editor if there is one.
+    fn read_line(&mut self, editor: Option<&mut LineEditor>, prompt: &str) -> Line {
+        if let Some(editor) = editor {
+            return match editor.read_line(self, prompt) {
+                Line::Text(line) => Line::Text(line + "\n"),
+                other => other,
+            };
+        }
+        if self.interactive {
+            print!("{prompt}");
+            stdio::stdout().flush().unwrap();
+        }
+        let mut line = String::new();
+        match stdio::stdin().read_line(&mut line) {
+            Ok(0) => Line::Eof,
+            Ok(_) => Line::Text(line),
+            Err(_) => Line::Failed,
+        }
+    }
+
+    /// Runs a script file, as `vssh script.sh args...` does, and returns its
+    /// exit status.
+    pub fn run_script(&mut self, path: &str) -> i32 {
+        match std::fs::read_to_string(path) {
+            Ok(script) => self.execute_command(&script),
+            Err(e) => {
+                self.report_error(format_args!("vssh: {path}: {e}"));
+                self.last_status = 127;
+                self.last_status
+            }
+        }
+    }
+
+    /// Parses and runs `command`, which may span several lines, and returns
+    /// its exit status. A syntax error runs nothing and returns 2.
+    pub fn execute_command(&mut self, command: &str) -> i32 {
+        match parser::parse(command) {
+            Ok(list) => self.run_list(&list),
+            Err(e) => {
+                self.report_error(format_args!("vssh: {e}"));
+                self.last_status = 2;
+            }
+        }
+        self.last_status
+    }
+
+    /// Runs an already parsed command list and returns its exit status.
+    pub fn execute(&mut self, list: &List) -> i32 {
+        self.run_list(list);
+        self.last_status
+    }
+
+    /// Writes a diagnostic to the shell's current stderr.
+    fn report_error(&self, message: impl Display) {
+        let _ = writeln!(self.fds.writer(2), "{message}");
+    }
+
+    fn change_directory(&mut self, dir: Option<&str>, physical: bool) -> i32 {
+        let (new_dir, show) = match dir {
+            None | Some("~") => match self.env_vars.scalar("HOME") {
+                Some(home) => (PathBuf::from(home), false),
+                None => (dirs::home_dir().unwrap_or_else(|| PathBuf::from("/")), false),
+            },
+            Some("-") => match self.env_vars.scalar("OLDPWD") {
+                Some(prev) => (PathBuf::from(prev), true),
+                None => {
+                    self.report_error("No previous directory");
+                    return 1;
+                }
+            },
+            Some(path) => self.search_cdpath(path),
+        };
+
+        match self.enter_directory(&new_dir, physical) {
+            Ok(()) => {
+                if show {
+                    let _ = writeln!(self.fds.writer(1), "{}", self.current_dir.display());
+                }
+                0
+            }
+            Err(e) => {
+                self.report_error(e);
+                1
+            }
+        }
+    }
+}
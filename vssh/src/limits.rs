@@ -0,0 +1,235 @@
+//! Resource limits: the `ulimit` builtin and the `limit` command prefix.
+
+use std::io::Write;
+
+use nix::sys::resource::{getrlimit, setrlimit, Resource, RLIM_INFINITY};
+
+use crate::Shell;
+
+/// A limit `ulimit` can show or change.
+struct Limit {
+    option: char,
+    resource: Resource,
+    description: &'static str,
+    unit: &'static str,
+    /// Bytes per unit, for limits counted in kilobytes or blocks.
+    scale: u64,
+}
+
+const LIMITS: &[Limit] = &[
+    Limit { option: 'c', resource: Resource::RLIMIT_CORE, description: "core file size", unit: "blocks", scale: 1024 },
+    Limit { option: 'd', resource: Resource::RLIMIT_DATA, description: "data seg size", unit: "kbytes", scale: 1024 },
+    Limit { option: 'f', resource: Resource::RLIMIT_FSIZE, description: "file size", unit: "blocks", scale: 1024 },
+    Limit { option: 'n', resource: Resource::RLIMIT_NOFILE, description: "open files", unit: "", scale: 1 },
+    Limit { option: 's', resource: Resource::RLIMIT_STACK, description: "stack size", unit: "kbytes", scale: 1024 },
+    Limit { option: 't', resource: Resource::RLIMIT_CPU, description: "cpu time", unit: "seconds", scale: 1 },
+    Limit { option: 'u', resource: Resource::RLIMIT_NPROC, description: "max user processes", unit: "", scale: 1 },
+    Limit { option: 'v', resource: Resource::RLIMIT_AS, description: "virtual memory", unit: "kbytes", scale: 1024 },
+];
+
+fn find_limit(option: char) -> Option<&'static Limit> {
+    LIMITS.iter().find(|limit| limit.option == option)
+}
+
+/// Which of the two limits an operation applies to.
+#[derive(Clone, Copy, PartialEq)]
+enum Which {
+    Soft,
+    Hard,
+    Both,
+}
+
+/// A new value for a limit, before it is resolved against the current ones.
+#[derive(Clone, Copy)]
+enum Value {
+    Unlimited,
+    CurrentSoft,
+    CurrentHard,
+    Units(u64),
+}
+
+impl Value {
+    fn parse(text: &str) -> Option<Value> {
+        match text {
+            "unlimited" => Some(Value::Unlimited),
+            "soft" => Some(Value::CurrentSoft),
+            "hard" => Some(Value::CurrentHard),
+            _ => text.parse().ok().map(Value::Units),
+        }
+    }
+}
+
+impl Limit {
+    fn format(&self, value: u64) -> String {
+        if value == RLIM_INFINITY {
+            "unlimited".to_string()
+        } else {
+            (value / self.scale).to_string()
+        }
+    }
+
+    /// Sets the soft and/or hard limit, keeping the other one as it is.
+    fn apply(&self, value: Value, which: Which) -> Result<(), String> {
+        let (soft, hard) = getrlimit(self.resource).map_err(|e| format!("{}: cannot get limit: {}", self.description, e.desc()))?;
+        let new = match value {
+            Value::Unlimited => RLIM_INFINITY,
+            Value::CurrentSoft => soft,
+            Value::CurrentHard => hard,
+            Value::Units(units) => units.checked_mul(self.scale).ok_or(format!("{units}: limit out of range"))?,
+        };
+        let (soft, hard) = match which {
+            Which::Soft => (new, hard),
+            Which::Hard => (soft.min(new), new),
+            Which::Both => (new, new),
+        };
+        setrlimit(self.resource, soft, hard)
+            .map_err(|e| format!("{}: cannot modify limit: {}", self.description, e.desc()))
+    }
+}
+
+impl Shell {
+    /// `ulimit [-HSa] [-cdfnstuv [limit]]`
+    pub(crate) fn builtin_ulimit(&mut self, argv: &[String]) -> i32 {
+        let mut which = Which::Both;
+        let mut all = false;
+        let mut requests: Vec<(&'static Limit, Option<Value>)> = Vec::new();
+        let mut i = 1;
+        while let Some(arg) = argv.get(i) {
+            i += 1;
+            if arg == "--" {
+                break;
+            }
+            let Some(flags) = arg.strip_prefix('-').filter(|f| !f.is_empty()) else {
+                // A bare value sets the default limit, the file size.
+                i -= 1;
+                break;
+            };
+            for flag in flags.chars() {
+                match flag {
+                    'H' => which = Which::Hard,
+                    'S' => which = Which::Soft,
+                    'a' => all = true,
+                    _ => match find_limit(flag) {
+                        Some(limit) => requests.push((limit, None)),
+                        None => {
+                            self.report_error(format_args!("ulimit: -{flag}: invalid option"));
+                            return 2;
+                        }
+                    },
+                }
+            }
+            // The value for the last limit named in the cluster, if any.
+            if let Some(value) = argv.get(i).filter(|v| !v.starts_with('-'))
+                && let Some(last) = requests.last_mut()
+            {
+                let Some(parsed) = Value::parse(value) else {
+                    self.report_error(format_args!("ulimit: {value}: invalid number"));
+                    return 1;
+                };
+                last.1 = Some(parsed);
+                i += 1;
+            }
+        }
+        if let Some(value) = argv.get(i) {
+            let Some(parsed) = Value::parse(value) else {
+                self.report_error(format_args!("ulimit: {value}: invalid number"));
+                return 1;
+            };
+            match requests.last_mut() {
+                Some(last) if last.1.is_none() => last.1 = Some(parsed),
+                Some(_) => {
+                    self.report_error(format_args!("ulimit: {value}: too many arguments"));
+                    return 2;
+                }
+                None => requests.push((find_limit('f').unwrap(), Some(parsed))),
+            }
+        }
+        if all {
+            requests = LIMITS.iter().map(|limit| (limit, None)).collect();
+        } else if requests.is_empty() {
+            requests.push((find_limit('f').unwrap(), None));
+        }
+
+        let long = requests.len() > 1;
+        let mut status = 0;
+        for (limit, value) in requests {
+            let result = match value {
+                Some(value) => limit.apply(value, which),
+                None => self.print_limit(limit, which == Which::Hard, long),
+            };
+            if let Err(e) = result {
+                self.report_error(format_args!("ulimit: {e}"));
+                status = 1;
+            }
+        }
+        status
+    }
+
+    fn print_limit(&mut self, limit: &Limit, hard: bool, long: bool) -> Result<(), String> {
+        let (soft, hard_value) =
+            getrlimit(limit.resource).map_err(|e| format!("{}: cannot get limit: {}", limit.description, e.desc()))?;
+        let value = limit.format(if hard { hard_value } else { soft });
+        let mut out = self.fds.writer(1);
+        if long {
+            let unit = if limit.unit.is_empty() {
+                format!("(-{})", limit.option)
+            } else {
+                format!("({}, -{})", limit.unit, limit.option)
+            };
+            let _ = writeln!(out, "{:<20} {:>18} {value}", limit.description, unit);
+        } else {
+            let _ = writeln!(out, "{value}");
+        }
+        Ok(())
+    }
+
+    /// `limit [-cdfnstuv limit]... [--] command [arg...]` runs one command
+    /// under tighter limits, in a subshell so that the shell keeps its own.
+    pub(crate) fn builtin_limit(&mut self, argv: &[String]) -> i32 {
+        let mut limits = Vec::new();
+        let mut i = 1;
+        while let Some(arg) = argv.get(i) {
+            if arg == "--" {
+                i += 1;
+                break;
+            }
+            let Some(option) = arg.strip_prefix('-').filter(|o| o.chars().count() == 1) else {
+                break;
+            };
+            let flag = option.chars().next().unwrap_or_default();
+            let Some(limit) = find_limit(flag) else {
+                self.report_error(format_args!("limit: -{flag}: invalid option"));
+                return 2;
+            };
+            let Some(value) = argv.get(i + 1) else {
+                self.report_error(format_args!("limit: -{flag}: option requires an argument"));
+                return 2;
+            };
+            let Some(value) = Value::parse(value) else {
+                self.report_error(format_args!("limit: {value}: invalid number"));
+                return 2;
+            };
+            limits.push((limit, value));
+            i += 2;
+        }
+        let command = argv[i..].to_vec();
+        if command.is_empty() {
+            self.report_error("limit: usage: limit [-cdfnstuv limit]... command [arg...]");
+            return 2;
+        }
+
+        let child = self.fork_subshell(None, move |shell| {
+            for (limit, value) in limits {
+                if let Err(e) = limit.apply(value, Which::Both) {
+                    shell.report_error(format_args!("limit: {e}"));
+                    return 1;
+                }
+            }
+            shell.run_argv(&command)
+        });
+        match child {
+            Ok(pid) => self.wait_for(pid),
+            Err(status) => status,
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! What happens when a command name finds nothing to run.
+//!
+//! If a function named `command_not_found_handle` is defined, it runs in a
+//! subshell with the command and its arguments as its arguments, and its
+//! status becomes the command's, as in bash. Otherwise the shell reports the
+//! name and suggests the builtins, functions and programs on `$PATH` that are
+//! closest to it by edit distance. With `shopt -s correct`, an interactive
+//! shell first offers to run the single closest name instead.
+
+use std::collections::BTreeSet;
+use std::io::Write;
+
+use crate::builtins::BUILTINS;
+use crate::exec::{is_executable, DEFAULT_PATH};
+use crate::Shell;
+
+const HANDLER: &str = "command_not_found_handle";
+
+/// At most this many suggestions are shown.
+const MAX_SUGGESTIONS: usize = 5;
+
+impl Shell {
+    /// Runs the not-found handler for `argv`, or reports the missing command
+    /// with suggestions, and returns the status of the command.
+    pub(crate) fn command_not_found(&mut self, argv: &[String]) -> i32 {
+        if let Some(handler) = self.functions.get(HANDLER).cloned() {
+            let args: Vec<String> = std::iter::once(HANDLER.to_string()).chain(argv.iter().cloned()).collect();
+            let status = match self.fork_subshell(None, |shell| {
+                // A command missing inside the handler gets the plain report.
+                shell.functions.remove(HANDLER);
+                shell.call_function(&handler, &args)
+            }) {
+                Ok(pid) => self.wait_for(pid),
+                Err(status) => status,
+            };
+            self.audit_unstarted(argv, status);
+            return status;
+        }
+
+        self.report_error(format_args!("vssh: {}: command not found", argv[0]));
+        let suggestions = self.suggestions(&argv[0]);
+        if !suggestions.is_empty() {
+            self.report_error(format_args!("vssh: did you mean: {}", suggestions.join(", ")));
+        }
+        self.audit_unstarted(argv, 127);
+        127
+    }
+
+    /// With `shopt -s correct`, asks on the terminal whether to run the
+    /// closest match to `name` instead, and returns it if the answer is yes.
+    pub(crate) fn offer_correction(&mut self, name: &str) -> Option<String> {
+        if !self.interactive || !self.options.get("correct") || self.functions.contains_key(HANDLER) {
+            return None;
+        }
+        let input = self.fds.get(0)?;
+        if !nix::unistd::isatty(input).unwrap_or(false) {
+            return None;
+        }
+        let [correction] = <[String; 1]>::try_from(self.suggestions(name)).ok()?;
+        let _ = write!(self.fds.writer(2), "vssh: correct '{name}' to '{correction}' [y/N]? ");
+
+        let mut answer = Vec::new();
+        let mut byte = [0u8; 1];
+        loop {
+            match nix::unistd::read(input, &mut byte) {
+                Ok(1) if byte[0] != b'\n' => answer.push(byte[0]),
+                Err(nix::errno::Errno::EINTR) => continue,
+                _ => break,
+            }
+        }
+        let answer = String::from_utf8_lossy(&answer);
+        matches!(answer.trim(), "y" | "Y" | "yes").then_some(correction)
+    }
+
+    /// The builtins, functions and programs closest to `name`, best first.
+    fn suggestions(&self, name: &str) -> Vec<String> {
+        let Some(limit) = max_distance(name) else {
+            return Vec::new();
+        };
+        let mut matches: Vec<(usize, String)> = self
+            .command_names()
+            .into_iter()
+            .filter_map(|candidate| {
+                let distance = edit_distance(name, &candidate);
+                (distance <= limit).then_some((distance, candidate))
+            })
+            .collect();
+        matches.sort();
+        matches.into_iter().take(MAX_SUGGESTIONS).map(|(_, name)| name).collect()
+    }
+
+    /// Every name that could be run as a command.
+    fn command_names(&self) -> BTreeSet<String> {
+        let mut names: BTreeSet<String> = BUILTINS.iter().map(|name| name.to_string()).collect();
+        names.extend(self.functions.keys().cloned());
+        let path_var = self.env_vars.scalar("PATH").unwrap_or(DEFAULT_PATH);
+        for dir in path_var.split(':') {
+            let dir = if dir.is_empty() { self.current_dir.clone() } else { self.current_dir.join(dir) };
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if is_executable(&path) {
+                    names.insert(entry.file_name().to_string_lossy().into_owned());
+                }
+            }
+        }
+        names
+    }
+}
+
+/// How far a suggestion for `name` may be from it, if anything is to be
+/// suggested at all: a name of one or two characters is close to too many
+/// others.
+fn max_distance(name: &str) -> Option<usize> {
+    match name.chars().count() {
+        0..=2 => None,
+        3..=5 => Some(1),
+        _ => Some(2),
+    }
+}
+
+/// The number of insertions, deletions, substitutions and swaps of adjacent
+/// characters that turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // rows[i][j] is the distance between a[..i] and b[..j].
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distance_counts_each_kind_of_edit() {
+        let cases = [
+            ("git", "git", 0),
+            ("gti", "git", 1),
+            ("gt", "git", 1),
+            ("gitt", "git", 1),
+            ("gat", "git", 1),
+            ("", "ls", 2),
+            ("sl", "ls", 1),
+            ("pyhton3", "python3", 1),
+            ("grpe", "grep", 1),
+            ("kitten", "sitting", 3),
+            ("é", "e", 1),
+        ];
+        for (a, b, distance) in cases {
+            assert_eq!(edit_distance(a, b), distance, "{a} -> {b}");
+            assert_eq!(edit_distance(b, a), distance, "{b} -> {a}");
+        }
+    }
+
+    #[test]
+    fn longer_names_allow_more_edits() {
+        let cases = [("", None), ("sl", None), ("gti", Some(1)), ("grpep", Some(1)), ("pyhton", Some(2)), ("docker-compse", Some(2))];
+        for (name, limit) in cases {
+            assert_eq!(max_distance(name), limit, "{name}");
+        }
+    }
+}
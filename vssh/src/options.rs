@@ -0,0 +1,108 @@
+//! The registry behind `set -o` and `shopt`.
+
+use std::collections::BTreeSet;
+
+pub struct OptionSpec {
+    pub name: &'static str,
+    /// The single-letter form accepted by `set`, e.g. `e` for `set -e`.
+    pub letter: Option<char>,
+    /// Options managed by `shopt` rather than `set -o`.
+    pub shopt: bool,
+}
+
+const OPTIONS: &[OptionSpec] = &[
+    OptionSpec {
+        name: "autocd",
+        letter: None,
+        shopt: true,
+    },
+    OptionSpec {
+        name: "correct",
+        letter: None,
+        shopt: true,
+    },
+    OptionSpec {
+        name: "errexit",
+        letter: Some('e'),
+        shopt: false,
+    },
+    OptionSpec {
+        name: "noclobber",
+        letter: Some('C'),
+        shopt: false,
+    },
+    OptionSpec {
+        name: "nounset",
+        letter: Some('u'),
+        shopt: false,
+    },
+    OptionSpec {
+        name: "physical",
+        letter: Some('P'),
+        shopt: false,
+    },
+    OptionSpec {
+        name: "pipefail",
+        letter: None,
+        shopt: false,
+    },
+    OptionSpec {
+        name: "restricted",
+        letter: Some('r'),
+        shopt: false,
+    },
+    OptionSpec {
+        name: "xtrace",
+        letter: Some('x'),
+        shopt: false,
+    },
+];
+
+#[derive(Debug, Default)]
+pub struct Options {
+    enabled: BTreeSet<&'static str>,
+}
+
+impl Options {
+    pub fn get(&self, name: &str) -> bool {
+        self.enabled.contains(name)
+    }
+
+    /// Turns an option on or off by name; `shopt` selects which namespace to search.
+    pub fn set(&mut self, name: &str, on: bool, shopt: bool) -> Result<(), String> {
+        let spec = OPTIONS
+            .iter()
+            .find(|spec| spec.name == name && spec.shopt == shopt)
+            .ok_or_else(|| format!("{name}: invalid option name"))?;
+        if spec.name == "restricted" && !on && self.get("restricted") {
+            return Err("restricted: cannot turn off restricted mode".to_string());
+        }
+        if on {
+            self.enabled.insert(spec.name);
+        } else {
+            self.enabled.remove(spec.name);
+        }
+        Ok(())
+    }
+
+    pub fn set_letter(&mut self, letter: char, on: bool) -> Result<(), String> {
+        let spec = OPTIONS
+            .iter()
+            .find(|spec| spec.letter == Some(letter))
+            .ok_or_else(|| format!("-{letter}: invalid option"))?;
+        self.set(spec.name, on, spec.shopt)
+    }
+
+    /// The letters of the enabled options, as `$-` shows them.
+    pub fn flags(&self) -> String {
+        OPTIONS
+            .iter()
+            .filter(|spec| self.get(spec.name))
+            .filter_map(|spec| spec.letter)
+            .collect()
+    }
+
+    pub fn specs(shopt: bool) -> impl Iterator<Item = &'static OptionSpec> {
+        OPTIONS.iter().filter(move |spec| spec.shopt == shopt)
+    }
+}
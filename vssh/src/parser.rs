@@ -0,0 +1,1472 @@
+//! Turns command lines into a small syntax tree.
+//!
+//! Words keep their quoting as [`WordPart`]s so that expansion can tell which
+//! characters came from quotes and must never be split.
+
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// The input ended inside a construct that is still open.
+    Incomplete,
+    Syntax(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Incomplete => write!(f, "syntax error: unexpected end of file"),
+            ParseError::Syntax(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Word {
+    /// The word as written, quotes included.
+    pub raw: String,
+    pub parts: Vec<WordPart>,
+    /// Set on `name=value` operands of declaration builtins such as `declare`,
+    /// which take assignments rather than plain words.
+    pub assignment: Option<Box<Assignment>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WordPart {
+    /// Literal text; quoted literals are never split or removed.
+    Lit { text: String, quoted: bool },
+    Param { param: Param, quoted: bool },
+}
+
+/// A parameter expansion such as `$x`, `${arr[@]}`, `${#x}` or `${!arr[@]}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: String,
+    /// The subscript as written, expanded only when the parameter is.
+    pub index: Option<String>,
+    pub op: ParamOp,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamOp {
+    Value,
+    Length,
+    Keys,
+    /// `${x-w}`, `${x:=w}`, `${x:+w}`, `${x:?w}` and friends. With `colon`
+    /// set, an empty value is treated like an unset one.
+    Test { colon: bool, kind: TestKind, word: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TestKind {
+    /// `-`: substitute the word.
+    Default,
+    /// `=`: substitute the word and assign it.
+    Assign,
+    /// `+`: substitute the word only if the parameter is set.
+    Alternate,
+    /// `?`: fail with the word as the message.
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub name: String,
+    pub index: Option<String>,
+    pub append: bool,
+    pub value: AssignValue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssignValue {
+    Scalar(Word),
+    /// `name=(a b [k]=v)`; each element may carry an explicit subscript.
+    Array(Vec<(Option<String>, Word)>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RedirOp {
+    /// `<`
+    In,
+    /// `>`
+    Out,
+    /// `>>`
+    Append,
+    /// `>|`
+    Clobber,
+    /// `<>`
+    ReadWrite,
+    /// `<&`
+    DupIn,
+    /// `>&`
+    DupOut,
+    /// `&>`
+    OutErr,
+    /// `&>>`
+    AppendOutErr,
+}
+
+impl RedirOp {
+    /// The operator as written.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RedirOp::In => "<",
+            RedirOp::Out => ">",
+            RedirOp::Append => ">>",
+            RedirOp::Clobber => ">|",
+            RedirOp::ReadWrite => "<>",
+            RedirOp::DupIn => "<&",
+            RedirOp::DupOut => ">&",
+            RedirOp::OutErr => "&>",
+            RedirOp::AppendOutErr => "&>>",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redirect {
+    pub fd: Option<i32>,
+    /// The `name` of `{name}>file`, which opens the file on a free
+    /// descriptor and stores its number in `$name`.
+    pub fd_var: Option<String>,
+    pub op: RedirOp,
+    pub target: Word,
+}
+
+impl Redirect {
+    /// The descriptor this redirection replaces when none is written.
+    pub fn default_fd(&self) -> i32 {
+        self.fd.unwrap_or(match self.op {
+            RedirOp::In | RedirOp::ReadWrite | RedirOp::DupIn => 0,
+            _ => 1,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimpleCommand {
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+}
+
+impl SimpleCommand {
+    fn is_empty(&self) -> bool {
+        self.assignments.is_empty() && self.words.is_empty() && self.redirects.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Simple(SimpleCommand),
+    Compound(CompoundCommand, Vec<Redirect>),
+    /// `name() body` or `function name body`, where the body is a compound
+    /// command with its redirections.
+    Function { name: String, body: Rc<Command> },
+    /// `coproc [NAME] command`, which runs the command in the background
+    /// with pipes to and from the shell. A name is only taken before a
+    /// compound command; otherwise it is `COPROC`.
+    Coproc { name: String, body: Box<Command> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompoundCommand {
+    /// `{ list; }`
+    Group(List),
+    /// `if c; then b; elif c; then b; else b; fi`
+    If {
+        branches: Vec<(List, List)>,
+        otherwise: Option<List>,
+    },
+    /// `while c; do b; done`, or `until` when `until` is set.
+    While { condition: List, body: List, until: bool },
+    /// `[[ expression ]]`, kept as words; operators such as `&&` and `(`
+    /// appear as unquoted words of their own.
+    Cond(Vec<Word>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    /// Set by a leading `!`, which inverts the pipeline's status.
+    pub negated: bool,
+    /// Set by a leading `time`, which reports how long the pipeline took.
+    pub time: Option<TimeFormat>,
+    /// Empty only for a bare `time`.
+    pub commands: Vec<Command>,
+}
+
+/// How `time` reports: through `$TIMEFORMAT`, or in the POSIX format for `time -p`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeFormat {
+    Default,
+    Posix,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Connector {
+    And,
+    Or,
+}
+
+/// Pipelines joined by `&&` and `||`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+    /// The source text, for job listings.
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListItem {
+    pub and_or: AndOr,
+    pub background: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct List {
+    pub items: Vec<ListItem>,
+}
+
+/// Parses a complete command line (or script) into a list.
+pub fn parse(input: &str) -> Result<List, ParseError> {
+    let mut parser = Parser::new(input);
+    let list = parser.parse_list(&[])?;
+    if parser.peek().is_some() {
+        return Err(parser.unexpected());
+    }
+    Ok(list)
+}
+
+/// Whether `input` stops inside a quote, an unfinished compound command or
+/// a line continuation, so that a reader should ask for another line.
+pub fn is_incomplete(input: &str) -> bool {
+    let mut parser = Parser::new(input);
+    match parser.parse_list(&[]) {
+        Err(ParseError::Incomplete) => true,
+        Err(_) => false,
+        Ok(_) => parser.peek().is_none() && parser.continued,
+    }
+}
+
+/// What a stretch of a command line is, for syntax highlighting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpanKind {
+    Keyword,
+    /// A word in command position.
+    Command,
+    /// The `name=` of an assignment.
+    Assignment,
+    Quoted,
+    Variable,
+    /// A redirection operator and its target.
+    Redirect,
+    /// `|`, `&&`, `;` and the like.
+    Operator,
+    Comment,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub kind: SpanKind,
+    /// Byte offsets into the line.
+    pub range: Range<usize>,
+}
+
+/// Classifies the parts of a command line, which may be incomplete or
+/// invalid, for syntax highlighting. A span may lie inside an earlier one,
+/// such as a variable inside a quoted string, and then takes precedence.
+pub fn spans(input: &str) -> Vec<Span> {
+    let offsets: Vec<usize> = input.char_indices().map(|(i, _)| i).chain([input.len()]).collect();
+    Parser::new(input)
+        .scan_spans()
+        .into_iter()
+        .map(|(kind, start, end)| Span {
+            kind,
+            range: offsets[start]..offsets[end],
+        })
+        .collect()
+}
+
+/// Words that are only special at the start of a command.
+pub const RESERVED_WORDS: &[&str] = &[
+    "!", "time", "[[", "]]", "{", "}", "function", "coproc", "if", "then", "elif", "else", "fi", "while", "until", "do", "done",
+];
+
+/// Splits a single word into its quoted and unquoted parts.
+pub fn parse_word(raw: &str) -> Result<Word, ParseError> {
+    let chars: Vec<char> = raw.chars().collect();
+    let mut parts = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => {
+                i += 1;
+                match chars.get(i) {
+                    Some('\n') => {}
+                    Some(&c) => push_lit(&mut parts, c.to_string(), true),
+                    None => push_lit(&mut parts, "\\".to_string(), false),
+                }
+                i += 1;
+            }
+            '\'' => {
+                let end = find_char(&chars, i + 1, '\'').ok_or(ParseError::Incomplete)?;
+                let text: String = chars[i + 1..end].iter().collect();
+                push_lit(&mut parts, text, true);
+                i = end + 1;
+            }
+            '"' => {
+                i = parse_double_quoted(&chars, i + 1, &mut parts)?;
+            }
+            '$' if chars.get(i + 1) == Some(&'\'') => {
+                let end = find_ansi_c_end(&chars, i + 2).ok_or(ParseError::Incomplete)?;
+                let text: String = chars[i + 2..end].iter().collect();
+                push_lit(&mut parts, decode_ansi_c(&text), true);
+                i = end + 1;
+            }
+            // There are no message catalogs to translate `$"..."` with, so it
+            // is an ordinary double-quoted string.
+            '$' if chars.get(i + 1) == Some(&'"') => {
+                i = parse_double_quoted(&chars, i + 2, &mut parts)?;
+            }
+            '$' => {
+                i = parse_dollar(&chars, i, false, &mut parts)?;
+            }
+            c => {
+                push_lit(&mut parts, c.to_string(), false);
+                i += 1;
+            }
+        }
+    }
+    Ok(Word {
+        raw: raw.to_string(),
+        parts,
+        assignment: None,
+    })
+}
+
+/// Builtins whose `name=value` operands are parsed as assignments.
+pub const DECLARATION_BUILTINS: &[&str] = &["declare", "export", "typeset"];
+
+fn push_lit(parts: &mut Vec<WordPart>, text: String, quoted: bool) {
+    if let Some(WordPart::Lit { text: last, quoted: q }) = parts.last_mut()
+        && *q == quoted
+    {
+        last.push_str(&text);
+        return;
+    }
+    parts.push(WordPart::Lit { text, quoted });
+}
+
+fn find_char(chars: &[char], from: usize, target: char) -> Option<usize> {
+    (from..chars.len()).find(|&j| chars[j] == target)
+}
+
+/// Finds the `'` closing a `$'` whose contents start at `from`. Unlike in
+/// plain single quotes, `\'` does not close it.
+fn find_ansi_c_end(chars: &[char], mut from: usize) -> Option<usize> {
+    while from < chars.len() {
+        match chars[from] {
+            '\'' => return Some(from),
+            '\\' => from += 2,
+            _ => from += 1,
+        }
+    }
+    None
+}
+
+/// Decodes the escapes of a `$'...'` string: those of printf's format, plus
+/// `\cX` for the control character Ctrl-X and `\?` for a question mark.
+fn decode_ansi_c(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(pos) = rest.find('\\') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        let mut after = rest[1..].chars();
+        match (after.next(), after.next()) {
+            (Some('c'), Some(c)) => {
+                out.push(char::from(c.to_ascii_uppercase() as u8 ^ 0x40));
+                rest = &rest[2 + c.len_utf8()..];
+            }
+            (Some('?'), _) => {
+                out.push('?');
+                rest = &rest[2..];
+            }
+            _ => {
+                let (decoded, len) = crate::printf::escape_at(rest, false);
+                out.push_str(&decoded);
+                rest = &rest[len..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Parses the inside of `"..."` starting just after the opening quote and
+/// returns the index just past the closing one.
+fn parse_double_quoted(chars: &[char], mut i: usize, parts: &mut Vec<WordPart>) -> Result<usize, ParseError> {
+    let start = i;
+    while i < chars.len() {
+        match chars[i] {
+            '"' => {
+                // An empty pair of quotes still produces a (quoted, empty) word.
+                if i == start {
+                    push_lit(parts, String::new(), true);
+                }
+                return Ok(i + 1);
+            }
+            '\\' => {
+                match chars.get(i + 1) {
+                    Some('\n') => {}
+                    Some(&c @ ('$' | '`' | '"' | '\\')) => push_lit(parts, c.to_string(), true),
+                    Some(&c) => push_lit(parts, format!("\\{c}"), true),
+                    None => return Err(ParseError::Incomplete),
+                }
+                i += 2;
+            }
+            '$' => i = parse_dollar(chars, i, true, parts)?,
+            c => {
+                push_lit(parts, c.to_string(), true);
+                i += 1;
+            }
+        }
+    }
+    Err(ParseError::Incomplete)
+}
+
+/// Parses an expansion starting at the `$` at `chars[i]`.
+fn parse_dollar(chars: &[char], i: usize, quoted: bool, parts: &mut Vec<WordPart>) -> Result<usize, ParseError> {
+    match chars.get(i + 1) {
+        Some('{') => {
+            let end = find_closing_brace(chars, i + 2).ok_or(ParseError::Incomplete)?;
+            let inner: String = chars[i + 2..end].iter().collect();
+            let param = parse_braced(&inner)?;
+            parts.push(WordPart::Param { param, quoted });
+            Ok(end + 1)
+        }
+        Some(&c) if c.is_ascii_alphabetic() || c == '_' => {
+            let mut j = i + 1;
+            while j < chars.len() && (chars[j].is_ascii_alphanumeric() || chars[j] == '_') {
+                j += 1;
+            }
+            let name: String = chars[i + 1..j].iter().collect();
+            parts.push(WordPart::Param {
+                param: Param {
+                    name,
+                    index: None,
+                    op: ParamOp::Value,
+                },
+                quoted,
+            });
+            Ok(j)
+        }
+        Some(&c) if c.is_ascii_digit() || is_special_param(c) => {
+            parts.push(WordPart::Param {
+                param: Param {
+                    name: c.to_string(),
+                    index: None,
+                    op: ParamOp::Value,
+                },
+                quoted,
+            });
+            Ok(i + 2)
+        }
+        _ => {
+            push_lit(parts, "$".to_string(), quoted);
+            Ok(i + 1)
+        }
+    }
+}
+
+fn is_special_param(c: char) -> bool {
+    matches!(c, '?' | '#' | '@' | '*' | '$' | '!' | '-')
+}
+
+/// Finds the `}` closing a `${` whose contents start at `from`.
+fn find_closing_brace(chars: &[char], from: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut j = from;
+    while j < chars.len() {
+        match chars[j] {
+            '\\' => j += 1,
+            '\'' => j = find_char(chars, j + 1, '\'')?,
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(j),
+            '}' => depth -= 1,
+            _ => {}
+        }
+        j += 1;
+    }
+    None
+}
+
+fn parse_braced(inner: &str) -> Result<Param, ParseError> {
+    let bad = || ParseError::Syntax(format!("${{{inner}}}: bad substitution"));
+    let (op, rest) = if inner.len() > 1 && inner.starts_with('#') {
+        (ParamOp::Length, &inner[1..])
+    } else if inner.len() > 1 && inner.starts_with('!') {
+        (ParamOp::Keys, &inner[1..])
+    } else {
+        (ParamOp::Value, inner)
+    };
+
+    let name_len = if rest.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len())
+    } else if rest.starts_with(|c: char| c.is_ascii_digit()) {
+        rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len())
+    } else if rest.starts_with(is_special_param) {
+        1
+    } else {
+        return Err(bad());
+    };
+    let name = rest[..name_len].to_string();
+    let mut rest = &rest[name_len..];
+
+    let mut index = None;
+    if rest.starts_with('[') {
+        let close = rest.rfind(']').ok_or_else(bad)?;
+        index = Some(rest[1..close].to_string());
+        rest = &rest[close + 1..];
+    }
+    if op == ParamOp::Keys && !matches!(index.as_deref(), Some("@" | "*")) {
+        return Err(bad());
+    }
+    if rest.is_empty() {
+        return Ok(Param { name, index, op });
+    }
+    if op != ParamOp::Value {
+        return Err(bad());
+    }
+    let (colon, rest) = match rest.strip_prefix(':') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let kind = match rest.chars().next() {
+        Some('-') => TestKind::Default,
+        Some('=') => TestKind::Assign,
+        Some('+') => TestKind::Alternate,
+        Some('?') => TestKind::Error,
+        _ => return Err(bad()),
+    };
+    Ok(Param {
+        name,
+        index,
+        op: ParamOp::Test {
+            colon,
+            kind,
+            word: rest[1..].to_string(),
+        },
+    })
+}
+
+/// Turns an already expanded `name=value` operand into an assignment whose
+/// value is taken literally.
+pub fn parse_assignment_text(text: &str) -> Option<Assignment> {
+    let (name, index, append, value) = split_assignment(text)?;
+    let value = Word {
+        raw: value.to_string(),
+        parts: vec![WordPart::Lit {
+            text: value.to_string(),
+            quoted: true,
+        }],
+        assignment: None,
+    };
+    Some(Assignment {
+        name,
+        index,
+        append,
+        value: AssignValue::Scalar(value),
+    })
+}
+
+/// Splits a leading `name`, `name[sub]`, `name=` or `name+=` off an assignment word.
+fn split_assignment(raw: &str) -> Option<(String, Option<String>, bool, &str)> {
+    let name_len = raw.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))?;
+    let name = &raw[..name_len];
+    if !crate::vars::is_valid_name(name) {
+        return None;
+    }
+    let mut rest = &raw[name_len..];
+    let mut index = None;
+    if rest.starts_with('[') {
+        let close = matching_bracket(rest)?;
+        index = Some(rest[1..close].to_string());
+        rest = &rest[close + 1..];
+    }
+    let (append, value) = if let Some(v) = rest.strip_prefix("+=") {
+        (true, v)
+    } else {
+        (false, rest.strip_prefix('=')?)
+    };
+    Some((name.to_string(), index, append, value))
+}
+
+/// Index of the `]` matching the `[` that starts `s`.
+fn matching_bracket(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Whether `name` can name a function. Besides variable names, dashes and
+/// dots are allowed, as in `git-status` or `..`.
+fn is_function_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || "_-.:".contains(c))
+        && !RESERVED_WORDS.contains(&name)
+}
+
+fn is_meta(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '|' | '&' | ';' | '<' | '>' | '(' | ')')
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    /// Set when the input ends with a backslash-newline, which continues
+    /// the line onto one that has not been read yet.
+    continued: bool,
+}
+
+impl Parser {
+    fn new(input: &str) -> Self {
+        Parser {
+            chars: input.chars().collect(),
+            pos: 0,
+            continued: false,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars().enumerate().all(|(i, c)| self.peek_at(i) == Some(c))
+    }
+
+    /// Skips spaces, tabs, line continuations and comments, but not newlines.
+    fn skip_blanks(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t') => self.pos += 1,
+                Some('\\') if self.peek_at(1) == Some('\n') => self.skip_continuation(),
+                Some('#') => {
+                    while !matches!(self.peek(), None | Some('\n')) {
+                        self.pos += 1;
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// Moves past a backslash-newline, noting whether it ends the input.
+    fn skip_continuation(&mut self) {
+        self.pos += 2;
+        if self.peek().is_none() {
+            self.continued = true;
+        }
+    }
+
+    fn skip_blanks_and_newlines(&mut self) {
+        loop {
+            self.skip_blanks();
+            if self.peek() == Some('\n') {
+                self.pos += 1;
+            } else {
+                return;
+            }
+        }
+    }
+
+    /// Describes the token at the cursor for error messages.
+    fn unexpected(&self) -> ParseError {
+        let token = match self.peek() {
+            None => return ParseError::Incomplete,
+            Some('\n') => "newline".to_string(),
+            Some(c) if is_meta(c) => {
+                let next = self.peek_at(1);
+                match (c, next) {
+                    ('|', Some('|')) | ('&', Some('&')) | (';', Some(';')) | ('>', Some('>')) | ('<', Some('<')) => {
+                        format!("{c}{c}")
+                    }
+                    _ => c.to_string(),
+                }
+            }
+            Some(_) => {
+                let end = (self.pos..self.chars.len())
+                    .find(|&j| is_meta(self.chars[j]))
+                    .unwrap_or(self.chars.len());
+                self.chars[self.pos..end].iter().collect()
+            }
+        };
+        ParseError::Syntax(format!("syntax error near unexpected token `{token}'"))
+    }
+
+    /// The unquoted word at the cursor, if it is a reserved word.
+    fn peek_reserved(&self) -> Option<&'static str> {
+        RESERVED_WORDS.iter().copied().find(|word| {
+            self.starts_with(word) && self.peek_at(word.len()).is_none_or(is_meta)
+        })
+    }
+
+    fn expect_reserved(&mut self, word: &str) -> Result<(), ParseError> {
+        self.skip_blanks_and_newlines();
+        if self.peek_reserved() == Some(word) {
+            self.pos += word.len();
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    /// Parses commands until the input ends or one of `terminators` appears
+    /// where a command would start.
+    fn parse_list(&mut self, terminators: &[&str]) -> Result<List, ParseError> {
+        let mut list = List::default();
+        loop {
+            self.skip_blanks_and_newlines();
+            match self.peek() {
+                None => return Ok(list),
+                Some(')') => return Ok(list),
+                _ => {}
+            }
+            if self.peek_reserved().is_some_and(|w| terminators.contains(&w)) {
+                return Ok(list);
+            }
+            let and_or = self.parse_and_or()?;
+            self.skip_blanks();
+            let mut background = false;
+            match self.peek() {
+                None => {}
+                Some('\n') => self.pos += 1,
+                Some('&') if !self.starts_with("&&") => {
+                    background = true;
+                    self.pos += 1;
+                }
+                Some(';') if !self.starts_with(";;") => self.pos += 1,
+                Some(_) if self.peek_reserved().is_some_and(|w| terminators.contains(&w)) => {}
+                Some(_) => return Err(self.unexpected()),
+            }
+            list.items.push(ListItem { and_or, background });
+        }
+    }
+
+    /// Like `parse_list`, but for the body of a compound command, which must
+    /// contain at least one command.
+    fn parse_compound_list(&mut self, terminators: &[&str]) -> Result<List, ParseError> {
+        let list = self.parse_list(terminators)?;
+        if list.items.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(list)
+    }
+
+    fn parse_and_or(&mut self) -> Result<AndOr, ParseError> {
+        self.skip_blanks();
+        let start = self.pos;
+        let first = self.parse_pipeline()?;
+        let mut rest = Vec::new();
+        loop {
+            let end = self.pos;
+            self.skip_blanks();
+            let connector = if self.starts_with("&&") {
+                Connector::And
+            } else if self.starts_with("||") {
+                Connector::Or
+            } else {
+                let text: String = self.chars[start..end].iter().collect();
+                return Ok(AndOr {
+                    first,
+                    rest,
+                    text: text.trim_end().to_string(),
+                });
+            };
+            self.pos += 2;
+            self.skip_blanks_and_newlines();
+            rest.push((connector, self.parse_pipeline()?));
+        }
+    }
+
+    fn parse_pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        self.skip_blanks();
+        let mut negated = false;
+        let mut time = None;
+        loop {
+            match self.peek_reserved() {
+                Some("!") => {
+                    self.pos += 1;
+                    negated = !negated;
+                }
+                Some("time") if time.is_none() => {
+                    self.pos += "time".len();
+                    self.skip_blanks();
+                    time = Some(TimeFormat::Default);
+                    if self.starts_with("-p") && self.peek_at(2).is_none_or(is_meta) {
+                        self.pos += 2;
+                        time = Some(TimeFormat::Posix);
+                    }
+                }
+                _ => break,
+            }
+            self.skip_blanks();
+        }
+        // A bare `time` just reports the shell's own usage.
+        if time.is_some() && matches!(self.peek(), None | Some('\n' | ';')) {
+            return Ok(Pipeline {
+                negated,
+                time,
+                commands: Vec::new(),
+            });
+        }
+        let mut commands = vec![self.parse_command()?];
+        loop {
+            self.skip_blanks();
+            if self.peek() == Some('|') && !self.starts_with("||") {
+                self.pos += 1;
+                self.skip_blanks_and_newlines();
+                commands.push(self.parse_command()?);
+            } else {
+                return Ok(Pipeline {
+                    negated,
+                    time,
+                    commands,
+                });
+            }
+        }
+    }
+
+    fn parse_command(&mut self) -> Result<Command, ParseError> {
+        self.skip_blanks();
+        let compound = match self.peek_reserved() {
+            Some("function") => {
+                self.pos += "function".len();
+                self.skip_blanks();
+                let name = self.read_word_raw()?;
+                if !is_function_name(&name) {
+                    return Err(ParseError::Syntax(format!("`{name}': not a valid identifier")));
+                }
+                self.skip_blanks();
+                if self.peek() == Some('(') {
+                    self.skip_function_parens()?;
+                }
+                return self.parse_function_body(name);
+            }
+            None if self.at_function_definition() => {
+                let name = self.read_word_raw()?;
+                self.skip_blanks();
+                self.skip_function_parens()?;
+                return self.parse_function_body(name);
+            }
+            Some("coproc") => {
+                self.pos += "coproc".len();
+                self.skip_blanks();
+                let mut name = "COPROC".to_string();
+                if self.peek_reserved().is_none() {
+                    let start = self.pos;
+                    let word = self.read_word_raw()?;
+                    self.skip_blanks();
+                    if crate::vars::is_valid_name(&word) && matches!(self.peek_reserved(), Some("{" | "if" | "while" | "until" | "[[")) {
+                        name = word;
+                    } else {
+                        self.pos = start;
+                    }
+                }
+                let body = Box::new(self.parse_command()?);
+                return Ok(Command::Coproc { name, body });
+            }
+            Some("{") => {
+                self.pos += 1;
+                let body = self.parse_compound_list(&["}"])?;
+                self.expect_reserved("}")?;
+                CompoundCommand::Group(body)
+            }
+            Some("if") => self.parse_if()?,
+            Some("[[") => self.parse_cond()?,
+            Some(word @ ("while" | "until")) => {
+                self.pos += word.len();
+                let condition = self.parse_compound_list(&["do"])?;
+                self.expect_reserved("do")?;
+                let body = self.parse_compound_list(&["done"])?;
+                self.expect_reserved("done")?;
+                CompoundCommand::While {
+                    condition,
+                    body,
+                    until: word == "until",
+                }
+            }
+            // Only reserved at the start of a pipeline; elsewhere it names
+            // the `time` utility.
+            Some("time") | None => return Ok(Command::Simple(self.parse_simple_command()?)),
+            Some(_) => return Err(self.unexpected()),
+        };
+        let mut redirects = Vec::new();
+        loop {
+            self.skip_blanks();
+            if self.at_redirect() {
+                redirects.push(self.parse_redirect()?);
+            } else {
+                return Ok(Command::Compound(compound, redirects));
+            }
+        }
+    }
+
+    /// Whether the cursor sits on `name()`, which starts a function definition.
+    fn at_function_definition(&mut self) -> bool {
+        let start = self.pos;
+        let found = self.read_word_raw().is_ok_and(|name| {
+            self.skip_blanks();
+            is_function_name(&name) && self.peek() == Some('(')
+        });
+        self.pos = start;
+        found
+    }
+
+    /// Moves past the `()` after a function's name.
+    fn skip_function_parens(&mut self) -> Result<(), ParseError> {
+        self.pos += 1;
+        self.skip_blanks();
+        if self.peek() != Some(')') {
+            return Err(self.unexpected());
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Parses the compound command that follows a function's name.
+    fn parse_function_body(&mut self, name: String) -> Result<Command, ParseError> {
+        self.skip_blanks_and_newlines();
+        match self.parse_command()? {
+            body @ Command::Compound(..) => Ok(Command::Function {
+                name,
+                body: Rc::new(body),
+            }),
+            _ => Err(ParseError::Syntax(format!("{name}: a function body must be a compound command"))),
+        }
+    }
+
+    fn parse_if(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.pos += "if".len();
+        let mut branches = Vec::new();
+        let mut otherwise = None;
+        loop {
+            let condition = self.parse_compound_list(&["then"])?;
+            self.expect_reserved("then")?;
+            let body = self.parse_compound_list(&["elif", "else", "fi"])?;
+            branches.push((condition, body));
+            self.skip_blanks_and_newlines();
+            match self.peek_reserved() {
+                Some("elif") => self.pos += "elif".len(),
+                Some("else") => {
+                    self.pos += "else".len();
+                    otherwise = Some(self.parse_compound_list(&["fi"])?);
+                    self.expect_reserved("fi")?;
+                    break;
+                }
+                Some("fi") => {
+                    self.pos += "fi".len();
+                    break;
+                }
+                _ => return Err(self.unexpected()),
+            }
+        }
+        Ok(CompoundCommand::If { branches, otherwise })
+    }
+
+    /// Parses `[[ ... ]]`. Inside it `&&`, `||`, `(`, `)`, `<` and `>` are
+    /// operators rather than shell syntax, and the operand of `=~` may use
+    /// parentheses and `|` unquoted.
+    fn parse_cond(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.pos += "[[".len();
+        let mut words: Vec<Word> = Vec::new();
+        loop {
+            self.skip_blanks_and_newlines();
+            let Some(c) = self.peek() else {
+                return Err(ParseError::Incomplete);
+            };
+            if self.starts_with("]]") && self.peek_at(2).is_none_or(is_meta) {
+                self.pos += 2;
+                break;
+            }
+            let raw = if self.starts_with("&&") || self.starts_with("||") {
+                self.pos += 2;
+                self.chars[self.pos - 2..self.pos].iter().collect()
+            } else if matches!(c, '(' | ')' | '<' | '>') {
+                self.pos += 1;
+                c.to_string()
+            } else if matches!(c, ';' | '&' | '|') {
+                return Err(self.unexpected());
+            } else if words.last().is_some_and(|w| w.raw == "=~") {
+                self.read_regex_raw()?
+            } else {
+                self.read_word_raw()?
+            };
+            words.push(parse_word(&raw)?);
+        }
+        if words.is_empty() {
+            return Err(ParseError::Syntax("syntax error near unexpected token `]]'".to_string()));
+        }
+        Ok(CompoundCommand::Cond(words))
+    }
+
+    /// Reads the right-hand side of `=~`, which ends at a blank or at a `)`
+    /// that closes a group of the surrounding expression.
+    fn read_regex_raw(&mut self) -> Result<String, ParseError> {
+        let start = self.pos;
+        let mut depth = 0usize;
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\n' if depth == 0 => break,
+                ')' if depth == 0 => break,
+                '(' => {
+                    depth += 1;
+                    self.pos += 1;
+                }
+                ')' => {
+                    depth -= 1;
+                    self.pos += 1;
+                }
+                '\\' => match self.peek_at(1) {
+                    None => return Err(ParseError::Incomplete),
+                    Some('\n') => self.skip_continuation(),
+                    Some(_) => self.pos += 2,
+                },
+                '\'' => {
+                    self.pos = find_char(&self.chars, self.pos + 1, '\'').ok_or(ParseError::Incomplete)? + 1;
+                }
+                '"' => self.skip_double_quoted()?,
+                '$' if self.peek_at(1) == Some('\'') => {
+                    self.pos = find_ansi_c_end(&self.chars, self.pos + 2).ok_or(ParseError::Incomplete)? + 1;
+                }
+                '$' if self.peek_at(1) == Some('{') => {
+                    self.pos = find_closing_brace(&self.chars, self.pos + 2).ok_or(ParseError::Incomplete)? + 1;
+                }
+                _ => self.pos += 1,
+            }
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn parse_simple_command(&mut self) -> Result<SimpleCommand, ParseError> {
+        let mut command = SimpleCommand::default();
+        loop {
+            self.skip_blanks();
+            match self.peek() {
+                None => break,
+                Some(_) if self.at_redirect() => command.redirects.push(self.parse_redirect()?),
+                Some(c) if is_meta(c) => break,
+                Some(_) => {
+                    let raw = self.read_word_raw()?;
+                    match command.words.first() {
+                        None => {
+                            if let Some(assignment) = self.parse_assignment(&raw)? {
+                                command.assignments.push(assignment);
+                                continue;
+                            }
+                            command.words.push(parse_word(&raw)?);
+                        }
+                        Some(first) if DECLARATION_BUILTINS.contains(&first.raw.as_str()) => {
+                            let start = self.pos - raw.chars().count();
+                            let word = match self.parse_assignment(&raw)? {
+                                Some(assignment) => {
+                                    let raw: String = self.chars[start..self.pos].iter().collect();
+                                    let mut word = parse_word(&raw)?;
+                                    word.assignment = Some(Box::new(assignment));
+                                    word
+                                }
+                                None => parse_word(&raw)?,
+                            };
+                            command.words.push(word);
+                        }
+                        Some(_) => command.words.push(parse_word(&raw)?),
+                    }
+                }
+            }
+        }
+        if command.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(command)
+    }
+
+    /// Whether the cursor sits on `[n]<`, `[n]>`, `{name}<`, `{name}>` or `&>`.
+    fn at_redirect(&self) -> bool {
+        self.starts_with("&>") || matches!(self.peek_at(self.redirect_prefix_len()), Some('<' | '>'))
+    }
+
+    /// The length of the `n` or `{name}` in front of a redirection operator
+    /// at the cursor, or 0 if there is none.
+    fn redirect_prefix_len(&self) -> usize {
+        let mut j = 0;
+        while self.peek_at(j).is_some_and(|c| c.is_ascii_digit()) {
+            j += 1;
+        }
+        if j == 0 && self.peek() == Some('{') {
+            let name: String = (1..).map_while(|k| self.peek_at(k).filter(|&c| c != '}' && !is_meta(c))).collect();
+            if crate::vars::is_valid_name(&name) && self.peek_at(name.len() + 1) == Some('}') {
+                return name.len() + 2;
+            }
+        }
+        j
+    }
+
+    fn parse_redirect(&mut self) -> Result<Redirect, ParseError> {
+        let mut fd_var = None;
+        if self.peek() == Some('{') {
+            let len = self.redirect_prefix_len();
+            fd_var = Some(self.chars[self.pos + 1..self.pos + len - 1].iter().collect());
+            self.pos += len;
+        }
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let fd = if self.pos > start {
+            let digits: String = self.chars[start..self.pos].iter().collect();
+            Some(digits.parse().map_err(|_| ParseError::Syntax(format!("{digits}: bad file descriptor")))?)
+        } else {
+            None
+        };
+        let ops: [(&str, RedirOp); 9] = [
+            ("&>>", RedirOp::AppendOutErr),
+            ("&>", RedirOp::OutErr),
+            (">>", RedirOp::Append),
+            (">|", RedirOp::Clobber),
+            (">&", RedirOp::DupOut),
+            (">", RedirOp::Out),
+            ("<&", RedirOp::DupIn),
+            ("<>", RedirOp::ReadWrite),
+            ("<", RedirOp::In),
+        ];
+        if self.starts_with("<<") {
+            return Err(ParseError::Syntax("here-documents are not supported".to_string()));
+        }
+        let (text, op) = ops
+            .iter()
+            .find(|(text, _)| self.starts_with(text))
+            .copied()
+            .ok_or_else(|| self.unexpected())?;
+        if (fd.is_some() || fd_var.is_some()) && matches!(op, RedirOp::OutErr | RedirOp::AppendOutErr) {
+            return Err(self.unexpected());
+        }
+        self.pos += text.len();
+        self.skip_blanks();
+        match self.peek() {
+            Some(c) if !is_meta(c) => {}
+            _ => return Err(self.unexpected()),
+        }
+        let raw = self.read_word_raw()?;
+        Ok(Redirect {
+            fd,
+            fd_var,
+            op,
+            target: parse_word(&raw)?,
+        })
+    }
+
+    /// Reads one word as written, up to the next unquoted metacharacter.
+    fn read_word_raw(&mut self) -> Result<String, ParseError> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            match c {
+                c if is_meta(c) => break,
+                '\\' => match self.peek_at(1) {
+                    None => return Err(ParseError::Incomplete),
+                    Some('\n') => self.skip_continuation(),
+                    Some(_) => self.pos += 2,
+                },
+                '\'' => {
+                    self.pos = find_char(&self.chars, self.pos + 1, '\'').ok_or(ParseError::Incomplete)? + 1;
+                }
+                '"' => self.skip_double_quoted()?,
+                '$' if self.peek_at(1) == Some('\'') => {
+                    self.pos = find_ansi_c_end(&self.chars, self.pos + 2).ok_or(ParseError::Incomplete)? + 1;
+                }
+                '$' if self.peek_at(1) == Some('{') => {
+                    self.pos = find_closing_brace(&self.chars, self.pos + 2).ok_or(ParseError::Incomplete)? + 1;
+                }
+                _ => self.pos += 1,
+            }
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn skip_double_quoted(&mut self) -> Result<(), ParseError> {
+        self.pos += 1;
+        while let Some(c) = self.peek() {
+            match c {
+                '"' => {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                '\\' => self.pos += 2,
+                '$' if self.peek_at(1) == Some('{') => {
+                    self.pos = find_closing_brace(&self.chars, self.pos + 2).ok_or(ParseError::Incomplete)? + 1;
+                }
+                _ => self.pos += 1,
+            }
+        }
+        Err(ParseError::Incomplete)
+    }
+
+    /// Recognises `name=value`, `name[sub]=value` and `name=(...)`.
+    fn parse_assignment(&mut self, raw: &str) -> Result<Option<Assignment>, ParseError> {
+        let Some((name, index, append, value)) = split_assignment(raw) else {
+            return Ok(None);
+        };
+        let value = if value.is_empty() && index.is_none() && self.peek() == Some('(') {
+            self.pos += 1;
+            AssignValue::Array(self.parse_array_elements()?)
+        } else {
+            AssignValue::Scalar(parse_word(value)?)
+        };
+        Ok(Some(Assignment {
+            name,
+            index,
+            append,
+            value,
+        }))
+    }
+
+    fn parse_array_elements(&mut self) -> Result<Vec<(Option<String>, Word)>, ParseError> {
+        let mut elements = Vec::new();
+        loop {
+            self.skip_blanks_and_newlines();
+            match self.peek() {
+                None => return Err(ParseError::Incomplete),
+                Some(')') => {
+                    self.pos += 1;
+                    return Ok(elements);
+                }
+                Some(c) if is_meta(c) => return Err(self.unexpected()),
+                Some(_) => {
+                    let raw = self.read_word_raw()?;
+                    let keyed = raw
+                        .starts_with('[')
+                        .then(|| matching_bracket(&raw))
+                        .flatten()
+                        .filter(|&close| raw[close + 1..].starts_with('='));
+                    match keyed {
+                        Some(close) => elements.push((Some(raw[1..close].to_string()), parse_word(&raw[close + 2..])?)),
+                        None => elements.push((None, parse_word(&raw)?)),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A span in characters, as [`Parser::scan_spans`] finds them.
+type CharSpan = (SpanKind, usize, usize);
+
+impl Parser {
+    /// Walks the input like the parser would, but never fails: an unfinished
+    /// quote or expansion runs to the end of the input.
+    fn scan_spans(&mut self) -> Vec<CharSpan> {
+        let mut spans = Vec::new();
+        let mut command_start = true;
+        let mut in_cond = false;
+        while let Some(c) = self.peek() {
+            let start = self.pos;
+            match c {
+                ' ' | '\t' => self.pos += 1,
+                '\n' => {
+                    self.pos += 1;
+                    command_start = !in_cond;
+                }
+                '\\' if self.peek_at(1) == Some('\n') => self.pos += 2,
+                '#' => {
+                    while !matches!(self.peek(), None | Some('\n')) {
+                        self.pos += 1;
+                    }
+                    spans.push((SpanKind::Comment, start, self.pos));
+                }
+                _ if in_cond && self.starts_with("]]") && self.peek_at(2).is_none_or(is_meta) => {
+                    self.pos += 2;
+                    spans.push((SpanKind::Keyword, start, self.pos));
+                    in_cond = false;
+                    command_start = false;
+                }
+                _ if !in_cond && self.at_redirect() => {
+                    self.pos += self.redirect_prefix_len();
+                    let op = ["&>>", "&>", ">>", ">|", ">&", "<&", "<>", "<<", ">", "<"]
+                        .iter()
+                        .find(|op| self.starts_with(op))
+                        .map_or(1, |op| op.len());
+                    self.pos += op;
+                    while matches!(self.peek(), Some(' ' | '\t')) {
+                        self.pos += 1;
+                    }
+                    let target = self.pos;
+                    self.skip_word_lenient();
+                    spans.push((SpanKind::Redirect, start, self.pos));
+                    self.scan_word_parts(target, &mut spans);
+                }
+                _ if is_meta(c) => {
+                    let op = ["&&", "||", ";;", "|", "&", ";", "(", ")"]
+                        .iter()
+                        .find(|op| self.starts_with(op))
+                        .map_or(1, |op| op.len());
+                    self.pos += op;
+                    spans.push((SpanKind::Operator, start, self.pos));
+                    command_start = !in_cond;
+                }
+                _ if command_start && self.peek_reserved().is_some() => {
+                    let word = self.peek_reserved().unwrap_or_default();
+                    self.pos += word.len();
+                    spans.push((SpanKind::Keyword, start, self.pos));
+                    in_cond = word == "[[";
+                    command_start = !matches!(word, "[[" | "]]" | "}" | "function" | "fi" | "done");
+                    if word == "coproc" {
+                        self.skip_coproc_name(&mut spans);
+                    }
+                }
+                _ => {
+                    self.skip_word_lenient();
+                    let raw: String = self.chars[start..self.pos].iter().collect();
+                    if command_start && let Some((name, ..)) = split_assignment(&raw) {
+                        let value = start + raw.chars().position(|c| c == '=').map_or(name.len(), |i| i + 1);
+                        spans.push((SpanKind::Assignment, start, value));
+                        self.scan_word_parts(value, &mut spans);
+                        if self.peek() == Some('(') && value == self.pos {
+                            self.scan_array_spans(&mut spans);
+                        }
+                        continue;
+                    }
+                    if command_start {
+                        spans.push((SpanKind::Command, start, self.pos));
+                        command_start = false;
+                    }
+                    self.scan_word_parts(start, &mut spans);
+                }
+            }
+        }
+        spans
+    }
+
+    /// Marks the name after `coproc`, if one is given, as an assignment,
+    /// since it names the variable the descriptors go in.
+    fn skip_coproc_name(&mut self, spans: &mut Vec<CharSpan>) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.pos += 1;
+        }
+        let start = self.pos;
+        if self.peek_reserved().is_some() || self.read_word_raw().is_err() {
+            self.pos = start;
+            return;
+        }
+        let end = self.pos;
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.pos += 1;
+        }
+        if matches!(self.peek_reserved(), Some("{" | "if" | "while" | "until" | "[[")) {
+            spans.push((SpanKind::Assignment, start, end));
+        } else {
+            self.pos = start;
+        }
+    }
+
+    /// Moves past the word at the cursor, or to the end of the input if it
+    /// never ends.
+    fn skip_word_lenient(&mut self) {
+        if self.read_word_raw().is_err() {
+            self.pos = self.chars.len();
+        }
+    }
+
+    /// Adds the quoted strings and expansions in `chars[start..self.pos]`.
+    fn scan_word_parts(&self, start: usize, spans: &mut Vec<CharSpan>) {
+        let end = self.pos;
+        let mut i = start;
+        while i < end {
+            match self.chars[i] {
+                '\\' => i += 2,
+                '\'' => {
+                    let close = find_char(&self.chars[..end], i + 1, '\'').map_or(end, |j| j + 1);
+                    spans.push((SpanKind::Quoted, i, close));
+                    i = close;
+                }
+                '$' if self.chars[..end].get(i + 1) == Some(&'\'') => {
+                    let close = find_ansi_c_end(&self.chars[..end], i + 2).map_or(end, |j| j + 1);
+                    spans.push((SpanKind::Quoted, i, close));
+                    i = close;
+                }
+                '"' => {
+                    let open = spans.len();
+                    spans.push((SpanKind::Quoted, i, end));
+                    i += 1;
+                    while i < end && self.chars[i] != '"' {
+                        match self.chars[i] {
+                            '\\' => i += 2,
+                            '$' => i = self.scan_dollar(i, end, spans),
+                            _ => i += 1,
+                        }
+                    }
+                    i = (i + 1).min(end);
+                    spans[open].2 = i;
+                }
+                '$' => i = self.scan_dollar(i, end, spans),
+                _ => i += 1,
+            }
+        }
+    }
+
+    /// Adds the expansion starting at `chars[i]`, if any, and returns the
+    /// index just past it.
+    fn scan_dollar(&self, i: usize, end: usize, spans: &mut Vec<CharSpan>) -> usize {
+        let name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        let close = match self.chars[..end].get(i + 1) {
+            Some('{') => find_closing_brace(&self.chars[..end], i + 2).map_or(end, |j| j + 1),
+            Some(&c) if c.is_ascii_alphabetic() || c == '_' => {
+                (i + 1..end).find(|&j| !name_char(self.chars[j])).unwrap_or(end)
+            }
+            Some(&c) if c.is_ascii_digit() || is_special_param(c) => i + 2,
+            _ => return i + 1,
+        };
+        spans.push((SpanKind::Variable, i, close));
+        close
+    }
+
+    /// Adds the elements of `name=(...)`, with the cursor on the `(`.
+    fn scan_array_spans(&mut self, spans: &mut Vec<CharSpan>) {
+        spans.push((SpanKind::Operator, self.pos, self.pos + 1));
+        self.pos += 1;
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\n' => self.pos += 1,
+                ')' => {
+                    spans.push((SpanKind::Operator, self.pos, self.pos + 1));
+                    self.pos += 1;
+                    return;
+                }
+                c if is_meta(c) => return,
+                _ => {
+                    let start = self.pos;
+                    self.skip_word_lenient();
+                    self.scan_word_parts(start, spans);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,407 @@
+//! The `echo` and `printf` builtins.
+
+use std::ffi::CString;
+use std::io::Write;
+
+use nix::libc;
+
+use crate::exec::quote_for_trace;
+use crate::vars;
+use crate::Shell;
+
+impl Shell {
+    /// `echo [-neE] [args...]`
+    pub(crate) fn builtin_echo(&mut self, argv: &[String]) -> i32 {
+        let mut newline = true;
+        let mut escapes = false;
+        let mut args = &argv[1..];
+        while let Some(flag) = args.first().and_then(|a| a.strip_prefix('-')) {
+            if flag.is_empty() || !flag.chars().all(|c| matches!(c, 'n' | 'e' | 'E')) {
+                break;
+            }
+            for c in flag.chars() {
+                match c {
+                    'n' => newline = false,
+                    'e' => escapes = true,
+                    _ => escapes = false,
+                }
+            }
+            args = &args[1..];
+        }
+
+        let mut out = args.join(" ");
+        if escapes {
+            let (text, stop) = unescape(&out, true);
+            out = text;
+            newline &= !stop;
+        }
+        if newline {
+            out.push('\n');
+        }
+        match self.fds.writer(1).write_all(out.as_bytes()) {
+            Ok(()) => 0,
+            Err(e) => {
+                self.report_error(format_args!("echo: write error: {e}"));
+                1
+            }
+        }
+    }
+
+    /// `printf [-v var] format [args...]`. The format is reused until every
+    /// argument has been consumed.
+    pub(crate) fn builtin_printf(&mut self, argv: &[String]) -> i32 {
+        let mut args = &argv[1..];
+        let mut target = None;
+        if args.first().is_some_and(|a| a == "-v") {
+            match args.get(1) {
+                Some(name) if vars::is_valid_name(name) => target = Some(name.clone()),
+                Some(name) => {
+                    self.report_error(format_args!("printf: `{name}': not a valid identifier"));
+                    return 2;
+                }
+                None => {
+                    self.report_error("printf: -v: option requires an argument");
+                    return 2;
+                }
+            }
+            args = &args[2..];
+        }
+        if args.first().is_some_and(|a| a == "--") {
+            args = &args[1..];
+        }
+        let Some((format, mut args)) = args.split_first() else {
+            self.report_error("printf: usage: printf [-v var] format [arguments]");
+            return 2;
+        };
+
+        let mut printer = Printer::default();
+        loop {
+            let consumed = printer.run(format, args);
+            args = &args[consumed.min(args.len())..];
+            if printer.stopped || consumed == 0 || args.is_empty() {
+                break;
+            }
+        }
+        for error in &printer.errors {
+            self.report_error(format_args!("printf: {error}"));
+        }
+        let status = (!printer.errors.is_empty()) as i32;
+
+        match target {
+            Some(name) => {
+                if let Err(e) = self.check_restricted_variable(&name) {
+                    self.report_error(format_args!("printf: {e}"));
+                    return 1;
+                }
+                self.env_vars.set_scalar(&name, printer.out);
+            }
+            None => {
+                if let Err(e) = self.fds.writer(1).write_all(printer.out.as_bytes()) {
+                    self.report_error(format_args!("printf: write error: {e}"));
+                    return 1;
+                }
+            }
+        }
+        status
+    }
+}
+
+#[derive(Default)]
+struct Printer {
+    out: String,
+    errors: Vec<String>,
+    /// Set by `\c` in a `%b` argument, which ends all output.
+    stopped: bool,
+}
+
+impl Printer {
+    /// Formats `args` through `format` once and returns how many were used.
+    fn run(&mut self, format: &str, args: &[String]) -> usize {
+        let chars: Vec<char> = format.chars().collect();
+        let mut used = 0;
+        let mut next_arg = || {
+            let arg = args.get(used).cloned();
+            used += 1;
+            arg
+        };
+        let mut i = 0;
+        while i < chars.len() && !self.stopped {
+            match chars[i] {
+                '\\' => {
+                    let rest: String = chars[i..].iter().collect();
+                    let (text, len) = escape_at(&rest, false);
+                    self.out.push_str(&text);
+                    i += len;
+                }
+                '%' if chars.get(i + 1) == Some(&'%') => {
+                    self.out.push('%');
+                    i += 2;
+                }
+                '%' => {
+                    let start = i;
+                    i += 1;
+                    let mut flags = String::new();
+                    while let Some(&c) = chars.get(i).filter(|c| "-+ #0".contains(**c)) {
+                        flags.push(c);
+                        i += 1;
+                    }
+                    let mut width = String::new();
+                    if chars.get(i) == Some(&'*') {
+                        width = self.integer(next_arg().as_deref().unwrap_or("")).to_string();
+                        i += 1;
+                    } else {
+                        while let Some(&c) = chars.get(i).filter(|c| c.is_ascii_digit()) {
+                            width.push(c);
+                            i += 1;
+                        }
+                    }
+                    let mut precision = None;
+                    if chars.get(i) == Some(&'.') {
+                        i += 1;
+                        let mut digits = String::new();
+                        if chars.get(i) == Some(&'*') {
+                            digits = self.integer(next_arg().as_deref().unwrap_or("")).to_string();
+                            i += 1;
+                        } else {
+                            while let Some(&c) = chars.get(i).filter(|c| c.is_ascii_digit()) {
+                                digits.push(c);
+                                i += 1;
+                            }
+                        }
+                        precision = Some(digits);
+                    }
+                    let Some(&conversion) = chars.get(i) else {
+                        self.errors.push(format!("`{}': missing format character", chars[start..].iter().collect::<String>()));
+                        break;
+                    };
+                    i += 1;
+                    let spec = Spec {
+                        flags,
+                        width,
+                        precision,
+                    };
+                    let arg = next_arg();
+                    self.convert(conversion, &spec, arg.as_deref());
+                }
+                c => {
+                    self.out.push(c);
+                    i += 1;
+                }
+            }
+        }
+        used.min(args.len())
+    }
+
+    fn convert(&mut self, conversion: char, spec: &Spec, arg: Option<&str>) {
+        let arg = arg.unwrap_or("");
+        match conversion {
+            's' => self.pad(arg, spec),
+            'b' => {
+                let (text, stop) = unescape(arg, true);
+                self.pad(&text, spec);
+                self.stopped = stop;
+            }
+            'q' => self.pad(&quote_for_trace(arg), spec),
+            'c' => self.pad(&arg.chars().next().map(String::from).unwrap_or_default(), spec),
+            'd' | 'i' => {
+                let value = self.integer(arg);
+                self.out.push_str(&c_format(&spec.c_spec("lld"), CValue::Int(value)));
+            }
+            'o' | 'u' | 'x' | 'X' => {
+                let value = self.integer(arg);
+                self.out.push_str(&c_format(&spec.c_spec(&format!("ll{conversion}")), CValue::Int(value)));
+            }
+            'e' | 'E' | 'f' | 'F' | 'g' | 'G' | 'a' | 'A' => {
+                let value = self.float(arg);
+                self.out.push_str(&c_format(&spec.c_spec(&conversion.to_string()), CValue::Float(value)));
+            }
+            other => self.errors.push(format!("`{other}': invalid format character")),
+        }
+    }
+
+    /// Applies width, precision and `-` to a string conversion.
+    fn pad(&mut self, text: &str, spec: &Spec) {
+        let text: String = match spec.precision.as_deref().map(|p| p.parse().unwrap_or(0)) {
+            Some(max) => text.chars().take(max).collect(),
+            None => text.to_string(),
+        };
+        let width: usize = spec.width.parse().unwrap_or(0);
+        let fill = width.saturating_sub(text.chars().count());
+        if spec.flags.contains('-') {
+            self.out.push_str(&text);
+            self.out.extend(std::iter::repeat_n(' ', fill));
+        } else {
+            self.out.extend(std::iter::repeat_n(' ', fill));
+            self.out.push_str(&text);
+        }
+    }
+
+    /// Parses a numeric argument: decimal, `0x` hex, `0` octal, or `'c` for
+    /// the character code of `c`.
+    fn integer(&mut self, arg: &str) -> i64 {
+        let text = arg.trim();
+        if let Some(quoted) = text.strip_prefix('\'').or_else(|| text.strip_prefix('"')) {
+            return quoted.chars().next().map_or(0, |c| c as i64);
+        }
+        if text.is_empty() {
+            return 0;
+        }
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let parsed = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+            i64::from_str_radix(hex, 16)
+        } else if digits.len() > 1 && digits.starts_with('0') {
+            i64::from_str_radix(&digits[1..], 8)
+        } else {
+            digits.parse()
+        };
+        match parsed {
+            Ok(value) if negative => -value,
+            Ok(value) => value,
+            Err(_) => {
+                self.errors.push(format!("{arg}: invalid number"));
+                0
+            }
+        }
+    }
+
+    fn float(&mut self, arg: &str) -> f64 {
+        let text = arg.trim();
+        if text.is_empty() {
+            return 0.0;
+        }
+        match text.parse() {
+            Ok(value) => value,
+            Err(_) => self.integer(arg) as f64,
+        }
+    }
+}
+
+/// The flags, width and precision of one conversion.
+struct Spec {
+    flags: String,
+    width: String,
+    precision: Option<String>,
+}
+
+impl Spec {
+    /// Rebuilds the conversion for C's printf with `conversion` as the length
+    /// modifier and conversion character.
+    fn c_spec(&self, conversion: &str) -> String {
+        let mut spec = format!("%{}{}", self.flags, self.width);
+        if let Some(precision) = &self.precision {
+            spec.push('.');
+            spec.push_str(precision);
+        }
+        spec.push_str(conversion);
+        spec
+    }
+}
+
+enum CValue {
+    Int(i64),
+    Float(f64),
+}
+
+/// Formats one number with snprintf(3), so that every flag behaves exactly as
+/// in C.
+fn c_format(spec: &str, value: CValue) -> String {
+    let Ok(spec) = CString::new(spec) else {
+        return String::new();
+    };
+    let mut buf = vec![0u8; 64];
+    loop {
+        // SAFETY: `spec` holds a single conversion matching the type of the
+        // one argument passed, and snprintf writes at most `buf.len()` bytes.
+        let len = unsafe {
+            match value {
+                CValue::Int(n) => libc::snprintf(buf.as_mut_ptr().cast(), buf.len(), spec.as_ptr(), n as libc::c_longlong),
+                CValue::Float(f) => libc::snprintf(buf.as_mut_ptr().cast(), buf.len(), spec.as_ptr(), f),
+            }
+        };
+        let Ok(len) = usize::try_from(len) else {
+            return String::new();
+        };
+        if len < buf.len() {
+            return String::from_utf8_lossy(&buf[..len]).into_owned();
+        }
+        buf.resize(len + 1, 0);
+    }
+}
+
+/// Expands backslash escapes in `text`. `echo` style (also used by `%b`)
+/// writes octal as `\0nnn`; printf's format string uses `\nnn`. The flag is
+/// set when `\c` cut the output short.
+pub fn unescape(text: &str, echo: bool) -> (String, bool) {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(pos) = rest.find('\\') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        if rest.starts_with("\\c") {
+            return (out, true);
+        }
+        let (decoded, len) = escape_at(rest, echo);
+        out.push_str(&decoded);
+        rest = &rest[len..];
+    }
+    out.push_str(rest);
+    (out, false)
+}
+
+/// Decodes the escape sequence at the start of `text`, which begins with a
+/// backslash, returning the result and how many bytes it used.
+pub(crate) fn escape_at(text: &str, echo: bool) -> (String, usize) {
+    let mut chars = text.char_indices().skip(1);
+    let Some((_, c)) = chars.next() else {
+        return ("\\".to_string(), 1);
+    };
+    let simple = match c {
+        'a' => Some('\x07'),
+        'b' => Some('\x08'),
+        'e' | 'E' => Some('\x1b'),
+        'f' => Some('\x0c'),
+        'n' => Some('\n'),
+        'r' => Some('\r'),
+        't' => Some('\t'),
+        'v' => Some('\x0b'),
+        '\\' => Some('\\'),
+        '"' if !echo => Some('"'),
+        '\'' if !echo => Some('\''),
+        _ => None,
+    };
+    if let Some(decoded) = simple {
+        return (decoded.to_string(), 2);
+    }
+    let digits_from = |start: usize, radix: u32, max: usize| {
+        let digits: String = text[start..].chars().take(max).take_while(|d| d.is_digit(radix)).collect();
+        let value = u32::from_str_radix(&digits, radix).ok();
+        (value, digits.len())
+    };
+    match c {
+        '0'..='7' => {
+            // echo wants a leading 0 (`\0nnn`); printf takes `\nnn` directly.
+            let (start, max) = if echo { (2, 3) } else { (1, 3) };
+            if echo && c != '0' {
+                return (text[..2].to_string(), 2);
+            }
+            let (value, len) = digits_from(start, 8, max);
+            let byte = value.unwrap_or(0) as u8;
+            (char::from(byte).to_string(), start + len)
+        }
+        'x' => match digits_from(2, 16, 2) {
+            (Some(value), len) => (char::from(value as u8).to_string(), 2 + len),
+            _ => (text[..2].to_string(), 2),
+        },
+        'u' | 'U' => {
+            let max = if c == 'u' { 4 } else { 8 };
+            match digits_from(2, 16, max) {
+                (Some(value), len) => (char::from_u32(value).map(String::from).unwrap_or_default(), 2 + len),
+                _ => (text[..2].to_string(), 2),
+            }
+        }
+        _ => (text[..1 + c.len_utf8()].to_string(), 1 + c.len_utf8()),
+    }
+}
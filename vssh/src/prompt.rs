@@ -0,0 +1,219 @@
+//! Prompt strings: `$PS1`, `$PS2` and `$PS4`.
+//!
+//! Besides the usual bash escapes, `\?` shows the last exit status, `\c` the
+//! time the last command line took and `\g` the current git branch.
+
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use nix::libc;
+
+use crate::Shell;
+
+impl Shell {
+    /// Renders the prompt stored in `var`, or `default` when it is unset.
+    pub(crate) fn prompt(&mut self, var: &str, default: &str) -> String {
+        let template = match self.env_vars.scalar(var) {
+            Some(template) => template.to_string(),
+            None if var == "PS1" => return format!("{}> ", self.current_dir.display()),
+            None => default.to_string(),
+        };
+        let decoded = self.decode_prompt(&template);
+        match self.expand_str(&decoded) {
+            Ok(prompt) => prompt,
+            Err(_) => decoded,
+        }
+    }
+
+    /// Replaces backslash escapes in a prompt template. Substituted text is
+    /// backslash-quoted so that the parameter expansion which follows leaves
+    /// it alone.
+    fn decode_prompt(&self, template: &str) -> String {
+        let mut out = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                out.push(c);
+                continue;
+            }
+            let Some(escape) = chars.next() else {
+                out.push('\\');
+                break;
+            };
+            let text = match escape {
+                'u' => user_name(),
+                'h' => host_name().split('.').next().unwrap_or_default().to_string(),
+                'H' => host_name(),
+                'w' => self.abbreviate_home(&self.current_dir),
+                'W' => match self.current_dir.file_name() {
+                    Some(name) if self.home_dir().as_deref() != Some(self.current_dir.as_path()) => {
+                        name.to_string_lossy().into_owned()
+                    }
+                    _ => self.abbreviate_home(&self.current_dir),
+                },
+                '$' => {
+                    out.push_str(if nix::unistd::geteuid().is_root() { "#" } else { "\\$" });
+                    continue;
+                }
+                't' => strftime("%H:%M:%S"),
+                'T' => strftime("%I:%M:%S"),
+                '@' => strftime("%I:%M %p"),
+                'A' => strftime("%H:%M"),
+                'd' => strftime("%a %b %d"),
+                'D' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    let format: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                    strftime(if format.is_empty() { "%X" } else { &format })
+                }
+                'j' => self.jobs.len().to_string(),
+                's' => "vssh".to_string(),
+                'v' | 'V' => env!("CARGO_PKG_VERSION").to_string(),
+                '?' => self.last_status.to_string(),
+                'c' => self.last_duration.map(format_duration).unwrap_or_default(),
+                'g' => git_branch(&self.current_dir).unwrap_or_default(),
+                'n' => "\n".to_string(),
+                'r' => "\r".to_string(),
+                'a' => "\x07".to_string(),
+                'e' => "\x1b".to_string(),
+                // Bash uses these to bracket invisible sequences for readline;
+                // the prompt is written directly, so they have nothing to do.
+                '[' | ']' => continue,
+                '0'..='7' => {
+                    let mut code = escape.to_digit(8).unwrap_or(0);
+                    for _ in 0..2 {
+                        match chars.peek().and_then(|c| c.to_digit(8)) {
+                            Some(digit) => {
+                                code = code * 8 + digit;
+                                chars.next();
+                            }
+                            None => break,
+                        }
+                    }
+                    char::from_u32(code).map(String::from).unwrap_or_default()
+                }
+                '\\' => "\\".to_string(),
+                other => format!("\\{other}"),
+            };
+            out.push_str(&quote_prompt_text(&text));
+        }
+        out
+    }
+
+    pub(crate) fn home_dir(&self) -> Option<PathBuf> {
+        self.env_vars.scalar("HOME").map(PathBuf::from).or_else(dirs::home_dir)
+    }
+
+    /// Shows `path` with the home directory replaced by `~`.
+    pub(crate) fn abbreviate_home(&self, path: &Path) -> String {
+        if let Some(home) = self.home_dir()
+            && let Ok(rest) = path.strip_prefix(&home)
+            && home != Path::new("/")
+        {
+            if rest.as_os_str().is_empty() {
+                return "~".to_string();
+            }
+            return format!("~/{}", rest.display());
+        }
+        path.display().to_string()
+    }
+}
+
+/// Backslash-quotes characters that parameter expansion would interpret.
+fn quote_prompt_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '$' | '`' | '"' | '\'') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn user_name() -> String {
+    nix::unistd::User::from_uid(nix::unistd::getuid())
+        .ok()
+        .flatten()
+        .map(|user| user.name)
+        .or_else(|| std::env::var("USER").ok())
+        .unwrap_or_default()
+}
+
+fn host_name() -> String {
+    nix::unistd::gethostname()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Formats the current local time with strftime(3).
+fn strftime(format: &str) -> String {
+    let Ok(format) = CString::new(format) else {
+        return String::new();
+    };
+    let mut buf = [0u8; 256];
+    // SAFETY: localtime_r fills `tm`, and strftime writes at most `buf.len()`
+    // bytes into `buf`.
+    let len = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            return String::new();
+        }
+        libc::strftime(buf.as_mut_ptr().cast(), buf.len(), format.as_ptr(), &tm)
+    };
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+/// Shows a duration the way a prompt wants it: `85ms`, `4.2s`, `3m07s`.
+fn format_duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    match millis {
+        0..1000 => format!("{millis}ms"),
+        1000..60_000 => format!("{:.1}s", duration.as_secs_f64()),
+        _ => {
+            let secs = duration.as_secs();
+            if secs < 3600 {
+                format!("{}m{:02}s", secs / 60, secs % 60)
+            } else {
+                format!("{}h{:02}m", secs / 3600, secs % 3600 / 60)
+            }
+        }
+    }
+}
+
+/// The branch checked out in the repository containing `dir`, or the short
+/// commit id when HEAD is detached. Reads `.git/HEAD` directly, following the
+/// `gitdir:` indirection used by worktrees and submodules.
+fn git_branch(dir: &Path) -> Option<String> {
+    let dot_git = dir.ancestors().map(|d| d.join(".git")).find(|p| p.exists())?;
+    let git_dir = if dot_git.is_file() {
+        let link = std::fs::read_to_string(&dot_git).ok()?;
+        let target = PathBuf::from(link.strip_prefix("gitdir:")?.trim());
+        dot_git.parent()?.join(target)
+    } else {
+        dot_git
+    };
+    let head = std::fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    match head.strip_prefix("ref:") {
+        Some(reference) => {
+            let reference = reference.trim();
+            Some(reference.strip_prefix("refs/heads/").unwrap_or(reference).to_string())
+        }
+        None => Some(head.chars().take(7).collect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_use_the_largest_fitting_unit() {
+        let cases = [(85, "85ms"), (999, "999ms"), (1000, "1.0s"), (4_240, "4.2s"), (187_000, "3m07s"), (3_725_000, "1h02m")];
+        for (millis, shown) in cases {
+            assert_eq!(format_duration(Duration::from_millis(millis)), shown);
+        }
+    }
+}
@@ -0,0 +1,377 @@
+//! Pseudo-terminals. `pty` runs a command on a fresh terminal for programs
+//! that misbehave without one, and `record` does the same while logging the
+//! output with timings in asciicast v2 format, which `record replay` plays
+//! back.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use nix::errno::Errno;
+use nix::libc;
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::pty::{openpty, Winsize};
+use nix::sys::termios::{cfmakeraw, tcgetattr, tcsetattr, SetArg, Termios};
+use nix::unistd::{dup2, setsid, Pid};
+use serde_json::{json, Value};
+
+use crate::io::Fds;
+use crate::parser::RedirOp;
+use crate::signals;
+use crate::Shell;
+
+/// Puts a terminal into raw mode until dropped, so that every keystroke,
+/// Ctrl-C included, goes to the program on the pseudo-terminal.
+pub(crate) struct RawMode {
+    fd: RawFd,
+    saved: Termios,
+}
+
+impl RawMode {
+    pub(crate) fn enter(fd: RawFd) -> Option<RawMode> {
+        let saved = tcgetattr(fd).ok()?;
+        let mut raw = saved.clone();
+        cfmakeraw(&mut raw);
+        tcsetattr(fd, SetArg::TCSADRAIN, &raw).ok()?;
+        Some(RawMode { fd, saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = tcsetattr(self.fd, SetArg::TCSADRAIN, &self.saved);
+    }
+}
+
+pub(crate) fn window_size(fd: RawFd) -> Option<Winsize> {
+    // SAFETY: TIOCGWINSZ writes a winsize into the struct it is given.
+    unsafe {
+        let mut size: Winsize = std::mem::zeroed();
+        (libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) == 0).then_some(size)
+    }
+}
+
+pub(crate) fn set_window_size(fd: RawFd, size: &Winsize) {
+    // SAFETY: TIOCSWINSZ only reads the struct it is given.
+    unsafe {
+        libc::ioctl(fd, libc::TIOCSWINSZ, size);
+    }
+}
+
+/// Writes an asciicast v2 recording: a header line, then one
+/// `[seconds, "o", text]` line per chunk of output.
+struct Recorder {
+    file: File,
+    started: Instant,
+    /// The start of a UTF-8 sequence split across two reads.
+    partial: Vec<u8>,
+}
+
+impl Recorder {
+    fn create(path: &Path, size: &Winsize, command: &[String]) -> std::io::Result<Recorder> {
+        let mut file = File::create(path)?;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let header = json!({
+            "version": 2,
+            "width": size.ws_col,
+            "height": size.ws_row,
+            "timestamp": timestamp,
+            "command": command.join(" "),
+            "env": {
+                "SHELL": std::env::var("SHELL").unwrap_or_default(),
+                "TERM": std::env::var("TERM").unwrap_or_default(),
+            },
+        });
+        writeln!(file, "{header}")?;
+        Ok(Recorder {
+            file,
+            started: Instant::now(),
+            partial: Vec::new(),
+        })
+    }
+
+    fn event(&mut self, kind: &str, data: &str) {
+        let time = (self.started.elapsed().as_secs_f64() * 1e6).round() / 1e6;
+        let _ = writeln!(self.file, "{}", json!([time, kind, data]));
+    }
+
+    fn output(&mut self, bytes: &[u8]) {
+        self.partial.extend_from_slice(bytes);
+        let (text, rest) = match std::str::from_utf8(&self.partial) {
+            Ok(text) => (text.to_string(), Vec::new()),
+            // Hold back an incomplete sequence at the end for the next read.
+            Err(e) if e.error_len().is_none() => {
+                let (valid, rest) = self.partial.split_at(e.valid_up_to());
+                (String::from_utf8_lossy(valid).into_owned(), rest.to_vec())
+            }
+            Err(_) => (String::from_utf8_lossy(&self.partial).into_owned(), Vec::new()),
+        };
+        self.partial = rest;
+        if !text.is_empty() {
+            self.event("o", &text);
+        }
+    }
+
+    fn resize(&mut self, size: &Winsize) {
+        self.event("r", &format!("{}x{}", size.ws_col, size.ws_row));
+    }
+}
+
+impl Shell {
+    /// Forks a subshell whose stdin, stdout and stderr are a new
+    /// pseudo-terminal, which is also its controlling terminal, and runs
+    /// `body` in it. Returns the master side and the child's pid.
+    pub(crate) fn fork_on_pty(
+        &mut self,
+        size: Option<Winsize>,
+        termios: Option<Termios>,
+        body: impl FnOnce(&mut Shell) -> i32,
+    ) -> Result<(OwnedFd, Pid), String> {
+        let pty = openpty(size.as_ref(), termios.as_ref())
+            .map_err(|e| format!("cannot open a pseudo-terminal: {}", e.desc()))?;
+        // SAFETY: openpty just returned these descriptors; nothing else owns them.
+        let (master, slave) = unsafe { (OwnedFd::from_raw_fd(pty.master), OwnedFd::from_raw_fd(pty.slave)) };
+        let slave_fd = slave.as_raw_fd();
+        let child = self.fork_subshell(Some(master.as_raw_fd()), move |shell| {
+            // SAFETY: TIOCSCTTY takes no pointer; the new session has no
+            // controlling terminal yet.
+            let attached = setsid().is_ok() && unsafe { libc::ioctl(slave_fd, libc::TIOCSCTTY, 0) } == 0;
+            if !attached || (0..3).any(|fd| dup2(slave_fd, fd).is_err()) {
+                return 126;
+            }
+            if slave_fd > 2 {
+                let _ = nix::unistd::close(slave_fd);
+            }
+            shell.fds = Fds::inherit();
+            body(shell)
+        });
+        drop(slave);
+        match child {
+            Ok(pid) => Ok((master, pid)),
+            Err(_) => Err("cannot fork".to_string()),
+        }
+    }
+
+    /// Starts `argv` on a pseudo-terminal shaped like the shell's own.
+    fn spawn_on_pty(&mut self, argv: &[String]) -> Result<(OwnedFd, Pid), String> {
+        let terminal = self.fds.get(0).filter(|&fd| nix::unistd::isatty(fd).unwrap_or(false));
+        let size = terminal.and_then(window_size);
+        let termios = terminal.and_then(|fd| tcgetattr(fd).ok());
+        let argv = argv.to_vec();
+        self.fork_on_pty(size, termios, move |shell| shell.exec_argv(&argv))
+    }
+
+    /// Copies keystrokes to `master` and its output back until the program
+    /// on the pseudo-terminal goes away, then returns its status.
+    fn proxy_pty(&mut self, master: OwnedFd, pid: Pid, mut recorder: Option<Recorder>) -> i32 {
+        let master_fd = master.as_raw_fd();
+        let mut input = self.fds.get(0);
+        let output = self.fds.get(1);
+        let terminal = input.filter(|&fd| nix::unistd::isatty(fd).unwrap_or(false));
+        let raw_mode = terminal.and_then(RawMode::enter);
+        signals::watch_window_size();
+
+        let mut buf = [0u8; 4096];
+        loop {
+            if signals::window_resized()
+                && let Some(size) = terminal.and_then(window_size)
+            {
+                set_window_size(master_fd, &size);
+                if let Some(recorder) = &mut recorder {
+                    recorder.resize(&size);
+                }
+            }
+            let mut fds = vec![PollFd::new(master_fd, PollFlags::POLLIN)];
+            if let Some(fd) = input {
+                fds.push(PollFd::new(fd, PollFlags::POLLIN));
+            }
+            match poll(&mut fds, -1) {
+                Ok(_) => {}
+                Err(Errno::EINTR) => continue,
+                Err(_) => break,
+            }
+            let ready = |fd: &PollFd| fd.revents().is_some_and(|r| !r.is_empty());
+            let input_ready = fds.get(1).is_some_and(ready);
+            if ready(&fds[0]) {
+                // Reading the master fails with EIO once every copy of the
+                // slave side has been closed.
+                match nix::unistd::read(master_fd, &mut buf) {
+                    Ok(0) | Err(Errno::EIO) => break,
+                    Ok(n) => {
+                        if let Some(fd) = output {
+                            write_all(fd, &buf[..n]);
+                        }
+                        if let Some(recorder) = &mut recorder {
+                            recorder.output(&buf[..n]);
+                        }
+                    }
+                    Err(Errno::EINTR | Errno::EAGAIN) => {}
+                    Err(_) => break,
+                }
+            }
+            if input_ready && let Some(fd) = input {
+                match nix::unistd::read(fd, &mut buf) {
+                    Ok(n) if n > 0 => write_all(master_fd, &buf[..n]),
+                    Err(Errno::EINTR | Errno::EAGAIN) => {}
+                    // Pass end of input on as the terminal's end-of-file key.
+                    _ => {
+                        write_all(master_fd, b"\x04");
+                        input = None;
+                    }
+                }
+            }
+        }
+        drop(raw_mode);
+        drop(master);
+        self.wait_for(pid)
+    }
+
+    /// `pty command [arg...]`
+    pub(crate) fn builtin_pty(&mut self, argv: &[String]) -> i32 {
+        if argv.len() < 2 {
+            self.report_error("pty: usage: pty command [arg...]");
+            return 2;
+        }
+        match self.spawn_on_pty(&argv[1..]) {
+            Ok((master, pid)) => self.proxy_pty(master, pid, None),
+            Err(e) => {
+                self.report_error(format_args!("pty: {e}"));
+                1
+            }
+        }
+    }
+
+    /// `record [-o file] [command [arg...]]` records a session, by default
+    /// a new interactive vssh; `record replay [-s speed] [-i idle] file`
+    /// plays one back.
+    pub(crate) fn builtin_record(&mut self, argv: &[String]) -> i32 {
+        if argv.get(1).is_some_and(|a| a == "replay") {
+            return self.replay(&argv[2..]);
+        }
+        let mut path = "session.cast".to_string();
+        let mut i = 1;
+        if argv.get(1).is_some_and(|a| a == "-o") {
+            let Some(file) = argv.get(2) else {
+                self.report_error("record: -o: option requires an argument");
+                return 2;
+            };
+            path = file.clone();
+            i = 3;
+        }
+        if let Err(e) = self.check_restricted_redirect(RedirOp::Out, &path) {
+            self.report_error(format_args!("record: {e}"));
+            return 1;
+        }
+        let command = match &argv[i..] {
+            [] => match std::env::current_exe() {
+                Ok(exe) => vec![exe.display().to_string()],
+                Err(e) => {
+                    self.report_error(format_args!("record: {e}"));
+                    return 1;
+                }
+            },
+            command => command.to_vec(),
+        };
+
+        let size = self.fds.get(0).and_then(window_size).unwrap_or(Winsize {
+            ws_row: 24,
+            ws_col: 80,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        });
+        let recorder = match Recorder::create(&self.resolve_path(&path), &size, &command) {
+            Ok(recorder) => recorder,
+            Err(e) => {
+                self.report_error(format_args!("record: {path}: {e}"));
+                return 1;
+            }
+        };
+        let _ = writeln!(self.fds.writer(2), "Recording to {path}");
+        let status = match self.spawn_on_pty(&command) {
+            Ok((master, pid)) => self.proxy_pty(master, pid, Some(recorder)),
+            Err(e) => {
+                self.report_error(format_args!("record: {e}"));
+                return 1;
+            }
+        };
+        let _ = writeln!(self.fds.writer(2), "Recording saved to {path}");
+        status
+    }
+
+    fn replay(&mut self, args: &[String]) -> i32 {
+        let mut speed = 1.0;
+        let mut max_idle = None;
+        let mut i = 0;
+        while let Some(flag @ ("-s" | "-i")) = args.get(i).map(String::as_str) {
+            let value = args.get(i + 1).and_then(|v| v.parse::<f64>().ok()).filter(|v| *v > 0.0);
+            let Some(value) = value else {
+                self.report_error(format_args!("record: replay: {flag}: expected a positive number"));
+                return 2;
+            };
+            match flag {
+                "-s" => speed = value,
+                _ => max_idle = Some(value),
+            }
+            i += 2;
+        }
+        let Some(path) = args.get(i) else {
+            self.report_error("record: usage: record replay [-s speed] [-i idle] file");
+            return 2;
+        };
+        let file = match File::open(self.resolve_path(path)) {
+            Ok(file) => file,
+            Err(e) => {
+                self.report_error(format_args!("record: {path}: {e}"));
+                return 1;
+            }
+        };
+
+        let mut lines = BufReader::new(file).lines();
+        let header: Option<Value> = lines.next().and_then(Result::ok).and_then(|l| serde_json::from_str(&l).ok());
+        if header.as_ref().and_then(|h| h["version"].as_u64()) != Some(2) {
+            self.report_error(format_args!("record: {path}: not an asciicast v2 recording"));
+            return 1;
+        }
+        let started = Instant::now();
+        let mut previous = 0.0;
+        let mut position = 0.0;
+        for (number, line) in lines.enumerate() {
+            let Ok(line) = line else { break };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event: Option<(f64, String, String)> = serde_json::from_str(&line).ok();
+            let Some((time, kind, data)) = event else {
+                self.report_error(format_args!("record: {path}:{}: malformed event", number + 2));
+                return 1;
+            };
+            // Long pauses are shortened to `-i` seconds.
+            let gap = (time - previous).max(0.0);
+            position += max_idle.map_or(gap, |idle: f64| gap.min(idle));
+            previous = time;
+            if kind != "o" {
+                continue;
+            }
+            let due = started + Duration::from_secs_f64(position / speed);
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                std::thread::sleep(wait);
+            }
+            let mut out = self.fds.writer(1);
+            let _ = out.write_all(data.as_bytes());
+            let _ = out.flush();
+        }
+        0
+    }
+}
+
+fn write_all(fd: RawFd, mut bytes: &[u8]) {
+    while !bytes.is_empty() {
+        match nix::unistd::write(fd, bytes) {
+            Ok(n) => bytes = &bytes[n..],
+            Err(Errno::EINTR) => {}
+            Err(_) => return,
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! The `read` builtin.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::os::fd::RawFd;
+use std::time::{Duration, Instant};
+
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+
+use crate::vars::{self, Value};
+use crate::Shell;
+
+/// What one call to `read` asked for.
+struct ReadOptions {
+    raw: bool,
+    prompt: Option<String>,
+    timeout: Option<Duration>,
+    array: Option<String>,
+    delimiter: u8,
+    count: Option<usize>,
+    fd: i32,
+}
+
+/// How reading the line ended.
+enum Outcome {
+    /// The delimiter or the `-n` count was reached.
+    Complete,
+    EndOfFile,
+    TimedOut,
+}
+
+impl Shell {
+    /// `read [-r] [-p prompt] [-t timeout] [-a array] [-d delim] [-n count] [-u fd] [name...]`
+    pub(crate) fn builtin_read(&mut self, argv: &[String]) -> i32 {
+        let mut options = ReadOptions {
+            raw: false,
+            prompt: None,
+            timeout: None,
+            array: None,
+            delimiter: b'\n',
+            count: None,
+            fd: 0,
+        };
+        let mut i = 1;
+        while let Some(arg) = argv.get(i).filter(|a| a.len() > 1 && a.starts_with('-')) {
+            i += 1;
+            if arg == "--" {
+                break;
+            }
+            let mut flags = arg[1..].chars();
+            while let Some(flag) = flags.next() {
+                if flag == 'r' {
+                    options.raw = true;
+                    continue;
+                }
+                // Every other option takes a value, attached or as the next word.
+                let attached: String = flags.by_ref().collect();
+                let value = if attached.is_empty() {
+                    i += 1;
+                    match argv.get(i - 1) {
+                        Some(value) => value.clone(),
+                        None => {
+                            self.report_error(format_args!("read: -{flag}: option requires an argument"));
+                            return 2;
+                        }
+                    }
+                } else {
+                    attached
+                };
+                let result = match flag {
+                    'p' => {
+                        options.prompt = Some(value);
+                        Ok(())
+                    }
+                    'a' => {
+                        options.array = Some(value);
+                        Ok(())
+                    }
+                    'd' => {
+                        options.delimiter = value.bytes().next().unwrap_or(0);
+                        Ok(())
+                    }
+                    't' => value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|secs| *secs >= 0.0)
+                        .map(|secs| options.timeout = Some(Duration::from_secs_f64(secs)))
+                        .ok_or(format!("{value}: invalid timeout specification")),
+                    'n' => value
+                        .parse()
+                        .map(|n| options.count = Some(n))
+                        .map_err(|_| format!("{value}: invalid number")),
+                    'u' => value
+                        .parse()
+                        .map(|fd| options.fd = fd)
+                        .map_err(|_| format!("{value}: invalid file descriptor specification")),
+                    _ => Err(format!("-{flag}: invalid option")),
+                };
+                if let Err(e) = result {
+                    self.report_error(format_args!("read: {e}"));
+                    return 2;
+                }
+            }
+        }
+        let names = &argv[i..];
+        for name in names.iter().chain(options.array.iter()) {
+            if !vars::is_valid_name(name) {
+                self.report_error(format_args!("read: `{name}': not a valid identifier"));
+                return 1;
+            }
+            if let Err(e) = self.check_restricted_variable(name) {
+                self.report_error(format_args!("read: {e}"));
+                return 1;
+            }
+        }
+
+        let Some(fd) = self.fds.get(options.fd) else {
+            self.report_error(format_args!("read: {}: invalid file descriptor: Bad file descriptor", options.fd));
+            return 1;
+        };
+        if let Some(prompt) = &options.prompt
+            && nix::unistd::isatty(fd).unwrap_or(false)
+        {
+            let _ = write!(self.fds.writer(2), "{prompt}");
+        }
+
+        // `-t 0` only asks whether input is waiting.
+        if options.timeout == Some(Duration::ZERO) {
+            return (!wait_readable(fd, Some(Duration::ZERO))) as i32;
+        }
+
+        let (line, outcome) = read_line(fd, &options);
+        let ifs = self.env_vars.scalar("IFS").unwrap_or(" \t\n").to_string();
+        match (&options.array, names) {
+            (Some(array), _) => {
+                let fields = split(&line, &ifs, None);
+                let map: BTreeMap<usize, String> = fields.into_iter().enumerate().collect();
+                self.env_vars.set_value(array, Value::Indexed(map));
+            }
+            (None, []) => {
+                let text: Vec<u8> = line.iter().map(|&(b, _)| b).collect();
+                self.env_vars.set_scalar("REPLY", String::from_utf8_lossy(&text).into_owned());
+            }
+            (None, names) => {
+                let mut fields = split(&line, &ifs, Some(names.len())).into_iter();
+                for name in names {
+                    self.env_vars.set_scalar(name, fields.next().unwrap_or_default());
+                }
+            }
+        }
+        match outcome {
+            Outcome::Complete => 0,
+            Outcome::EndOfFile => 1,
+            Outcome::TimedOut => 128 + nix::libc::SIGALRM,
+        }
+    }
+}
+
+/// Reads up to the delimiter a byte at a time, so that nothing past the line
+/// is consumed from a shared descriptor. Each byte comes with whether a
+/// backslash escaped it.
+fn read_line(fd: RawFd, options: &ReadOptions) -> (Vec<(u8, bool)>, Outcome) {
+    let deadline = options.timeout.map(|t| Instant::now() + t);
+    let mut line = Vec::new();
+    let mut escaped = false;
+    loop {
+        if options.count.is_some_and(|n| line.len() >= n) {
+            return (line, Outcome::Complete);
+        }
+        let remaining = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+        if remaining.is_some() && !wait_readable(fd, remaining) {
+            return (line, Outcome::TimedOut);
+        }
+        let mut byte = [0u8];
+        match nix::unistd::read(fd, &mut byte) {
+            Ok(0) => return (line, Outcome::EndOfFile),
+            Ok(_) => {}
+            Err(Errno::EINTR) => continue,
+            Err(_) => return (line, Outcome::EndOfFile),
+        }
+        let b = byte[0];
+        if escaped {
+            escaped = false;
+            // A backslash-newline joins the next line onto this one.
+            if b != b'\n' {
+                line.push((b, true));
+            }
+            continue;
+        }
+        if b == b'\\' && !options.raw {
+            escaped = true;
+            continue;
+        }
+        if b == options.delimiter {
+            return (line, Outcome::Complete);
+        }
+        line.push((b, false));
+    }
+}
+
+fn wait_readable(fd: RawFd, timeout: Option<Duration>) -> bool {
+    let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+    let millis = timeout.map_or(-1, |t| t.as_millis().min(i32::MAX as u128) as i32);
+    matches!(poll(&mut fds, millis), Ok(n) if n > 0)
+}
+
+/// Splits a line into fields on IFS. Runs of IFS whitespace separate fields
+/// and are trimmed from both ends; every other IFS character ends exactly one
+/// field. With `limit`, the last field takes the rest of the line.
+fn split(line: &[(u8, bool)], ifs: &str, limit: Option<usize>) -> Vec<String> {
+    let is_ifs = |(b, escaped): (u8, bool)| !escaped && ifs.as_bytes().contains(&b);
+    let is_space = |item: (u8, bool)| is_ifs(item) && matches!(item.0, b' ' | b'\t' | b'\n');
+    let text = |items: &[(u8, bool)]| {
+        let bytes: Vec<u8> = items.iter().map(|&(b, _)| b).collect();
+        String::from_utf8_lossy(&bytes).into_owned()
+    };
+
+    let mut fields = Vec::new();
+    let mut i = 0;
+    while i < line.len() && is_space(line[i]) {
+        i += 1;
+    }
+    while i < line.len() {
+        if limit.is_some_and(|n| fields.len() + 1 == n) {
+            let mut end = line.len();
+            while end > i && is_space(line[end - 1]) {
+                end -= 1;
+            }
+            fields.push(text(&line[i..end]));
+            return fields;
+        }
+        let start = i;
+        while i < line.len() && !is_ifs(line[i]) {
+            i += 1;
+        }
+        fields.push(text(&line[start..i]));
+        // Consume one delimiter: surrounding whitespace plus at most one
+        // non-whitespace IFS character.
+        while i < line.len() && is_space(line[i]) {
+            i += 1;
+        }
+        if i < line.len() && is_ifs(line[i]) && !is_space(line[i]) {
+            i += 1;
+            while i < line.len() && is_space(line[i]) {
+                i += 1;
+            }
+        }
+    }
+    fields
+}
@@ -0,0 +1,450 @@
+//! Remote sessions: `vssh --listen ADDR` serves an interactive shell on a
+//! pseudo-terminal to each client, and `vssh --connect ADDR` is the client.
+//!
+//! An address is `host:port` for TCP or `unix:PATH` (or any path containing
+//! a slash) for a Unix socket. Both sides share a secret, taken from
+//! `$VSSH_SECRET` or `--secret-file`. Nothing is encrypted, so over TCP this
+//! is meant for localhost and trusted lab networks.
+//!
+//! Traffic is a sequence of frames: a kind byte, a big-endian u32 length and
+//! the payload. The client opens with a hello carrying the secret, its TERM
+//! and window size; the server answers OK or an error, then both sides
+//! exchange terminal data until the server reports the session's exit status.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::time::Duration;
+
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::pty::Winsize;
+use nix::sys::wait::{waitpid, WaitPidFlag};
+use nix::unistd::Pid;
+use serde_json::json;
+
+use crate::pty::{self, RawMode};
+use crate::signals;
+use crate::Shell;
+
+const HELLO: u8 = b'H';
+const OK: u8 = b'O';
+const ERROR: u8 = b'E';
+const DATA: u8 = b'D';
+const RESIZE: u8 = b'W';
+const EXIT: u8 = b'X';
+
+/// Frames larger than this are refused rather than buffered.
+const MAX_FRAME: usize = 1 << 20;
+
+/// How long a client has to send its hello before it is dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+trait Stream: Read + Write + AsRawFd {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Stream for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+impl Stream for UnixStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl Listener {
+    fn bind(addr: &str) -> io::Result<Listener> {
+        match unix_path(addr) {
+            Some(path) => {
+                // A socket left behind by an earlier server would make bind fail.
+                if std::fs::metadata(path).is_ok_and(|m| std::os::unix::fs::FileTypeExt::is_socket(&m.file_type())) {
+                    let _ = std::fs::remove_file(path);
+                }
+                UnixListener::bind(path).map(Listener::Unix)
+            }
+            None => TcpListener::bind(addr).map(Listener::Tcp),
+        }
+    }
+
+    fn accept(&self) -> io::Result<(Box<dyn Stream>, String)> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, peer) = listener.accept()?;
+                let _ = stream.set_nodelay(true);
+                Ok((Box::new(stream), peer.to_string()))
+            }
+            Listener::Unix(listener) => {
+                let (stream, _) = listener.accept()?;
+                Ok((Box::new(stream), "local client".to_string()))
+            }
+        }
+    }
+
+    /// The address actually bound, with the port chosen for port 0.
+    fn describe(&self, addr: &str) -> String {
+        match self {
+            Listener::Tcp(listener) => listener.local_addr().map_or_else(|_| addr.to_string(), |a| a.to_string()),
+            Listener::Unix(_) => addr.to_string(),
+        }
+    }
+
+    fn raw_fd(&self) -> RawFd {
+        match self {
+            Listener::Tcp(listener) => listener.as_raw_fd(),
+            Listener::Unix(listener) => listener.as_raw_fd(),
+        }
+    }
+}
+
+fn unix_path(addr: &str) -> Option<&str> {
+    addr.strip_prefix("unix:").or_else(|| addr.contains('/').then_some(addr))
+}
+
+fn open(addr: &str) -> io::Result<Box<dyn Stream>> {
+    match unix_path(addr) {
+        Some(path) => Ok(Box::new(UnixStream::connect(path)?)),
+        None => {
+            let stream = TcpStream::connect(addr)?;
+            let _ = stream.set_nodelay(true);
+            Ok(Box::new(stream))
+        }
+    }
+}
+
+fn send(stream: &mut dyn Stream, kind: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 5);
+    frame.push(kind);
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    stream.write_all(&frame)
+}
+
+/// Collects bytes from a stream and hands out whole frames.
+#[derive(Default)]
+struct Frames {
+    buf: Vec<u8>,
+}
+
+impl Frames {
+    /// Reads what the stream has. Returns false at end of stream.
+    fn fill(&mut self, stream: &mut dyn Stream) -> io::Result<bool> {
+        let mut chunk = [0u8; 8192];
+        loop {
+            match stream.read(&mut chunk) {
+                Ok(0) => return Ok(false),
+                Ok(n) => {
+                    self.buf.extend_from_slice(&chunk[..n]);
+                    return Ok(true);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn next(&mut self) -> io::Result<Option<(u8, Vec<u8>)>> {
+        if self.buf.len() < 5 {
+            return Ok(None);
+        }
+        let len = u32::from_be_bytes([self.buf[1], self.buf[2], self.buf[3], self.buf[4]]) as usize;
+        if len > MAX_FRAME {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too large"));
+        }
+        if self.buf.len() < 5 + len {
+            return Ok(None);
+        }
+        let kind = self.buf[0];
+        let payload = self.buf[5..5 + len].to_vec();
+        self.buf.drain(..5 + len);
+        Ok(Some((kind, payload)))
+    }
+
+    /// Blocks until a whole frame has arrived.
+    fn read(&mut self, stream: &mut dyn Stream) -> io::Result<(u8, Vec<u8>)> {
+        loop {
+            if let Some(frame) = self.next()? {
+                return Ok(frame);
+            }
+            if !self.fill(stream)? {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+}
+
+fn encode_size(size: &Winsize) -> Vec<u8> {
+    [size.ws_row.to_be_bytes(), size.ws_col.to_be_bytes()].concat()
+}
+
+fn decode_size(payload: &[u8]) -> Option<Winsize> {
+    let [r0, r1, c0, c1] = payload.try_into().ok()?;
+    Some(Winsize {
+        ws_row: u16::from_be_bytes([r0, r1]),
+        ws_col: u16::from_be_bytes([c0, c1]),
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    })
+}
+
+/// Compares secrets in time that does not depend on where they differ.
+fn same_secret(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn ready(fd: &PollFd) -> bool {
+    fd.revents().is_some_and(|r| !r.is_empty())
+}
+
+impl Shell {
+    /// Accepts connections on `addr` until killed, serving each from its own
+    /// process.
+    pub fn serve(&mut self, addr: &str, secret: String) -> i32 {
+        let listener = match Listener::bind(addr) {
+            Ok(listener) => listener,
+            Err(e) => {
+                self.report_error(format_args!("{addr}: {e}"));
+                return 1;
+            }
+        };
+        eprintln!("vssh: listening on {}", listener.describe(addr));
+        loop {
+            let (stream, peer) = match listener.accept() {
+                Ok(connection) => connection,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.report_error(format_args!("accept: {e}"));
+                    return 1;
+                }
+            };
+            // Collect sessions that have ended since the last connection.
+            while let Ok(status) = waitpid(Pid::from_raw(-1), Some(WaitPidFlag::WNOHANG)) {
+                if status.pid().is_none() {
+                    break;
+                }
+            }
+            let secret = secret.clone();
+            let _ = self.fork_subshell(Some(listener.raw_fd()), move |shell| shell.serve_client(stream, &peer, &secret));
+        }
+    }
+
+    /// Runs in a process of its own for one connection.
+    fn serve_client(&mut self, mut stream: Box<dyn Stream>, peer: &str, secret: &str) -> i32 {
+        let mut frames = Frames::default();
+        // A client that connects and says nothing must not hold a process
+        // forever.
+        let _ = stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT));
+        let hello = match frames.read(stream.as_mut()) {
+            Ok((HELLO, payload)) => serde_json::from_slice::<serde_json::Value>(&payload).ok(),
+            _ => None,
+        };
+        let Some(hello) = hello else {
+            eprintln!("vssh: {peer}: bad hello");
+            return 1;
+        };
+        if stream.set_read_timeout(None).is_err() {
+            return 1;
+        }
+        let offered = hello["secret"].as_str().unwrap_or_default();
+        if !same_secret(offered.as_bytes(), secret.as_bytes()) {
+            eprintln!("vssh: {peer}: authentication failed");
+            let _ = send(stream.as_mut(), ERROR, b"authentication failed");
+            return 1;
+        }
+        eprintln!("vssh: {peer}: session started");
+        if let Some(term) = hello["term"].as_str().filter(|t| !t.is_empty()) {
+            self.env_vars.set_scalar("TERM", term.to_string());
+            self.env_vars.set_exported("TERM", true);
+        }
+        let size = Winsize {
+            ws_row: hello["rows"].as_u64().unwrap_or(24) as u16,
+            ws_col: hello["cols"].as_u64().unwrap_or(80) as u16,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+
+        let socket = stream.as_raw_fd();
+        let session = self.fork_on_pty(Some(size), None, move |shell| {
+            let _ = nix::unistd::close(socket);
+            shell.interactive = true;
+            println!("Simple Rust Shell - Type 'exit' to quit");
+            shell.run();
+            shell.run_exit_trap();
+            shell.last_status
+        });
+        let (master, pid) = match session {
+            Ok(session) => session,
+            Err(e) => {
+                let _ = send(stream.as_mut(), ERROR, e.as_bytes());
+                return 1;
+            }
+        };
+        if send(stream.as_mut(), OK, b"").is_err() {
+            return 1;
+        }
+
+        let master_fd = master.as_raw_fd();
+        let mut buf = [0u8; 8192];
+        'session: loop {
+            let mut fds = [PollFd::new(master_fd, PollFlags::POLLIN), PollFd::new(socket, PollFlags::POLLIN)];
+            match poll(&mut fds, -1) {
+                Ok(_) => {}
+                Err(Errno::EINTR) => continue,
+                Err(_) => break,
+            }
+            if ready(&fds[0]) {
+                match nix::unistd::read(master_fd, &mut buf) {
+                    Ok(0) | Err(Errno::EIO) => break,
+                    Ok(n) => {
+                        if send(stream.as_mut(), DATA, &buf[..n]).is_err() {
+                            break;
+                        }
+                    }
+                    Err(Errno::EINTR | Errno::EAGAIN) => {}
+                    Err(_) => break,
+                }
+            }
+            if ready(&fds[1]) {
+                if !matches!(frames.fill(stream.as_mut()), Ok(true)) {
+                    break;
+                }
+                loop {
+                    match frames.next() {
+                        Ok(Some((DATA, payload))) => {
+                            if nix::unistd::write(master_fd, &payload).is_err() {
+                                break 'session;
+                            }
+                        }
+                        Ok(Some((RESIZE, payload))) => {
+                            if let Some(size) = decode_size(&payload) {
+                                pty::set_window_size(master_fd, &size);
+                            }
+                        }
+                        Ok(Some(_)) => {}
+                        Ok(None) => break,
+                        Err(_) => break 'session,
+                    }
+                }
+            }
+        }
+        // A client that went away leaves the shell to its hangup signal.
+        drop(master);
+        let status = self.wait_for(pid);
+        let _ = send(stream.as_mut(), EXIT, &status.to_be_bytes());
+        eprintln!("vssh: {peer}: session ended with status {status}");
+        0
+    }
+}
+
+/// Runs the client side: puts the terminal into raw mode and relays it to
+/// the remote shell. Returns the remote session's exit status.
+pub fn connect(addr: &str, secret: &str) -> i32 {
+    let mut stream = match open(addr) {
+        Ok(stream) => stream,
+        Err(e) => {
+            eprintln!("vssh: {addr}: {e}");
+            return 1;
+        }
+    };
+    let terminal = nix::unistd::isatty(0).unwrap_or(false).then_some(0);
+    let size = terminal.and_then(pty::window_size);
+    let hello = json!({
+        "secret": secret,
+        "term": std::env::var("TERM").unwrap_or_default(),
+        "rows": size.map_or(24, |s| s.ws_row),
+        "cols": size.map_or(80, |s| s.ws_col),
+    });
+    let mut frames = Frames::default();
+    let reply = send(stream.as_mut(), HELLO, hello.to_string().as_bytes()).and_then(|()| frames.read(stream.as_mut()));
+    match reply {
+        Ok((OK, _)) => {}
+        Ok((ERROR, message)) => {
+            eprintln!("vssh: {addr}: {}", String::from_utf8_lossy(&message));
+            return 1;
+        }
+        Ok(_) => {
+            eprintln!("vssh: {addr}: unexpected reply");
+            return 1;
+        }
+        Err(e) => {
+            eprintln!("vssh: {addr}: {e}");
+            return 1;
+        }
+    }
+
+    let raw_mode = terminal.and_then(RawMode::enter);
+    signals::watch_window_size();
+    let socket = stream.as_raw_fd();
+    let mut input = Some(0);
+    let mut buf = [0u8; 8192];
+    let mut status = 1;
+    'session: loop {
+        if signals::window_resized()
+            && let Some(size) = terminal.and_then(pty::window_size)
+            && send(stream.as_mut(), RESIZE, &encode_size(&size)).is_err()
+        {
+            break;
+        }
+        let mut fds = vec![PollFd::new(socket, PollFlags::POLLIN)];
+        if let Some(fd) = input {
+            fds.push(PollFd::new(fd, PollFlags::POLLIN));
+        }
+        // A resize interrupts the wait; it is sent at the top of the loop.
+        match poll(&mut fds, -1) {
+            Ok(_) => {}
+            Err(Errno::EINTR) => continue,
+            Err(_) => break,
+        }
+        if fds.get(1).is_some_and(ready) {
+            match nix::unistd::read(0, &mut buf) {
+                Ok(n) if n > 0 => {
+                    if send(stream.as_mut(), DATA, &buf[..n]).is_err() {
+                        break;
+                    }
+                }
+                Err(Errno::EINTR | Errno::EAGAIN) => {}
+                // Without a terminal, end of input becomes the end-of-file key.
+                _ => {
+                    input = None;
+                    if send(stream.as_mut(), DATA, b"\x04").is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+        if ready(&fds[0]) {
+            if !matches!(frames.fill(stream.as_mut()), Ok(true)) {
+                break;
+            }
+            loop {
+                match frames.next() {
+                    Ok(Some((DATA, payload))) => {
+                        let mut out = io::stdout();
+                        let _ = out.write_all(&payload);
+                        let _ = out.flush();
+                    }
+                    Ok(Some((EXIT, payload))) => {
+                        if let Ok(bytes) = payload.try_into() {
+                            status = i32::from_be_bytes(bytes);
+                        }
+                        break 'session;
+                    }
+                    Ok(Some(_)) => {}
+                    Ok(None) => break,
+                    Err(_) => break 'session,
+                }
+            }
+        }
+    }
+    drop(raw_mode);
+    status
+}
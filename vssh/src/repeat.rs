@@ -0,0 +1,257 @@
+//! The `repeat` and `every` builtins: run a command over and over.
+//!
+//! `repeat -n N` runs it N times in a row and `every INTERVAL` runs it at
+//! that interval until Ctrl-C, or N times with `-n`. The operands are joined
+//! with spaces, as `eval` does, and parsed once into a command list, such as
+//! the pipeline in `every 2 'ps | wc -l'`, which each run then expands and
+//! runs afresh. Each run is a forked subshell, and one of the shell's jobs
+//! until it ends. With `-d`, every run after the first
+//! prints only how its output differs from the one before, as lines marked
+//! `-` and `+` among unchanged lines marked with a space. The status is that
+//! of the last run.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::OwnedFd;
+use std::time::{Duration, Instant};
+
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+
+use crate::io;
+use crate::parser::{self, List};
+use crate::signals;
+use crate::timeout::parse_duration;
+use crate::Shell;
+
+impl Shell {
+    /// `repeat [-d] -n N [-i INTERVAL] command [arg...]` and
+    /// `every [-d] [-n N] INTERVAL command [arg...]`
+    pub(crate) fn builtin_repeat(&mut self, argv: &[String]) -> i32 {
+        let name = argv[0].as_str();
+        let every = name == "every";
+        let usage = if every {
+            "every [-d] [-n count] interval command [arg...]"
+        } else {
+            "repeat [-d] -n count [-i interval] command [arg...]"
+        };
+        let mut diff = false;
+        let mut count = None;
+        let mut interval = Duration::ZERO;
+        let mut i = 1;
+        while let Some(arg) = argv.get(i).filter(|a| a.len() > 1 && a.starts_with('-')) {
+            i += 1;
+            match arg.as_str() {
+                "--" => break,
+                "-d" => diff = true,
+                "-n" => match argv.get(i).and_then(|n| n.parse::<usize>().ok()) {
+                    Some(n) => {
+                        count = Some(n);
+                        i += 1;
+                    }
+                    None => {
+                        self.report_error(format_args!("{name}: -n: a count is required"));
+                        return 2;
+                    }
+                },
+                "-i" if !every => match argv.get(i).and_then(|d| parse_duration(d)) {
+                    Some(d) => {
+                        interval = d;
+                        i += 1;
+                    }
+                    None => {
+                        self.report_error(format_args!("{name}: -i: a time interval is required"));
+                        return 2;
+                    }
+                },
+                _ => {
+                    self.report_error(format_args!("{name}: {arg}: invalid option"));
+                    return 2;
+                }
+            }
+        }
+        if every {
+            let Some(d) = argv.get(i).and_then(|d| parse_duration(d)) else {
+                self.report_error(format_args!("{name}: usage: {usage}"));
+                return 2;
+            };
+            interval = d;
+            i += 1;
+        } else if count.is_none() {
+            self.report_error(format_args!("{name}: usage: {usage}"));
+            return 2;
+        }
+        if i >= argv.len() {
+            self.report_error(format_args!("{name}: usage: {usage}"));
+            return 2;
+        }
+        let text = argv[i..].join(" ");
+        let list = match parser::parse(&text) {
+            Ok(list) => list,
+            Err(e) => {
+                self.report_error(format_args!("{name}: {e}"));
+                return 2;
+            }
+        };
+        self.run_repeatedly(&list, &text, count, interval, diff)
+    }
+
+    /// Runs `list`, whose source is `text`, `count` times or until Ctrl-C.
+    fn run_repeatedly(&mut self, list: &List, text: &str, count: Option<usize>, interval: Duration, diff: bool) -> i32 {
+        let mut status = 0;
+        let mut previous: Option<String> = None;
+        let mut last_start: Option<Instant> = None;
+        let mut run = 0;
+        while count.is_none_or(|count| run < count) {
+            // The interval runs from the start of one run to the next.
+            if let Some(start) = last_start
+                && !pause(interval.saturating_sub(start.elapsed()))
+            {
+                break;
+            }
+            last_start = Some(Instant::now());
+            run += 1;
+            if !diff {
+                status = match self.start_run(list, text, None) {
+                    Ok(job) => self.wait_foreground_job(job),
+                    Err(status) => status,
+                };
+            } else {
+                let (read, write) = match io::pipe() {
+                    Ok(pipe) => pipe,
+                    Err(e) => {
+                        self.report_error(format_args!("Failed to create pipe: {}", e));
+                        return 1;
+                    }
+                };
+                let started = self.start_run(list, text, Some(write));
+                let mut output = Vec::new();
+                let _ = File::from(read).read_to_end(&mut output);
+                status = match started {
+                    Ok(job) => self.wait_foreground_job(job),
+                    Err(status) => status,
+                };
+                let output = String::from_utf8_lossy(&output).into_owned();
+                let mut out = self.fds.writer(1);
+                let _ = match &previous {
+                    None => out.write_all(output.as_bytes()),
+                    Some(before) => out.write_all(diff_lines(before, &output).as_bytes()),
+                };
+                previous = Some(output);
+            }
+            if signals::interrupt_pending() {
+                break;
+            }
+        }
+        status
+    }
+
+    /// Starts `list` in a forked subshell, with `stdout` as its output if
+    /// given, and returns its job.
+    fn start_run(&mut self, list: &List, text: &str, stdout: Option<OwnedFd>) -> Result<usize, i32> {
+        let saved = stdout.map(|stdout| {
+            let mut fds = self.fds.clone();
+            fds.set(1, stdout);
+            std::mem::replace(&mut self.fds, fds)
+        });
+        let child = self.fork_subshell(None, |shell| shell.execute(list));
+        // Dropping the subshell's table closes the shell's end of the pipe,
+        // so that reading it ends when the subshell exits.
+        if let Some(saved) = saved {
+            self.fds = saved;
+        }
+        Ok(self.jobs.add(vec![child?], text))
+    }
+}
+
+/// Sleeps for `duration`, and returns false if Ctrl-C cut it short.
+fn pause(duration: Duration) -> bool {
+    let end = Instant::now() + duration;
+    let mut interrupt = signals::interrupt_fd();
+    loop {
+        if signals::interrupt_pending() {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= end {
+            return true;
+        }
+        let millis = (end - now).as_micros().div_ceil(1000).min(i32::MAX as u128) as i32;
+        let Some(fd) = interrupt else {
+            std::thread::sleep(end - now);
+            continue;
+        };
+        let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+        match poll(&mut fds, millis) {
+            Ok(_) | Err(Errno::EINTR) => {}
+            Err(_) => std::thread::sleep(end - now),
+        }
+        if fds[0].revents().is_some_and(|r| !r.is_empty()) {
+            interrupt = signals::interrupt_fd();
+        }
+    }
+}
+
+/// The most cells [`diff_lines`] compares line by line, bounding its table
+/// to 16 MiB.
+const MAX_DIFF_CELLS: usize = 1 << 22;
+
+/// Shows `after` line by line against `before`: unchanged lines start with a
+/// space, removed ones with `-` and added ones with `+`.
+fn diff_lines(before: &str, after: &str) -> String {
+    let old: Vec<&str> = before.lines().collect();
+    let new: Vec<&str> = after.lines().collect();
+    // Lines the two share at either end are unchanged without comparing the
+    // rest, which leaves only the part in between to diff.
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let mut out = String::new();
+    for line in &old[..prefix] {
+        out.push_str(&format!(" {line}\n"));
+    }
+    diff_middle(&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix], &mut out);
+    for line in &old[old.len() - suffix..] {
+        out.push_str(&format!(" {line}\n"));
+    }
+    out
+}
+
+/// Diffs lines that differ at both ends, by their longest common
+/// subsequence, or shows them all as replaced if there are too many.
+fn diff_middle(old: &[&str], new: &[&str], out: &mut String) {
+    if old.len().saturating_mul(new.len()) > MAX_DIFF_CELLS {
+        for line in old {
+            out.push_str(&format!("-{line}\n"));
+        }
+        for line in new {
+            out.push_str(&format!("+{line}\n"));
+        }
+        return;
+    }
+    // common[i][j] is the length of the longest common subsequence of
+    // old[i..] and new[j..].
+    let mut common = vec![vec![0u32; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            out.push_str(&format!(" {}\n", old[i]));
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && common[i + 1][j] >= common[i][j + 1]) {
+            out.push_str(&format!("-{}\n", old[i]));
+            i += 1;
+        } else {
+            out.push_str(&format!("+{}\n", new[j]));
+            j += 1;
+        }
+    }
+}
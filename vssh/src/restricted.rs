@@ -0,0 +1,57 @@
+//! Restricted mode (`vssh -r`, `set -r`), for handing the shell to users who
+//! should stay where they were put. Like bash's rbash, a restricted shell
+//! refuses to change directory, to change the variables that decide what
+//! runs, to run commands by path, and to write files through redirections.
+//! Once on, it cannot be turned off.
+
+use crate::parser::RedirOp;
+use crate::Shell;
+
+/// Variables a restricted shell treats as read-only.
+const PROTECTED_VARIABLES: &[&str] = &["PATH", "SHELL", "ENV", "HISTFILE"];
+
+/// Builtins a restricted shell refuses to run. `pty` and `record` are among
+/// them because a terminal session could start an unrestricted shell.
+const FORBIDDEN_BUILTINS: &[&str] = &["cd", "pushd", "popd", "exec", "isolate", "pty", "record", "sandbox", "vsshenv"];
+
+impl Shell {
+    pub(crate) fn restricted(&self) -> bool {
+        self.options.get("restricted")
+    }
+
+    /// Checks a command name before it is looked up.
+    pub(crate) fn check_restricted_command(&self, name: &str) -> Result<(), String> {
+        if !self.restricted() {
+            return Ok(());
+        }
+        if name.contains('/') {
+            return Err(format!("{name}: restricted: cannot specify `/' in command names"));
+        }
+        if FORBIDDEN_BUILTINS.contains(&name) {
+            return Err(format!("{name}: restricted"));
+        }
+        Ok(())
+    }
+
+    /// Checks a variable before it is assigned, unset or given new attributes.
+    pub(crate) fn check_restricted_variable(&self, name: &str) -> Result<(), String> {
+        if self.restricted() && PROTECTED_VARIABLES.contains(&name) {
+            return Err(format!("{name}: readonly variable"));
+        }
+        Ok(())
+    }
+
+    /// Checks a redirection before its file is opened. Anything that opens a
+    /// file for writing is refused; duplicating and closing descriptors is not.
+    pub(crate) fn check_restricted_redirect(&self, op: RedirOp, target: &str) -> Result<(), String> {
+        let writes = match op {
+            RedirOp::In | RedirOp::DupIn => false,
+            RedirOp::DupOut => target != "-" && target.parse::<i32>().is_err(),
+            _ => true,
+        };
+        if self.restricted() && writes {
+            return Err(format!("{target}: restricted: cannot redirect output"));
+        }
+        Ok(())
+    }
+}
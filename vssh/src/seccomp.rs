@@ -0,0 +1,503 @@
+//! Seccomp filters for spawned commands, set up with `vssh --seccomp-profile`
+//! or the `sandbox` builtin.
+//!
+//! A profile is a text file with one directive per line; blank lines and
+//! `#` comments are ignored:
+//!
+//! ```text
+//! allow read write openat close execve exit_group   # only these may run
+//! deny ptrace mount                                 # or: everything but these
+//! action errno EPERM                                # or `action kill`, the default
+//! ```
+//!
+//! A profile allows or denies, never both. System calls are named as in
+//! `<sys/syscall.h>` or given by number. The filter is installed in the child
+//! between fork and exec, so `execve` is always allowed. A command killed for
+//! a violation exits with status 159 (128 + SIGSYS).
+
+use std::io::{self, Write};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Command as Process;
+use std::sync::Arc;
+
+use nix::libc;
+
+use crate::Shell;
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+/// Offsets into `struct seccomp_data`.
+const SYSCALL_NR: u32 = 0;
+const ARCH: u32 = 4;
+
+/// Looks up a system call number by name.
+fn syscall_number(name: &str) -> Option<libc::c_long> {
+    let number = match name {
+        "read" => libc::SYS_read,
+        "write" => libc::SYS_write,
+        "openat" => libc::SYS_openat,
+        "close" => libc::SYS_close,
+        "fstat" => libc::SYS_fstat,
+        "newfstatat" => libc::SYS_newfstatat,
+        "statx" => libc::SYS_statx,
+        "lseek" => libc::SYS_lseek,
+        "mmap" => libc::SYS_mmap,
+        "mprotect" => libc::SYS_mprotect,
+        "munmap" => libc::SYS_munmap,
+        "mremap" => libc::SYS_mremap,
+        "madvise" => libc::SYS_madvise,
+        "brk" => libc::SYS_brk,
+        "rt_sigaction" => libc::SYS_rt_sigaction,
+        "rt_sigprocmask" => libc::SYS_rt_sigprocmask,
+        "rt_sigreturn" => libc::SYS_rt_sigreturn,
+        "ioctl" => libc::SYS_ioctl,
+        "pread64" => libc::SYS_pread64,
+        "pwrite64" => libc::SYS_pwrite64,
+        "readv" => libc::SYS_readv,
+        "writev" => libc::SYS_writev,
+        "sched_yield" => libc::SYS_sched_yield,
+        "dup" => libc::SYS_dup,
+        "dup3" => libc::SYS_dup3,
+        "nanosleep" => libc::SYS_nanosleep,
+        "clock_nanosleep" => libc::SYS_clock_nanosleep,
+        "getpid" => libc::SYS_getpid,
+        "gettid" => libc::SYS_gettid,
+        "getppid" => libc::SYS_getppid,
+        "socket" => libc::SYS_socket,
+        "socketpair" => libc::SYS_socketpair,
+        "connect" => libc::SYS_connect,
+        "accept" => libc::SYS_accept,
+        "accept4" => libc::SYS_accept4,
+        "sendto" => libc::SYS_sendto,
+        "recvfrom" => libc::SYS_recvfrom,
+        "sendmsg" => libc::SYS_sendmsg,
+        "recvmsg" => libc::SYS_recvmsg,
+        "shutdown" => libc::SYS_shutdown,
+        "bind" => libc::SYS_bind,
+        "listen" => libc::SYS_listen,
+        "getsockname" => libc::SYS_getsockname,
+        "getpeername" => libc::SYS_getpeername,
+        "setsockopt" => libc::SYS_setsockopt,
+        "getsockopt" => libc::SYS_getsockopt,
+        "clone" => libc::SYS_clone,
+        "clone3" => libc::SYS_clone3,
+        "execve" => libc::SYS_execve,
+        "execveat" => libc::SYS_execveat,
+        "exit" => libc::SYS_exit,
+        "exit_group" => libc::SYS_exit_group,
+        "wait4" => libc::SYS_wait4,
+        "waitid" => libc::SYS_waitid,
+        "kill" => libc::SYS_kill,
+        "tkill" => libc::SYS_tkill,
+        "tgkill" => libc::SYS_tgkill,
+        "uname" => libc::SYS_uname,
+        "fcntl" => libc::SYS_fcntl,
+        "flock" => libc::SYS_flock,
+        "fsync" => libc::SYS_fsync,
+        "fdatasync" => libc::SYS_fdatasync,
+        "truncate" => libc::SYS_truncate,
+        "ftruncate" => libc::SYS_ftruncate,
+        "getcwd" => libc::SYS_getcwd,
+        "chdir" => libc::SYS_chdir,
+        "fchdir" => libc::SYS_fchdir,
+        "fchmod" => libc::SYS_fchmod,
+        "fchmodat" => libc::SYS_fchmodat,
+        "fchown" => libc::SYS_fchown,
+        "fchownat" => libc::SYS_fchownat,
+        "umask" => libc::SYS_umask,
+        "gettimeofday" => libc::SYS_gettimeofday,
+        "settimeofday" => libc::SYS_settimeofday,
+        "clock_gettime" => libc::SYS_clock_gettime,
+        "clock_settime" => libc::SYS_clock_settime,
+        "prlimit64" => libc::SYS_prlimit64,
+        "getrusage" => libc::SYS_getrusage,
+        "sysinfo" => libc::SYS_sysinfo,
+        "times" => libc::SYS_times,
+        "ptrace" => libc::SYS_ptrace,
+        "getuid" => libc::SYS_getuid,
+        "getgid" => libc::SYS_getgid,
+        "geteuid" => libc::SYS_geteuid,
+        "getegid" => libc::SYS_getegid,
+        "setuid" => libc::SYS_setuid,
+        "setgid" => libc::SYS_setgid,
+        "setreuid" => libc::SYS_setreuid,
+        "setregid" => libc::SYS_setregid,
+        "setresuid" => libc::SYS_setresuid,
+        "setresgid" => libc::SYS_setresgid,
+        "getgroups" => libc::SYS_getgroups,
+        "setgroups" => libc::SYS_setgroups,
+        "setpgid" => libc::SYS_setpgid,
+        "getpgid" => libc::SYS_getpgid,
+        "setsid" => libc::SYS_setsid,
+        "getsid" => libc::SYS_getsid,
+        "capget" => libc::SYS_capget,
+        "capset" => libc::SYS_capset,
+        "personality" => libc::SYS_personality,
+        "statfs" => libc::SYS_statfs,
+        "fstatfs" => libc::SYS_fstatfs,
+        "getpriority" => libc::SYS_getpriority,
+        "setpriority" => libc::SYS_setpriority,
+        "prctl" => libc::SYS_prctl,
+        "chroot" => libc::SYS_chroot,
+        "pivot_root" => libc::SYS_pivot_root,
+        "sync" => libc::SYS_sync,
+        "syncfs" => libc::SYS_syncfs,
+        "mount" => libc::SYS_mount,
+        "umount2" => libc::SYS_umount2,
+        "swapon" => libc::SYS_swapon,
+        "swapoff" => libc::SYS_swapoff,
+        "reboot" => libc::SYS_reboot,
+        "sethostname" => libc::SYS_sethostname,
+        "setdomainname" => libc::SYS_setdomainname,
+        "init_module" => libc::SYS_init_module,
+        "finit_module" => libc::SYS_finit_module,
+        "delete_module" => libc::SYS_delete_module,
+        "kexec_load" => libc::SYS_kexec_load,
+        "futex" => libc::SYS_futex,
+        "sched_setaffinity" => libc::SYS_sched_setaffinity,
+        "sched_getaffinity" => libc::SYS_sched_getaffinity,
+        "getdents64" => libc::SYS_getdents64,
+        "set_tid_address" => libc::SYS_set_tid_address,
+        "set_robust_list" => libc::SYS_set_robust_list,
+        "mkdirat" => libc::SYS_mkdirat,
+        "mknodat" => libc::SYS_mknodat,
+        "unlinkat" => libc::SYS_unlinkat,
+        "renameat2" => libc::SYS_renameat2,
+        "linkat" => libc::SYS_linkat,
+        "symlinkat" => libc::SYS_symlinkat,
+        "readlinkat" => libc::SYS_readlinkat,
+        "faccessat" => libc::SYS_faccessat,
+        "faccessat2" => libc::SYS_faccessat2,
+        "pselect6" => libc::SYS_pselect6,
+        "ppoll" => libc::SYS_ppoll,
+        "unshare" => libc::SYS_unshare,
+        "setns" => libc::SYS_setns,
+        "splice" => libc::SYS_splice,
+        "tee" => libc::SYS_tee,
+        "epoll_create1" => libc::SYS_epoll_create1,
+        "epoll_ctl" => libc::SYS_epoll_ctl,
+        "epoll_pwait" => libc::SYS_epoll_pwait,
+        "utimensat" => libc::SYS_utimensat,
+        "timerfd_create" => libc::SYS_timerfd_create,
+        "eventfd2" => libc::SYS_eventfd2,
+        "pipe2" => libc::SYS_pipe2,
+        "inotify_init1" => libc::SYS_inotify_init1,
+        "getrandom" => libc::SYS_getrandom,
+        "memfd_create" => libc::SYS_memfd_create,
+        "bpf" => libc::SYS_bpf,
+        "pidfd_open" => libc::SYS_pidfd_open,
+        "pidfd_send_signal" => libc::SYS_pidfd_send_signal,
+        "close_range" => libc::SYS_close_range,
+        "rseq" => libc::SYS_rseq,
+        "seccomp" => libc::SYS_seccomp,
+        "keyctl" => libc::SYS_keyctl,
+        "add_key" => libc::SYS_add_key,
+        "request_key" => libc::SYS_request_key,
+        "perf_event_open" => libc::SYS_perf_event_open,
+        "userfaultfd" => libc::SYS_userfaultfd,
+        "io_uring_setup" => libc::SYS_io_uring_setup,
+        "io_uring_enter" => libc::SYS_io_uring_enter,
+        "acct" => libc::SYS_acct,
+        "mlock" => libc::SYS_mlock,
+        "munlock" => libc::SYS_munlock,
+        "mlockall" => libc::SYS_mlockall,
+        "munlockall" => libc::SYS_munlockall,
+        "process_vm_readv" => libc::SYS_process_vm_readv,
+        "process_vm_writev" => libc::SYS_process_vm_writev,
+        "copy_file_range" => libc::SYS_copy_file_range,
+        "landlock_create_ruleset" => libc::SYS_landlock_create_ruleset,
+        #[cfg(target_arch = "x86_64")]
+        "open" => libc::SYS_open,
+        #[cfg(target_arch = "x86_64")]
+        "creat" => libc::SYS_creat,
+        #[cfg(target_arch = "x86_64")]
+        "stat" => libc::SYS_stat,
+        #[cfg(target_arch = "x86_64")]
+        "lstat" => libc::SYS_lstat,
+        #[cfg(target_arch = "x86_64")]
+        "poll" => libc::SYS_poll,
+        #[cfg(target_arch = "x86_64")]
+        "access" => libc::SYS_access,
+        #[cfg(target_arch = "x86_64")]
+        "pipe" => libc::SYS_pipe,
+        #[cfg(target_arch = "x86_64")]
+        "select" => libc::SYS_select,
+        #[cfg(target_arch = "x86_64")]
+        "dup2" => libc::SYS_dup2,
+        #[cfg(target_arch = "x86_64")]
+        "pause" => libc::SYS_pause,
+        #[cfg(target_arch = "x86_64")]
+        "alarm" => libc::SYS_alarm,
+        #[cfg(target_arch = "x86_64")]
+        "fork" => libc::SYS_fork,
+        #[cfg(target_arch = "x86_64")]
+        "vfork" => libc::SYS_vfork,
+        #[cfg(target_arch = "x86_64")]
+        "getdents" => libc::SYS_getdents,
+        #[cfg(target_arch = "x86_64")]
+        "rename" => libc::SYS_rename,
+        #[cfg(target_arch = "x86_64")]
+        "renameat" => libc::SYS_renameat,
+        #[cfg(target_arch = "x86_64")]
+        "mkdir" => libc::SYS_mkdir,
+        #[cfg(target_arch = "x86_64")]
+        "rmdir" => libc::SYS_rmdir,
+        #[cfg(target_arch = "x86_64")]
+        "link" => libc::SYS_link,
+        #[cfg(target_arch = "x86_64")]
+        "unlink" => libc::SYS_unlink,
+        #[cfg(target_arch = "x86_64")]
+        "symlink" => libc::SYS_symlink,
+        #[cfg(target_arch = "x86_64")]
+        "readlink" => libc::SYS_readlink,
+        #[cfg(target_arch = "x86_64")]
+        "chmod" => libc::SYS_chmod,
+        #[cfg(target_arch = "x86_64")]
+        "chown" => libc::SYS_chown,
+        #[cfg(target_arch = "x86_64")]
+        "lchown" => libc::SYS_lchown,
+        #[cfg(target_arch = "x86_64")]
+        "utime" => libc::SYS_utime,
+        #[cfg(target_arch = "x86_64")]
+        "utimes" => libc::SYS_utimes,
+        #[cfg(target_arch = "x86_64")]
+        "mknod" => libc::SYS_mknod,
+        #[cfg(target_arch = "x86_64")]
+        "time" => libc::SYS_time,
+        #[cfg(target_arch = "x86_64")]
+        "arch_prctl" => libc::SYS_arch_prctl,
+        #[cfg(target_arch = "x86_64")]
+        "epoll_create" => libc::SYS_epoll_create,
+        #[cfg(target_arch = "x86_64")]
+        "epoll_wait" => libc::SYS_epoll_wait,
+        #[cfg(target_arch = "x86_64")]
+        "inotify_init" => libc::SYS_inotify_init,
+        #[cfg(target_arch = "x86_64")]
+        "eventfd" => libc::SYS_eventfd,
+        #[cfg(target_arch = "x86_64")]
+        "signalfd" => libc::SYS_signalfd,
+        #[cfg(target_arch = "x86_64")]
+        "getpgrp" => libc::SYS_getpgrp,
+        #[cfg(target_arch = "x86_64")]
+        "iopl" => libc::SYS_iopl,
+        #[cfg(target_arch = "x86_64")]
+        "ioperm" => libc::SYS_ioperm,
+        #[cfg(target_arch = "x86_64")]
+        "modify_ldt" => libc::SYS_modify_ldt,
+        #[cfg(target_arch = "x86_64")]
+        "sendfile" => libc::SYS_sendfile,
+        #[cfg(target_arch = "x86_64")]
+        "getrlimit" => libc::SYS_getrlimit,
+        #[cfg(target_arch = "x86_64")]
+        "setrlimit" => libc::SYS_setrlimit,
+        _ => return None,
+    };
+    Some(number)
+}
+
+/// A compiled profile.
+#[derive(Debug)]
+pub struct Profile {
+    /// Where the profile came from, for messages.
+    pub name: String,
+    program: Vec<libc::sock_filter>,
+}
+
+impl Profile {
+    pub fn load(path: &Path) -> Result<Profile, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Profile::parse(&path.display().to_string(), &text)
+    }
+
+    fn parse(name: &str, text: &str) -> Result<Profile, String> {
+        let mut listed = Vec::new();
+        let mut allowlist = None;
+        let mut violation = libc::SECCOMP_RET_KILL_PROCESS;
+        for (number, line) in text.lines().enumerate() {
+            let error = |message: String| format!("{name}:{}: {message}", number + 1);
+            let line = line.split('#').next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+            let Some(directive) = words.next() else {
+                continue;
+            };
+            match directive {
+                "allow" | "deny" => {
+                    let allow = directive == "allow";
+                    if allowlist.is_some_and(|a| a != allow) {
+                        return Err(error("a profile cannot both allow and deny".to_string()));
+                    }
+                    allowlist = Some(allow);
+                    for call in words {
+                        let nr = call
+                            .parse()
+                            .ok()
+                            .or_else(|| syscall_number(call))
+                            .ok_or_else(|| error(format!("unknown system call `{call}'")))?;
+                        listed.push(nr as u32);
+                    }
+                }
+                "action" => {
+                    violation = match (words.next(), words.next()) {
+                        (Some("kill"), None) => libc::SECCOMP_RET_KILL_PROCESS,
+                        (Some("errno"), errno) => {
+                            let errno = match errno {
+                                None => libc::EPERM,
+                                Some(errno) => errno
+                                    .parse()
+                                    .ok()
+                                    .or_else(|| errno_number(errno))
+                                    .ok_or_else(|| error(format!("unknown errno `{errno}'")))?,
+                            };
+                            libc::SECCOMP_RET_ERRNO | (errno as u32 & libc::SECCOMP_RET_DATA)
+                        }
+                        _ => return Err(error("expected `action kill' or `action errno [ERRNO]'".to_string())),
+                    };
+                }
+                other => return Err(error(format!("unknown directive `{other}'"))),
+            }
+        }
+        let allowlist = allowlist.ok_or_else(|| format!("{name}: no allow or deny rules"))?;
+        if allowlist {
+            listed.push(libc::SYS_execve as u32);
+        }
+        let (on_match, otherwise) = if allowlist {
+            (libc::SECCOMP_RET_ALLOW, violation)
+        } else {
+            (violation, libc::SECCOMP_RET_ALLOW)
+        };
+        Ok(Profile {
+            name: name.to_string(),
+            program: compile(&listed, on_match, otherwise),
+        })
+    }
+
+    /// Installs the filter in the calling process. Only calls prctl(2), so it
+    /// is safe to run between fork and exec.
+    pub fn install(&self) -> io::Result<()> {
+        let program = libc::sock_fprog {
+            len: self.program.len() as libc::c_ushort,
+            filter: self.program.as_ptr() as *mut libc::sock_filter,
+        };
+        // SAFETY: `program` points at a valid filter that outlives both calls.
+        unsafe {
+            // Required to install a filter without CAP_SYS_ADMIN.
+            if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) < 0
+                || libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, &program) < 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn statement(code: u32, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+/// Builds a BPF program that returns `on_match` for the listed system calls
+/// and `otherwise` for the rest. Calls made through another ABI, such as x32
+/// or 32-bit compatibility mode, are killed outright, since their numbers
+/// would not mean what the profile says.
+fn compile(listed: &[u32], on_match: u32, otherwise: u32) -> Vec<libc::sock_filter> {
+    let load = libc::BPF_LD | libc::BPF_W | libc::BPF_ABS;
+    let mut program = vec![
+        statement(load, ARCH),
+        jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, AUDIT_ARCH, 1, 0),
+        statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+        statement(load, SYSCALL_NR),
+    ];
+    #[cfg(target_arch = "x86_64")]
+    program.extend([
+        jump(libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K, 0x4000_0000, 0, 1),
+        statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+    ]);
+    for &nr in listed {
+        program.push(jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, nr, 0, 1));
+        program.push(statement(libc::BPF_RET | libc::BPF_K, on_match));
+    }
+    program.push(statement(libc::BPF_RET | libc::BPF_K, otherwise));
+    program
+}
+
+fn errno_number(name: &str) -> Option<i32> {
+    let errno = match name {
+        "EPERM" => libc::EPERM,
+        "ENOENT" => libc::ENOENT,
+        "EACCES" => libc::EACCES,
+        "EINVAL" => libc::EINVAL,
+        "ENOSYS" => libc::ENOSYS,
+        "EAGAIN" => libc::EAGAIN,
+        "ENOMEM" => libc::ENOMEM,
+        "EROFS" => libc::EROFS,
+        _ => return None,
+    };
+    Some(errno)
+}
+
+impl Shell {
+    /// Arranges for `cmd` to run under the active profile. Call this after
+    /// anything else that adds work between fork and exec, so that the
+    /// filter does not have to allow it.
+    pub(crate) fn apply_seccomp(&self, cmd: &mut Process) {
+        if let Some(profile) = self.seccomp.clone() {
+            // SAFETY: installing the filter only calls prctl(2).
+            unsafe {
+                cmd.pre_exec(move || profile.install());
+            }
+        }
+    }
+
+    /// `sandbox [profile [command [arg...]]]`: with a command, runs it under
+    /// the profile; without one, applies the profile to every command the
+    /// shell starts from now on; with no arguments, shows the active profile.
+    /// `sandbox -r` removes it again.
+    pub(crate) fn builtin_sandbox(&mut self, argv: &[String]) -> i32 {
+        match argv.get(1).map(String::as_str) {
+            None => {
+                if let Some(profile) = &self.seccomp {
+                    let _ = writeln!(self.fds.writer(1), "{}", profile.name);
+                }
+                return 0;
+            }
+            Some("-r") => {
+                self.seccomp = None;
+                return 0;
+            }
+            Some(_) => {}
+        }
+        let profile = match Profile::load(&self.resolve_path(&argv[1])) {
+            Ok(profile) => Arc::new(profile),
+            Err(e) => {
+                self.report_error(format_args!("sandbox: {e}"));
+                return 1;
+            }
+        };
+        if argv.len() == 2 {
+            self.seccomp = Some(profile);
+            return 0;
+        }
+        let outer = self.seccomp.replace(profile);
+        let status = self.run_argv(&argv[2..]);
+        self.seccomp = outer;
+        status
+    }
+}
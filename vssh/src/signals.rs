@@ -0,0 +1,189 @@
+//! Trap conditions and the signal plumbing behind `trap`.
+//!
+//! Handlers only record that a signal arrived; the shell runs the trap action
+//! between commands, where it is safe to execute shell code.
+
+use std::os::fd::RawFd;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+use nix::fcntl::OFlag;
+use nix::libc;
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+
+/// Something a trap can be attached to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Trap {
+    Exit,
+    Signal(Signal),
+    Err,
+    Debug,
+}
+
+impl Trap {
+    /// Parses `EXIT`, `ERR`, `DEBUG`, a signal name with or without the `SIG`
+    /// prefix, or a signal number (`0` meaning EXIT).
+    pub fn parse(spec: &str) -> Option<Trap> {
+        match spec.to_ascii_uppercase().as_str() {
+            "EXIT" => Some(Trap::Exit),
+            "ERR" => Some(Trap::Err),
+            "DEBUG" => Some(Trap::Debug),
+            _ => Some(parse_signal(spec)?.map_or(Trap::Exit, Trap::Signal)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Trap::Exit => "EXIT",
+            Trap::Signal(signal) => signal.as_str(),
+            Trap::Err => "ERR",
+            Trap::Debug => "DEBUG",
+        }
+    }
+}
+
+/// Parses a signal name with or without the `SIG` prefix, or a number.
+/// Signal 0, which only probes whether a process exists, is `Some(None)`.
+pub fn parse_signal(spec: &str) -> Option<Option<Signal>> {
+    if let Ok(number) = spec.parse::<i32>() {
+        return match number {
+            0 => Some(None),
+            _ => Signal::try_from(number).ok().map(Some),
+        };
+    }
+    let upper = spec.to_ascii_uppercase();
+    let name = if upper.starts_with("SIG") { upper } else { format!("SIG{upper}") };
+    name.parse().ok().map(Some)
+}
+
+/// How the shell process itself responds to a signal.
+pub enum Disposition {
+    Default,
+    Ignore,
+    Catch,
+}
+
+const NSIG: usize = 65;
+
+static PENDING: [AtomicBool; NSIG] = [const { AtomicBool::new(false) }; NSIG];
+
+/// Set once `Shell::run` has handed SIGINT over to the ctrlc handler.
+static INTERACTIVE_INT: AtomicBool = AtomicBool::new(false);
+
+/// Whether SIGINT currently has a trap, which silences the interactive notice.
+static INT_TRAPPED: AtomicBool = AtomicBool::new(false);
+
+/// The ends of the pipe the handler `record_signal` writes a byte to for
+/// each SIGINT, once `interrupt_fd` has created it. Both are non-blocking.
+static INTERRUPT_READ: AtomicI32 = AtomicI32::new(-1);
+static INTERRUPT_WRITE: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn record_signal(signal: i32) {
+    if let Some(flag) = PENDING.get(signal as usize) {
+        flag.store(true, Ordering::SeqCst);
+    }
+    let fd = INTERRUPT_WRITE.load(Ordering::SeqCst);
+    if signal == Signal::SIGINT as i32 && fd >= 0 {
+        // SAFETY: write(2) is async-signal-safe, and a full pipe already
+        // has a wakeup in it.
+        unsafe { libc::write(fd, [0u8].as_ptr().cast(), 1) };
+    }
+}
+
+/// Installs the interactive Ctrl-C handler. SIGINT stays with this handler
+/// for the rest of the session; `trap` only decides what a press does.
+pub fn install_interrupt_handler() {
+    INTERACTIVE_INT.store(true, Ordering::SeqCst);
+    ctrlc::set_handler(move || {
+        record_signal(Signal::SIGINT as i32);
+        if !INT_TRAPPED.load(Ordering::SeqCst) {
+            println!("\nType 'exit' to quit.");
+        }
+    })
+    .expect("Error setting Ctrl-C handler");
+}
+
+/// Records a Ctrl-C that the line editor read as a key press, since the
+/// terminal does not send SIGINT while it is in raw mode. Returns whether a
+/// trap will handle it.
+pub fn interrupt_from_editor() -> bool {
+    record_signal(Signal::SIGINT as i32);
+    INT_TRAPPED.load(Ordering::SeqCst)
+}
+
+pub fn set_disposition(signal: Signal, disposition: Disposition) -> Result<(), String> {
+    if signal == Signal::SIGINT {
+        INT_TRAPPED.store(!matches!(disposition, Disposition::Default), Ordering::SeqCst);
+        if INTERACTIVE_INT.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+    }
+    let handler = match disposition {
+        Disposition::Default => SigHandler::SigDfl,
+        Disposition::Ignore => SigHandler::SigIgn,
+        Disposition::Catch => SigHandler::Handler(record_signal),
+    };
+    let action = SigAction::new(handler, SaFlags::SA_RESTART, SigSet::empty());
+    // SAFETY: `record_signal` only touches atomics, which is async-signal-safe.
+    unsafe { sigaction(signal, &action) }
+        .map(drop)
+        .map_err(|e| format!("{}: {}", signal.as_str(), e.desc()))
+}
+
+/// Returns the signals that arrived since the last call, in number order.
+pub fn take_pending() -> Vec<Signal> {
+    Signal::iterator()
+        .filter(|&signal| PENDING[signal as usize].swap(false, Ordering::SeqCst))
+        .collect()
+}
+
+/// Whether a Ctrl-C has arrived that has not been handled yet.
+pub fn interrupt_pending() -> bool {
+    PENDING[Signal::SIGINT as usize].load(Ordering::SeqCst)
+}
+
+/// A descriptor that becomes readable when a Ctrl-C arrives, so that poll(2)
+/// can wait for one along with other events. What it already holds is
+/// discarded, so call this again after it wakes a poll; `interrupt_pending`
+/// still tells whether a Ctrl-C is unhandled.
+pub fn interrupt_fd() -> Option<RawFd> {
+    let mut read = INTERRUPT_READ.load(Ordering::SeqCst);
+    if read < 0 {
+        let (r, w) = nix::unistd::pipe2(OFlag::O_CLOEXEC | OFlag::O_NONBLOCK).ok()?;
+        INTERRUPT_WRITE.store(w, Ordering::SeqCst);
+        INTERRUPT_READ.store(r, Ordering::SeqCst);
+        read = r;
+    }
+    let mut buf = [0u8; 64];
+    while matches!(nix::unistd::read(read, &mut buf), Ok(n) if n > 0) {}
+    Some(read)
+}
+
+/// Set by SIGWINCH while a pseudo-terminal session is being proxied.
+static WINDOW_RESIZED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn note_resize(signal: i32) {
+    WINDOW_RESIZED.store(true, Ordering::SeqCst);
+    record_signal(signal);
+}
+
+/// Starts noticing terminal resizes, which `window_resized` then reports.
+/// A WINCH trap still runs. The handler leaves out SA_RESTART so that a
+/// resize wakes up a blocked poll(2).
+pub fn watch_window_size() {
+    let action = SigAction::new(SigHandler::Handler(note_resize), SaFlags::empty(), SigSet::empty());
+    // SAFETY: `note_resize` only touches atomics.
+    let _ = unsafe { sigaction(Signal::SIGWINCH, &action) };
+}
+
+/// Whether the terminal was resized since the last call.
+pub fn window_resized() -> bool {
+    WINDOW_RESIZED.swap(false, Ordering::SeqCst)
+}
+
+/// Called in a forked subshell, which has no Ctrl-C handler thread: SIGINT
+/// goes back to its default action until a trap says otherwise.
+pub fn leave_interactive() {
+    if INTERACTIVE_INT.swap(false, Ordering::SeqCst) {
+        let _ = set_disposition(Signal::SIGINT, Disposition::Default);
+    }
+}
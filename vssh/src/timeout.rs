@@ -0,0 +1,197 @@
+//! The `timeout` builtin: runs a command for at most a given time.
+//!
+//! The command replaces a forked copy of the shell that leads a process group
+//! of its own, so that the signal sent when time runs out reaches everything
+//! the command started. As in GNU timeout, `-k` escalates to SIGKILL if the
+//! command is still running that long after the first signal, and the status
+//! is 124 after a timeout, 137 if SIGKILL was needed, and otherwise the
+//! command's own. Since the group is not the terminal's, a Ctrl-C at the
+//! shell is passed on to it. While it runs, the command is one of the
+//! shell's jobs.
+
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::time::{Duration, Instant};
+
+use nix::errno::Errno;
+use nix::libc;
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::signal::{killpg, Signal};
+use nix::unistd::{setpgid, Pid};
+
+use crate::builtins::is_builtin;
+use crate::signals;
+use crate::Shell;
+
+/// The status `timeout` returns when the command ran out of time.
+const TIMED_OUT: i32 = 124;
+
+/// The status `timeout` returns when it could not follow the command.
+const CANNOT_WATCH: i32 = 125;
+
+impl Shell {
+    /// `timeout [-s SIG] [-k DURATION] [--preserve-status] DURATION command [arg...]`
+    pub(crate) fn builtin_timeout(&mut self, argv: &[String]) -> i32 {
+        let mut signal = Signal::SIGTERM;
+        let mut kill_after = None;
+        let mut preserve_status = false;
+        let mut i = 1;
+        while let Some(arg) = argv.get(i).filter(|a| a.len() > 1 && a.starts_with('-')) {
+            i += 1;
+            match arg.as_str() {
+                "--" => break,
+                "--preserve-status" => preserve_status = true,
+                "-s" | "-k" => {
+                    let Some(value) = argv.get(i) else {
+                        self.report_error(format_args!("timeout: {arg}: option requires an argument"));
+                        return 2;
+                    };
+                    i += 1;
+                    if arg == "-s" {
+                        let Some(Some(parsed)) = signals::parse_signal(value) else {
+                            self.report_error(format_args!("timeout: {value}: invalid signal specification"));
+                            return 2;
+                        };
+                        signal = parsed;
+                    } else {
+                        let Some(duration) = parse_duration(value) else {
+                            self.report_error(format_args!("timeout: {value}: invalid time interval"));
+                            return 2;
+                        };
+                        kill_after = Some(duration);
+                    }
+                }
+                _ => {
+                    self.report_error(format_args!("timeout: {arg}: invalid option"));
+                    return 2;
+                }
+            }
+        }
+        if argv.len() < i + 2 {
+            self.report_error("timeout: usage: timeout [-s sig] [-k duration] duration command [arg...]");
+            return 2;
+        }
+        let Some(limit) = parse_duration(&argv[i]) else {
+            self.report_error(format_args!("timeout: {}: invalid time interval", argv[i]));
+            return 2;
+        };
+
+        let command = argv[i + 1..].to_vec();
+        if let Err(e) = self.check_restricted_command(&command[0]) {
+            self.report_error(e);
+            return 1;
+        }
+        // The copy of the shell becomes the command, so that the process
+        // watched is the one the signal is meant for.
+        let child = self.fork_subshell(None, |shell| {
+            let _ = setpgid(Pid::from_raw(0), Pid::from_raw(0));
+            shell.exec_argv(&command)
+        });
+        let pid = match child {
+            Ok(pid) => pid,
+            Err(status) => return status,
+        };
+        // Set it from this side too, so that the group exists before any
+        // signal is sent to it.
+        let _ = setpgid(pid, pid);
+        if !is_builtin(&command[0]) {
+            self.audit_spawned(pid, &command);
+        }
+        let job = self.jobs.add(vec![pid], &argv.join(" "));
+        let exited = match pidfd_open(pid) {
+            Ok(fd) => fd,
+            Err(e) => {
+                self.report_error(format_args!("timeout: cannot watch process {pid}: {}", e.desc()));
+                let _ = killpg(pid, Signal::SIGKILL);
+                self.wait_foreground_job(job);
+                return CANNOT_WATCH;
+            }
+        };
+
+        let deadline = Instant::now() + limit;
+        let mut timed_out = false;
+        let mut kill_at = None;
+        let mut killed = false;
+        let mut interrupt_passed_on = false;
+        let mut interrupt = signals::interrupt_fd();
+        loop {
+            let now = Instant::now();
+            if !interrupt_passed_on && signals::interrupt_pending() {
+                let _ = killpg(pid, Signal::SIGINT);
+                interrupt_passed_on = true;
+            }
+            if !timed_out && now >= deadline {
+                let _ = killpg(pid, signal);
+                // A stopped command would never act on the signal.
+                let _ = killpg(pid, Signal::SIGCONT);
+                timed_out = true;
+                kill_at = kill_after.map(|after| now + after);
+            }
+            if let Some(at) = kill_at
+                && now >= at
+            {
+                let _ = killpg(pid, Signal::SIGKILL);
+                kill_at = None;
+                killed = true;
+            }
+
+            // Sleep until the command exits, a Ctrl-C arrives or the next
+            // signal is due.
+            let next = if timed_out { kill_at } else { Some(deadline) };
+            let wait = next.map_or(-1, |at| {
+                let millis = at.saturating_duration_since(now).as_micros().div_ceil(1000);
+                millis.min(i32::MAX as u128) as i32
+            });
+            let mut fds = vec![PollFd::new(exited.as_raw_fd(), PollFlags::POLLIN)];
+            if let Some(fd) = interrupt.filter(|_| !interrupt_passed_on) {
+                fds.push(PollFd::new(fd, PollFlags::POLLIN));
+            }
+            match poll(&mut fds, wait) {
+                Ok(_) | Err(Errno::EINTR) => {}
+                Err(_) => break,
+            }
+            if ready(&fds[0]) {
+                break;
+            }
+            if fds.get(1).is_some_and(ready) {
+                interrupt = signals::interrupt_fd();
+            }
+        }
+        let status = self.wait_foreground_job(job);
+        match (timed_out, killed) {
+            (true, true) => 128 + Signal::SIGKILL as i32,
+            (true, false) if !preserve_status => TIMED_OUT,
+            _ => status,
+        }
+    }
+}
+
+/// A descriptor that becomes readable once `pid` has exited.
+fn pidfd_open(pid: Pid) -> nix::Result<OwnedFd> {
+    // SAFETY: pidfd_open takes no pointers, and the descriptor it returns is
+    // owned by nothing else.
+    unsafe {
+        let fd = libc::syscall(libc::SYS_pidfd_open, pid.as_raw(), 0);
+        Errno::result(fd).map(|fd| OwnedFd::from_raw_fd(fd as RawFd))
+    }
+}
+
+fn ready(fd: &PollFd) -> bool {
+    fd.revents().is_some_and(|r| !r.is_empty())
+}
+
+/// Parses a duration such as `10`, `1.5s`, `2m`, `1h` or `1d`. A number
+/// without a suffix counts seconds.
+pub(crate) fn parse_duration(text: &str) -> Option<Duration> {
+    let (number, scale) = match text.char_indices().last()? {
+        (end, 's') => (&text[..end], 1.0),
+        (end, 'm') => (&text[..end], 60.0),
+        (end, 'h') => (&text[..end], 3600.0),
+        (end, 'd') => (&text[..end], 86400.0),
+        _ => (text, 1.0),
+    };
+    if !number.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        return None;
+    }
+    let seconds: f64 = number.parse().ok()?;
+    Duration::try_from_secs_f64(seconds * scale).ok()
+}
@@ -0,0 +1,185 @@
+//! The `time` reserved word.
+//!
+//! CPU times, page faults and context switches come from getrusage(2) for the
+//! shell and its reaped children, taken before and after the pipeline. The
+//! peak resident set size is the largest one reported by wait4(2) for the
+//! pipeline's own processes.
+
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use nix::libc;
+
+use crate::parser::{Pipeline, TimeFormat};
+use crate::Shell;
+
+/// The report used when `$TIMEFORMAT` is unset.
+const DEFAULT_FORMAT: &str = "\nreal\t%3lR\nuser\t%3lU\nsys\t%3lS\nmaxrss\t%MKB\n\
+                              faults\t%F major, %f minor\nctxsw\t%w voluntary, %c involuntary";
+const POSIX_FORMAT: &str = "real %2R\nuser %2U\nsys %2S";
+
+/// Resource usage over an interval.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Usage {
+    pub(crate) real: Duration,
+    pub(crate) user: Duration,
+    pub(crate) sys: Duration,
+    /// Kilobytes.
+    pub(crate) max_rss: i64,
+    pub(crate) major_faults: i64,
+    pub(crate) minor_faults: i64,
+    pub(crate) voluntary_switches: i64,
+    pub(crate) involuntary_switches: i64,
+}
+
+impl Usage {
+    /// The combined usage of the shell and every child it has reaped.
+    fn now() -> Usage {
+        let own = Usage::own();
+        let children = Usage::from_rusage(&getrusage(libc::RUSAGE_CHILDREN));
+        Usage {
+            real: Duration::ZERO,
+            user: own.user + children.user,
+            sys: own.sys + children.sys,
+            max_rss: own.max_rss,
+            major_faults: own.major_faults + children.major_faults,
+            minor_faults: own.minor_faults + children.minor_faults,
+            voluntary_switches: own.voluntary_switches + children.voluntary_switches,
+            involuntary_switches: own.involuntary_switches + children.involuntary_switches,
+        }
+    }
+
+    /// The usage of the shell process alone.
+    pub(crate) fn own() -> Usage {
+        Usage::from_rusage(&getrusage(libc::RUSAGE_SELF))
+    }
+
+    /// The usage getrusage(2) or wait4(2) reported, with no real time.
+    pub(crate) fn from_rusage(usage: &libc::rusage) -> Usage {
+        let timeval = |tv: libc::timeval| Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000);
+        Usage {
+            real: Duration::ZERO,
+            user: timeval(usage.ru_utime),
+            sys: timeval(usage.ru_stime),
+            max_rss: usage.ru_maxrss,
+            major_faults: usage.ru_majflt,
+            minor_faults: usage.ru_minflt,
+            voluntary_switches: usage.ru_nvcsw,
+            involuntary_switches: usage.ru_nivcsw,
+        }
+    }
+
+    pub(crate) fn since(self, start: Usage) -> Usage {
+        Usage {
+            real: self.real,
+            user: self.user.saturating_sub(start.user),
+            sys: self.sys.saturating_sub(start.sys),
+            max_rss: self.max_rss,
+            major_faults: self.major_faults - start.major_faults,
+            minor_faults: self.minor_faults - start.minor_faults,
+            voluntary_switches: self.voluntary_switches - start.voluntary_switches,
+            involuntary_switches: self.involuntary_switches - start.involuntary_switches,
+        }
+    }
+}
+
+fn getrusage(who: libc::c_int) -> libc::rusage {
+    // SAFETY: getrusage only writes into the struct it is given.
+    unsafe {
+        let mut usage: libc::rusage = std::mem::zeroed();
+        libc::getrusage(who, &mut usage);
+        usage
+    }
+}
+
+impl Shell {
+    /// Runs a pipeline preceded by `time` and reports its usage on stderr.
+    pub(crate) fn run_timed(&mut self, pipeline: &Pipeline, format: TimeFormat) -> i32 {
+        let outer_rss = self.timed_max_rss.replace(0);
+        let start = Usage::now();
+        let started = Instant::now();
+
+        let status = self.run_untimed(pipeline);
+
+        let mut usage = Usage::now().since(start);
+        usage.real = started.elapsed();
+        let children_rss = self.timed_max_rss.take().unwrap_or(0);
+        // A pipeline of builtins ran in the shell itself.
+        if children_rss > 0 {
+            usage.max_rss = children_rss;
+        }
+        self.timed_max_rss = outer_rss.map(|rss| rss.max(children_rss));
+
+        let template = match format {
+            TimeFormat::Posix => POSIX_FORMAT.to_string(),
+            TimeFormat::Default => self.env_vars.scalar("TIMEFORMAT").unwrap_or(DEFAULT_FORMAT).to_string(),
+        };
+        if !template.is_empty() {
+            let report = format_usage(&template, &usage);
+            let _ = writeln!(self.fds.writer(2), "{report}");
+        }
+        status
+    }
+
+    /// Notes the peak memory of a process reaped while a `time` is running.
+    pub(crate) fn record_max_rss(&mut self, usage: &libc::rusage) {
+        if let Some(rss) = &mut self.timed_max_rss {
+            *rss = (*rss).max(usage.ru_maxrss);
+        }
+    }
+}
+
+/// Expands a `$TIMEFORMAT` template. As in bash, `%[p][l]R`, `%[p][l]U` and
+/// `%[p][l]S` give real, user and system time with `p` (0 to 3, default 3)
+/// decimal places, `l` selecting the `1m2.345s` form, and `%P` the CPU
+/// percentage. `%M` adds the peak resident set size in kilobytes, `%F` and
+/// `%f` the major and minor page faults, and `%w` and `%c` the voluntary and
+/// involuntary context switches.
+fn format_usage(template: &str, usage: &Usage) -> String {
+    let mut out = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let mut precision = 3;
+        if let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+            precision = digit.min(3) as usize;
+            chars.next();
+        }
+        let long = chars.next_if_eq(&'l').is_some();
+        let seconds = |duration: Duration| {
+            if long {
+                let secs = duration.as_secs_f64();
+                let minutes = (secs / 60.0).floor();
+                format!("{}m{:.*}s", minutes, precision, secs - minutes * 60.0)
+            } else {
+                format!("{:.*}", precision, duration.as_secs_f64())
+            }
+        };
+        match chars.next() {
+            Some('R') => out.push_str(&seconds(usage.real)),
+            Some('U') => out.push_str(&seconds(usage.user)),
+            Some('S') => out.push_str(&seconds(usage.sys)),
+            Some('P') => {
+                let real = usage.real.as_secs_f64();
+                let cpu = (usage.user + usage.sys).as_secs_f64();
+                let percent = if real > 0.0 { cpu / real * 100.0 } else { 0.0 };
+                out.push_str(&format!("{percent:.2}"));
+            }
+            Some('M') => out.push_str(&usage.max_rss.to_string()),
+            Some('F') => out.push_str(&usage.major_faults.to_string()),
+            Some('f') => out.push_str(&usage.minor_faults.to_string()),
+            Some('w') => out.push_str(&usage.voluntary_switches.to_string()),
+            Some('c') => out.push_str(&usage.involuntary_switches.to_string()),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
@@ -0,0 +1,378 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+
+/// The value held by a shell variable.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Scalar(String),
+    Indexed(BTreeMap<usize, String>),
+    Assoc(BTreeMap<String, String>),
+}
+
+impl Value {
+    /// The value `$name` expands to: the scalar itself or element zero of an array.
+    pub fn first(&self) -> Option<&str> {
+        match self {
+            Value::Scalar(s) => Some(s),
+            Value::Indexed(map) => map.get(&0).map(String::as_str),
+            Value::Assoc(map) => map.get("0").map(String::as_str),
+        }
+    }
+
+    /// Every element in subscript order; a scalar is a one-element array.
+    pub fn values(&self) -> Vec<String> {
+        match self {
+            Value::Scalar(s) => vec![s.clone()],
+            Value::Indexed(map) => map.values().cloned().collect(),
+            Value::Assoc(map) => map.values().cloned().collect(),
+        }
+    }
+
+    /// The subscripts in use, as `${!name[@]}` reports them.
+    pub fn keys(&self) -> Vec<String> {
+        match self {
+            Value::Scalar(_) => vec!["0".to_string()],
+            Value::Indexed(map) => map.keys().map(|k| k.to_string()).collect(),
+            Value::Assoc(map) => map.keys().cloned().collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Value::Scalar(_) => 1,
+            Value::Indexed(map) => map.len(),
+            Value::Assoc(map) => map.len(),
+        }
+    }
+}
+
+/// A resolved array subscript.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Subscript {
+    Index(i64),
+    Key(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArrayKind {
+    Indexed,
+    Assoc,
+}
+
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub value: Value,
+    pub exported: bool,
+}
+
+/// The shell's variable table.
+///
+/// Scalars and arrays share one namespace, as in bash. Only exported scalars
+/// are passed on to child processes.
+#[derive(Debug, Default)]
+pub struct Variables {
+    vars: HashMap<String, Variable>,
+}
+
+impl Variables {
+    /// Seeds the table from the process environment, marking everything exported.
+    pub fn from_env() -> Self {
+        Variables::from_pairs(env::vars())
+    }
+
+    /// Seeds the table from `name, value` pairs, marking everything exported.
+    pub fn from_pairs(pairs: impl IntoIterator<Item = (String, String)>) -> Self {
+        let vars = pairs
+            .into_iter()
+            .map(|(name, value)| {
+                (
+                    name,
+                    Variable {
+                        value: Value::Scalar(value),
+                        exported: true,
+                    },
+                )
+            })
+            .collect();
+        Variables { vars }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Variable> {
+        self.vars.get(name)
+    }
+
+    /// The scalar value of `name`, or element zero if it is an array.
+    pub fn scalar(&self, name: &str) -> Option<&str> {
+        self.vars.get(name).and_then(|v| v.value.first())
+    }
+
+    pub fn is_assoc(&self, name: &str) -> bool {
+        matches!(
+            self.vars.get(name),
+            Some(Variable {
+                value: Value::Assoc(_),
+                ..
+            })
+        )
+    }
+
+    /// Assigns `name=value`, which writes element zero when `name` is an array.
+    pub fn set_scalar(&mut self, name: &str, value: String) {
+        match self.vars.get_mut(name) {
+            Some(var) => match &mut var.value {
+                Value::Scalar(s) => *s = value,
+                Value::Indexed(map) => {
+                    map.insert(0, value);
+                }
+                Value::Assoc(map) => {
+                    map.insert("0".to_string(), value);
+                }
+            },
+            None => {
+                self.vars.insert(
+                    name.to_string(),
+                    Variable {
+                        value: Value::Scalar(value),
+                        exported: false,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Replaces the whole value of `name`, keeping its attributes.
+    pub fn set_value(&mut self, name: &str, value: Value) {
+        match self.vars.get_mut(name) {
+            Some(var) => var.value = value,
+            None => {
+                self.vars.insert(
+                    name.to_string(),
+                    Variable {
+                        value,
+                        exported: false,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Assigns `name[subscript]=value`, turning a scalar into an indexed array.
+    pub fn set_element(&mut self, name: &str, subscript: Subscript, value: String) -> Result<(), String> {
+        let var = self.vars.entry(name.to_string()).or_insert_with(|| Variable {
+            value: Value::Indexed(BTreeMap::new()),
+            exported: false,
+        });
+        if let Value::Scalar(s) = &var.value {
+            let mut map = BTreeMap::new();
+            map.insert(0, s.clone());
+            var.value = Value::Indexed(map);
+        }
+        match (&mut var.value, subscript) {
+            (Value::Indexed(map), Subscript::Index(i)) => {
+                let index = resolve_index(map, i).ok_or_else(|| format!("{name}[{i}]: bad array subscript"))?;
+                map.insert(index, value);
+            }
+            (Value::Assoc(map), Subscript::Key(k)) => {
+                map.insert(k, value);
+            }
+            (Value::Assoc(map), Subscript::Index(i)) => {
+                map.insert(i.to_string(), value);
+            }
+            (Value::Indexed(_), Subscript::Key(k)) => {
+                return Err(format!("{name}[{k}]: bad array subscript"));
+            }
+            (Value::Scalar(_), _) => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Looks up `name[subscript]`; a scalar behaves like a one-element array.
+    pub fn element(&self, name: &str, subscript: &Subscript) -> Option<&str> {
+        let var = self.vars.get(name)?;
+        match (&var.value, subscript) {
+            (Value::Scalar(s), Subscript::Index(0 | -1)) => Some(s),
+            (Value::Scalar(_), _) => None,
+            (Value::Indexed(map), Subscript::Index(i)) => {
+                resolve_index(map, *i).and_then(|i| map.get(&i)).map(String::as_str)
+            }
+            (Value::Indexed(_), Subscript::Key(_)) => None,
+            (Value::Assoc(map), Subscript::Key(k)) => map.get(k).map(String::as_str),
+            (Value::Assoc(map), Subscript::Index(i)) => map.get(&i.to_string()).map(String::as_str),
+        }
+    }
+
+    /// Appends `values` after the highest index of `name`, as `name+=(...)` does.
+    pub fn append_elements(&mut self, name: &str, values: Vec<String>) {
+        let var = self.vars.entry(name.to_string()).or_insert_with(|| Variable {
+            value: Value::Indexed(BTreeMap::new()),
+            exported: false,
+        });
+        if let Value::Scalar(s) = &var.value {
+            let mut map = BTreeMap::new();
+            map.insert(0, s.clone());
+            var.value = Value::Indexed(map);
+        }
+        if let Value::Indexed(map) = &mut var.value {
+            let next = map.keys().next_back().map_or(0, |k| k + 1);
+            map.extend((next..).zip(values));
+        }
+    }
+
+    /// Creates `name` as an empty array of `kind` unless it already is one.
+    pub fn declare_array(&mut self, name: &str, kind: ArrayKind) -> Result<(), String> {
+        let var = self.vars.entry(name.to_string()).or_insert_with(|| Variable {
+            value: Value::Scalar(String::new()),
+            exported: false,
+        });
+        match (&var.value, kind) {
+            (Value::Indexed(_), ArrayKind::Indexed) | (Value::Assoc(_), ArrayKind::Assoc) => {}
+            (Value::Scalar(s), ArrayKind::Indexed) => {
+                let mut map = BTreeMap::new();
+                if !s.is_empty() {
+                    map.insert(0, s.clone());
+                }
+                var.value = Value::Indexed(map);
+            }
+            (Value::Scalar(s), ArrayKind::Assoc) => {
+                let mut map = BTreeMap::new();
+                if !s.is_empty() {
+                    map.insert("0".to_string(), s.clone());
+                }
+                var.value = Value::Assoc(map);
+            }
+            (Value::Indexed(_), ArrayKind::Assoc) | (Value::Assoc(_), ArrayKind::Indexed) => {
+                return Err(format!("{name}: cannot convert array kind"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts back a variable saved with [`Variables::get`], or removes it if
+    /// it did not exist.
+    pub fn restore(&mut self, name: &str, var: Option<Variable>) {
+        match var {
+            Some(var) => {
+                self.vars.insert(name.to_string(), var);
+            }
+            None => {
+                self.vars.remove(name);
+            }
+        }
+    }
+
+    pub fn unset(&mut self, name: &str) {
+        self.vars.remove(name);
+    }
+
+    pub fn unset_element(&mut self, name: &str, subscript: &Subscript) {
+        let Some(var) = self.vars.get_mut(name) else {
+            return;
+        };
+        match (&mut var.value, subscript) {
+            (Value::Indexed(map), Subscript::Index(i)) => {
+                if let Some(i) = resolve_index(map, *i) {
+                    map.remove(&i);
+                }
+            }
+            (Value::Assoc(map), Subscript::Key(k)) => {
+                map.remove(k);
+            }
+            (Value::Scalar(_), Subscript::Index(0 | -1)) => {
+                self.vars.remove(name);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn set_exported(&mut self, name: &str, exported: bool) {
+        match self.vars.get_mut(name) {
+            Some(var) => var.exported = exported,
+            None if exported => {
+                self.vars.insert(
+                    name.to_string(),
+                    Variable {
+                        value: Value::Scalar(String::new()),
+                        exported,
+                    },
+                );
+            }
+            None => {}
+        }
+    }
+
+    /// Exported scalars, which make up the environment of spawned commands.
+    pub fn exported(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.vars.iter().filter_map(|(name, var)| match &var.value {
+            Value::Scalar(s) if var.exported => Some((name.as_str(), s.as_str())),
+            _ => None,
+        })
+    }
+
+    /// Every variable, sorted by name.
+    pub fn sorted(&self) -> Vec<(&str, &Variable)> {
+        let mut all: Vec<_> = self.vars.iter().map(|(k, v)| (k.as_str(), v)).collect();
+        all.sort_by(|a, b| a.0.cmp(b.0));
+        all
+    }
+}
+
+/// Maps a possibly negative index onto the array, counting back from its end.
+fn resolve_index(map: &BTreeMap<usize, String>, index: i64) -> Option<usize> {
+    if index >= 0 {
+        return Some(index as usize);
+    }
+    let end = map.keys().next_back().map_or(0, |k| k + 1) as i64;
+    usize::try_from(end + index).ok()
+}
+
+/// Renders a variable the way `declare -p` prints it.
+pub fn declaration(name: &str, var: &Variable) -> String {
+    let mut flags = String::new();
+    match var.value {
+        Value::Indexed(_) => flags.push('a'),
+        Value::Assoc(_) => flags.push('A'),
+        Value::Scalar(_) => {}
+    }
+    if var.exported {
+        flags.push('x');
+    }
+    if flags.is_empty() {
+        flags.push('-');
+    }
+    match &var.value {
+        Value::Scalar(s) => format!("declare -{flags} {name}={}", double_quote(s)),
+        Value::Indexed(map) => {
+            let items: Vec<String> = map
+                .iter()
+                .map(|(k, v)| format!("[{k}]={}", double_quote(v)))
+                .collect();
+            format!("declare -{flags} {name}=({})", items.join(" "))
+        }
+        Value::Assoc(map) => {
+            let items: Vec<String> = map
+                .iter()
+                .map(|(k, v)| format!("[{k}]={}", double_quote(v)))
+                .collect();
+            format!("declare -{flags} {name}=({})", items.join(" "))
+        }
+    }
+}
+
+fn double_quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('"');
+    out
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
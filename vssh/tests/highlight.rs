@@ -0,0 +1,90 @@
+//! The spans the line editor colours, as the parser classifies them.
+
+use vssh::parser::{spans, SpanKind};
+
+/// Each span as its kind and the text it covers.
+fn classify(line: &str) -> Vec<(SpanKind, &str)> {
+    spans(line).into_iter().map(|span| (span.kind, &line[span.range])).collect()
+}
+
+#[test]
+fn commands_arguments_and_operators() {
+    use SpanKind::*;
+    assert_eq!(
+        classify("echo a | grep -v b && ls; true &"),
+        [
+            (Command, "echo"),
+            (Operator, "|"),
+            (Command, "grep"),
+            (Operator, "&&"),
+            (Command, "ls"),
+            (Operator, ";"),
+            (Command, "true"),
+            (Operator, "&"),
+        ]
+    );
+}
+
+#[test]
+fn quotes_and_variables_nest() {
+    use SpanKind::*;
+    assert_eq!(
+        classify(r#"echo "a $x ${y:-z}" '$no' $1"#),
+        [
+            (Command, "echo"),
+            (Quoted, r#""a $x ${y:-z}""#),
+            (Variable, "$x"),
+            (Variable, "${y:-z}"),
+            (Quoted, "'$no'"),
+            (Variable, "$1"),
+        ]
+    );
+}
+
+#[test]
+fn keywords_assignments_redirections_and_comments() {
+    use SpanKind::*;
+    assert_eq!(
+        classify("if [[ $a == b ]]; then x=1 arr=(p q) cmd 2>&1 >out; fi # done"),
+        [
+            (Keyword, "if"),
+            (Keyword, "[["),
+            (Variable, "$a"),
+            (Keyword, "]]"),
+            (Operator, ";"),
+            (Keyword, "then"),
+            (Assignment, "x="),
+            (Assignment, "arr="),
+            (Operator, "("),
+            (Operator, ")"),
+            (Command, "cmd"),
+            (Redirect, "2>&1"),
+            (Redirect, ">out"),
+            (Operator, ";"),
+            (Keyword, "fi"),
+            (Comment, "# done"),
+        ]
+    );
+}
+
+#[test]
+fn incomplete_input_still_highlights() {
+    use SpanKind::*;
+    assert_eq!(classify(r#"echo "open $x"#), [(Command, "echo"), (Quoted, r#""open $x"#), (Variable, "$x")]);
+    assert_eq!(classify("echo ${x"), [(Command, "echo"), (Variable, "${x")]);
+    assert_eq!(classify("while true; do\nls"), [
+        (Keyword, "while"),
+        (Command, "true"),
+        (Operator, ";"),
+        (Keyword, "do"),
+        (Command, "ls"),
+    ]);
+}
+
+#[test]
+fn offsets_are_in_bytes() {
+    let line = "echo é'ü' $ß";
+    let ranges: Vec<_> = spans(line).into_iter().map(|span| span.range).collect();
+    assert_eq!(&line[ranges[1].clone()], "'ü'");
+    assert_eq!(ranges.len(), 2, "$ß is not a parameter");
+}
@@ -0,0 +1,179 @@
+//! Drives the shell as a library, with its output sent to files.
+
+use std::fs::File;
+use std::io::{Read, Seek};
+
+use vssh::parser::{self, ParseError};
+use vssh::Shell;
+
+/// A shell with an empty environment apart from `PATH`, whose stdout and
+/// stderr go to temporary files.
+struct Harness {
+    shell: Shell,
+    stdout: File,
+    stderr: File,
+}
+
+impl Harness {
+    fn new() -> Self {
+        let path = std::env::var("PATH").unwrap_or_default();
+        let mut shell = Shell::with_env([("PATH".to_string(), path)]);
+        let stdout = tempfile::tempfile().unwrap();
+        let stderr = tempfile::tempfile().unwrap();
+        shell.set_fd(1, stdout.try_clone().unwrap());
+        shell.set_fd(2, stderr.try_clone().unwrap());
+        Harness { shell, stdout, stderr }
+    }
+
+    fn read(file: &mut File) -> String {
+        let mut text = String::new();
+        file.rewind().unwrap();
+        file.read_to_string(&mut text).unwrap();
+        text
+    }
+
+    fn stdout(&mut self) -> String {
+        Harness::read(&mut self.stdout)
+    }
+
+    fn stderr(&mut self) -> String {
+        Harness::read(&mut self.stderr)
+    }
+}
+
+#[test]
+fn output_goes_to_the_injected_fds() {
+    let mut h = Harness::new();
+    assert_eq!(h.shell.execute_command("echo out; echo err >&2; printf '%s\\n' x"), 0);
+    assert_eq!(h.stdout(), "out\nx\n");
+    assert_eq!(h.stderr(), "err\n");
+}
+
+#[test]
+fn external_commands_inherit_the_injected_fds() {
+    let mut h = Harness::new();
+    assert_eq!(h.shell.execute_command("sh -c 'echo child; echo oops >&2; exit 4'"), 4);
+    assert_eq!(h.stdout(), "child\n");
+    assert_eq!(h.stderr(), "oops\n");
+}
+
+#[test]
+fn environment_is_injected() {
+    let mut h = Harness::new();
+    h.shell.set_var("HOME", "/nonexistent", true);
+    assert_eq!(h.shell.var("HOME"), Some("/nonexistent"));
+    assert_eq!(h.shell.var("USER"), None);
+    h.shell.execute_command("sh -c 'echo ${HOME}-${USER:-none}'");
+    assert_eq!(h.stdout(), "/nonexistent-none\n");
+}
+
+#[test]
+fn state_persists_between_commands() {
+    let mut h = Harness::new();
+    h.shell.execute_command("x=1; arr=(a b)");
+    h.shell.execute_command("x=${x}2");
+    assert_eq!(h.shell.var("x"), Some("12"));
+    assert_eq!(h.shell.expand_str("${arr[1]}-$x").unwrap(), "b-12");
+    h.shell.execute_command("false");
+    assert_eq!(h.shell.last_status(), 1);
+}
+
+#[test]
+fn exit_stops_the_shell() {
+    let mut h = Harness::new();
+    assert!(h.shell.is_running());
+    assert_eq!(h.shell.execute_command("exit 5; echo unreachable"), 5);
+    assert!(!h.shell.is_running());
+    assert_eq!(h.stdout(), "");
+}
+
+#[test]
+fn working_directory_and_arguments() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir = dir.path().canonicalize().unwrap();
+    let mut h = Harness::new();
+    h.shell.set_current_dir(&dir).unwrap();
+    h.shell.set_args("name", &["a".to_string(), "b c".to_string()]);
+    h.shell.execute_command("pwd; echo $0 $# \"$2\"; echo made > file");
+    assert_eq!(h.stdout(), format!("{}\nname 2 b c\n", dir.display()));
+    assert_eq!(std::fs::read_to_string(dir.join("file")).unwrap(), "made\n");
+}
+
+#[test]
+fn directory_environments_load_once_allowed() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir = dir.path().canonicalize().unwrap();
+    let project = dir.join("project");
+    std::fs::create_dir_all(project.join("sub")).unwrap();
+    std::fs::write(project.join(".vsshenv"), "export FOO=bar\nunset GONE\nlocal=1\n").unwrap();
+    let mut h = Harness::new();
+    h.shell.set_var("HOME", dir.to_str().unwrap(), true);
+    h.shell.set_var("GONE", "here", true);
+    h.shell.set_interactive(true);
+
+    let script = format!("cd {}; echo ${{FOO-unset}}", project.display());
+    h.shell.execute_command(&script);
+    assert!(h.stderr().contains("is not allowed"), "{}", h.stderr());
+    h.shell.execute_command("vsshenv allow; echo $FOO ${GONE-unset} ${local-unset}; cd sub; echo $FOO");
+    assert!(h.stderr().contains("vssh: export +FOO -GONE\n"), "{}", h.stderr());
+    h.shell.execute_command("cd /; echo ${FOO-unset} $GONE");
+    assert!(h.stderr().contains("vssh: export +GONE -FOO\n"), "{}", h.stderr());
+    assert_eq!(h.stdout(), "unset\nbar unset unset\nbar\nunset here\n");
+
+    std::fs::write(project.join(".vsshenv"), "export FOO=changed\n").unwrap();
+    h.shell.execute_command(&script);
+    assert!(h.stdout().ends_with("unset\n"));
+}
+
+#[test]
+fn denying_a_directory_environment_unloads_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir = dir.path().canonicalize().unwrap();
+    std::fs::write(dir.join(".vsshenv"), "export FOO=bar\n").unwrap();
+    let mut h = Harness::new();
+    h.shell.set_var("HOME", dir.to_str().unwrap(), true);
+    h.shell.set_interactive(true);
+
+    h.shell.execute_command(&format!("cd {}; vsshenv allow; echo $FOO", dir.display()));
+    h.shell.execute_command("vsshenv deny; echo ${FOO-unset}; cd .; echo ${FOO-unset}");
+    assert_eq!(h.stdout(), "bar\nunset\nunset\n");
+    assert!(h.stderr().contains("vssh: unloading"), "{}", h.stderr());
+}
+
+#[test]
+fn options_can_be_set() {
+    let mut h = Harness::new();
+    h.shell.set_option("errexit", true).unwrap();
+    assert_eq!(h.shell.execute_command("false; echo after"), 1);
+    assert_eq!(h.stdout(), "");
+    assert!(h.shell.set_option("no-such-option", true).is_err());
+}
+
+#[test]
+fn parsed_lists_can_be_run() {
+    let mut h = Harness::new();
+    let list = parser::parse("echo parsed && false").unwrap();
+    assert_eq!(list.items.len(), 1);
+    assert_eq!(h.shell.execute(&list), 1);
+    assert_eq!(h.stdout(), "parsed\n");
+}
+
+#[test]
+fn syntax_errors_are_reported() {
+    assert_eq!(parser::parse("if true; then"), Err(ParseError::Incomplete));
+    assert!(matches!(parser::parse("fi"), Err(ParseError::Syntax(_))));
+
+    let mut h = Harness::new();
+    assert_eq!(h.shell.execute_command("echo 'open"), 2);
+    assert!(h.stderr().contains("syntax error"));
+}
+
+#[test]
+fn words_keep_their_quoting() {
+    let word = parser::parse_word("a\"$x b\"").unwrap();
+    let mut h = Harness::new();
+    h.shell.set_var("x", "1  2", false);
+    assert_eq!(h.shell.expand_words(&[word]).unwrap(), ["a1  2 b"]);
+    let word = parser::parse_word("$x").unwrap();
+    assert_eq!(h.shell.expand_words(&[word]).unwrap(), ["1", "2"]);
+}
@@ -0,0 +1,294 @@
+//! Conformance checks against the POSIX shell command language, using the
+//! `cmd` fixture to observe what commands actually receive. Section numbers
+//! refer to XCU chapter 2, "Shell Command Language".
+
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use serde_json::Value;
+
+struct Run {
+    stdout: String,
+    stderr: String,
+    status: i32,
+}
+
+/// Runs `script` with `vssh -c`, with the fixture first in `PATH`.
+fn vssh(script: &str, args: &[&str]) -> Run {
+    let fixture = Path::new(env!("CARGO_BIN_EXE_cmd")).parent().unwrap();
+    let path = format!("{}:{}", fixture.display(), std::env::var("PATH").unwrap_or_default());
+    let output = Command::new(env!("CARGO_BIN_EXE_vssh"))
+        .arg("-c")
+        .arg(script)
+        .arg("vssh")
+        .args(args)
+        .env("PATH", path)
+        .stdin(Stdio::piped())
+        .output()
+        .expect("vssh should start");
+    Run {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        status: output.status.code().expect("vssh should exit normally"),
+    }
+}
+
+/// Runs `script` and parses each line of its output as JSON.
+#[track_caller]
+fn json_lines(script: &str, args: &[&str]) -> Vec<Value> {
+    let run = vssh(script, args);
+    assert_eq!(run.status, 0, "{script:?} failed: {}", run.stderr);
+    run.stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("{line:?}: {e}")))
+        .collect()
+}
+
+#[track_caller]
+fn argv(script: &str, args: &[&str]) -> Vec<String> {
+    let lines = json_lines(script, args);
+    assert_eq!(lines.len(), 1, "{script:?}");
+    serde_json::from_value(lines[0].clone()).unwrap()
+}
+
+fn fds(value: &Value) -> BTreeSet<i32> {
+    value.as_object().unwrap().keys().map(|fd| fd.parse().unwrap()).collect()
+}
+
+// 2.2 Quoting
+
+#[test]
+fn quoting_and_quote_removal() {
+    assert_eq!(argv(r#"cmd --argv a\ b "c"d'e' '$x' "\$x" \\"#, &[]), ["a b", "cde", "$x", "$x", "\\"]);
+    assert_eq!(argv(r#"cmd --argv "" '' a"""#, &[]), ["", "", "a"]);
+}
+
+// 2.5.2 Special Parameters
+
+#[test]
+fn at_and_star() {
+    assert_eq!(argv(r#"cmd --argv "$@""#, &["a b", "", "c"]), ["a b", "", "c"]);
+    assert_eq!(argv(r#"cmd --argv "$*""#, &["a b", "c"]), ["a b c"]);
+    assert_eq!(argv("cmd --argv $@", &["a b", "c"]), ["a", "b", "c"]);
+    assert_eq!(argv(r#"cmd --argv "$@""#, &[]), Vec::<String>::new());
+    assert_eq!(argv(r#"cmd --argv $# "$0" "$1""#, &["x", "y"]), ["2", "vssh", "x"]);
+}
+
+#[test]
+fn question_mark_and_bang() {
+    assert_eq!(vssh("cmd --exit 7; echo $?; echo $?", &[]).stdout, "7\n0\n");
+    let run = vssh("cmd --pgid & pid=$!; wait; echo $pid", &[]);
+    let mut out = run.stdout.lines();
+    let job: Value = serde_json::from_str(out.next().unwrap()).unwrap();
+    assert_eq!(job["pid"].to_string(), out.next().unwrap());
+}
+
+#[test]
+fn dollar_dollar_is_the_shell() {
+    let run = vssh("echo $$; cmd --pgid", &[]);
+    let mut out = run.stdout.lines();
+    let shell = out.next().unwrap();
+    let child: Value = serde_json::from_str(out.next().unwrap()).unwrap();
+    assert_eq!(child["ppid"].to_string(), shell);
+}
+
+// 2.6.2 Parameter Expansion
+
+#[test]
+fn parameter_expansion_forms() {
+    let script = r#"set -- set; e=; unset u
+        cmd --argv "${u-d}" "${e-d}" "${e:-d}" "${1:+alt}" "${u+alt}" "${#1}""#;
+    assert_eq!(argv(script, &[]), ["d", "", "d", "alt", "", "3"]);
+    assert_eq!(argv(r#"unset u; : ${u:=v}; cmd --argv "$u""#, &[]), ["v"]);
+}
+
+#[test]
+fn unset_parameter_with_question_mark_fails() {
+    let run = vssh("unset u; echo ${u?nope}; echo after", &[]);
+    assert_eq!(run.stdout, "");
+    assert!(run.stderr.contains("nope"), "{}", run.stderr);
+    assert_ne!(run.status, 0);
+}
+
+// 2.6.5 Field Splitting
+
+#[test]
+fn unquoted_expansions_are_split() {
+    assert_eq!(argv(r#"x='a  b	c'; cmd --argv $x "$x""#, &[]), ["a", "b", "c", "a  b\tc"]);
+    assert_eq!(argv("x=' lead trail '; cmd --argv $x", &[]), ["lead", "trail"]);
+}
+
+#[test]
+fn empty_unquoted_expansions_disappear() {
+    assert_eq!(argv(r#"e=; cmd --argv $e "$e" $e"""#, &[]), ["", ""]);
+    assert_eq!(argv("unset u; cmd --argv $u $u", &[]), Vec::<String>::new());
+}
+
+#[test]
+fn fields_are_split_on_ifs() {
+    assert_eq!(argv("IFS=:; x='a::b:'; cmd --argv $x", &[]), ["a", "", "b"]);
+    assert_eq!(argv("IFS=' :'; x=' :a : b  c'; cmd --argv $x", &[]), ["", "a", "b", "c"]);
+    assert_eq!(argv("IFS=; x='a b'; cmd --argv $x", &[]), ["a b"]);
+    assert_eq!(argv("unset IFS; x='a  b'; cmd --argv $x", &[]), ["a", "b"]);
+}
+
+// 2.7 Redirection
+
+#[test]
+fn redirections_apply_left_to_right() {
+    assert_eq!(vssh("cmd --stderr x 2>&1 >/dev/null", &[]).stdout, "x\n");
+    let run = vssh("cmd --stderr x >/dev/null 2>&1", &[]);
+    assert_eq!((run.stdout.as_str(), run.stderr.as_str()), ("", ""));
+}
+
+#[test]
+fn input_and_output_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("f");
+    let file = file.display();
+    let run = vssh(&format!("echo one >{file}; echo two >>{file}; cmd --stdin <{file}"), &[]);
+    assert_eq!(run.stdout, "one\ntwo\n");
+    let run = vssh(&format!("echo new >{file}; cmd --stdin 0<{file}"), &[]);
+    assert_eq!(run.stdout, "new\n");
+}
+
+#[test]
+fn failed_redirection_skips_the_command() {
+    let run = vssh("cmd --argv ran </nonexistent/file; echo $?", &[]);
+    assert!(!run.stdout.contains("ran"));
+    assert_eq!(run.stdout.lines().last(), Some("1"));
+    assert!(!run.stderr.is_empty());
+}
+
+#[test]
+fn numbered_descriptors_reach_the_command() {
+    let lines = json_lines("cmd --fds 3>/dev/null 4</dev/null 5<>/dev/null", &[]);
+    assert_eq!(fds(&lines[0]), BTreeSet::from([0, 1, 2, 3, 4, 5]));
+    assert_eq!(lines[0]["5"], "/dev/null");
+}
+
+#[test]
+fn the_shell_leaks_no_descriptors() {
+    let lines = json_lines("cmd --fds; cmd --fds | cmd --stdin; cmd --fds & wait", &[]);
+    for line in &lines {
+        assert_eq!(fds(line), BTreeSet::from([0, 1, 2]), "{line}");
+    }
+}
+
+#[test]
+fn duplicating_a_descriptor() {
+    let lines = json_lines("cmd --fds 3>&1", &[]);
+    assert_eq!(lines[0]["3"], lines[0]["1"]);
+}
+
+// 2.8.2 Exit Status for Commands
+
+#[test]
+fn exit_statuses() {
+    assert_eq!(vssh("cmd --exit 3; echo $?", &[]).stdout, "3\n");
+    assert_eq!(vssh("cmd --exit 300; echo $?", &[]).stdout, "44\n");
+    assert_eq!(vssh("cmd --exit 9", &[]).status, 9);
+    assert_eq!(vssh("vssh-no-such-command; echo $?", &[]).stdout, "127\n");
+}
+
+#[test]
+fn signals_give_statuses_above_128() {
+    assert_eq!(vssh("cmd --signal TERM; echo $?", &[]).stdout, "143\n");
+    assert_eq!(vssh("cmd --signal KILL; echo $?", &[]).stdout, "137\n");
+    assert_eq!(vssh("cmd --sleep 10 & kill $!; wait $!; echo $?", &[]).stdout, "143\n");
+}
+
+// 2.9.1 Simple Commands
+
+#[test]
+fn assignments_before_a_command_are_temporary() {
+    let lines = json_lines("X=1 cmd --env X; cmd --env X; echo \"\\\"$X\\\"\"", &[]);
+    assert_eq!(lines, [serde_json::json!({"X": "1"}), serde_json::json!({"X": null}), Value::from("")]);
+}
+
+#[test]
+fn only_exported_variables_reach_commands() {
+    let lines = json_lines("A=1; export B=2; C=3; export C; cmd --env A B C", &[]);
+    assert_eq!(lines[0], serde_json::json!({"A": null, "B": "2", "C": "3"}));
+}
+
+#[test]
+fn assignments_alone_set_shell_variables() {
+    assert_eq!(argv(r#"a=1 b=$a; cmd --argv "$a$b""#, &[]), ["11"]);
+}
+
+// 2.9.2 Pipelines
+
+#[test]
+fn pipeline_status_is_the_last_command() {
+    assert_eq!(vssh("cmd --exit 3 | cmd --exit 0; echo $?", &[]).stdout, "0\n");
+    assert_eq!(vssh("cmd --exit 0 | cmd --exit 4; echo $?", &[]).stdout, "4\n");
+    assert_eq!(vssh("! cmd --exit 3; echo $?; ! cmd --exit 0; echo $?", &[]).stdout, "0\n1\n");
+}
+
+#[test]
+fn pipes_connect_stdout_to_stdin() {
+    assert_eq!(vssh("cmd a b | cmd --stdin | cmd --stdin", &[]).stdout, "a\nb\n");
+    let run = vssh("cmd --stderr err | cmd --stdin", &[]);
+    assert_eq!((run.stdout.as_str(), run.stderr.as_str()), ("", "err\n"));
+}
+
+// 2.9.3 Lists
+
+#[test]
+fn and_or_lists() {
+    assert_eq!(vssh("cmd --exit 4 && echo no || echo yes", &[]).stdout, "yes\n");
+    assert_eq!(vssh("cmd --exit 0 || echo no && echo yes", &[]).stdout, "yes\n");
+}
+
+#[test]
+fn asynchronous_lists_read_from_dev_null() {
+    // Without job control, a background command's stdin is /dev/null even
+    // when the shell's is not.
+    let lines = json_lines("cmd --fds & wait", &[]);
+    assert_eq!(lines[0]["0"], "/dev/null");
+}
+
+#[test]
+fn asynchronous_lists_stay_in_the_shells_process_group() {
+    let lines = json_lines("cmd --pgid; cmd --pgid & wait", &[]);
+    assert_eq!(lines[0]["pgid"], lines[1]["pgid"]);
+}
+
+#[test]
+fn wait_returns_the_jobs_status() {
+    let run = vssh("cmd --sleep 0.1 --exit 6 & echo started; wait $!; echo $?", &[]);
+    assert_eq!(run.stdout, "started\n6\n");
+}
+
+// 2.9.4 Compound Commands
+
+#[test]
+fn if_and_loops() {
+    assert_eq!(vssh("if cmd --exit 1; then echo a; elif cmd --exit 0; then echo b; fi", &[]).stdout, "b\n");
+    assert_eq!(vssh("if cmd --exit 1; then echo a; fi; echo $?", &[]).stdout, "0\n");
+    assert_eq!(vssh("i=; while cmd --exit ${i:+1}0; do i=x; echo loop; done", &[]).stdout, "loop\n");
+    assert_eq!(vssh("i=; until [ \"$i\" = xx ]; do i=x$i; done; echo $i", &[]).stdout, "xx\n");
+}
+
+// 2.14 Special Built-In Utilities
+
+#[test]
+fn errexit() {
+    let run = vssh("set -e; cmd --exit 5; echo no", &[]);
+    assert_eq!((run.stdout.as_str(), run.status), ("", 5));
+    let run = vssh("set -e; cmd --exit 5 || echo handled; ! cmd --exit 0; echo yes", &[]);
+    assert_eq!(run.stdout, "handled\nyes\n");
+}
+
+#[test]
+fn exit_trap_runs_on_exit() {
+    let run = vssh("trap 'echo bye $?' EXIT; cmd --exit 3; exit", &[]);
+    assert_eq!((run.stdout.as_str(), run.status), ("bye 3\n", 3));
+}
+
+#[test]
+fn set_replaces_positional_parameters() {
+    assert_eq!(argv(r#"set -- x "y z"; cmd --argv "$#" "$@""#, &["a"]), ["2", "x", "y z"]);
+}
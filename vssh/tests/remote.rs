@@ -0,0 +1,163 @@
+//! Runs `vssh --listen` on a local port and talks to it, both with
+//! `vssh --connect` and frame by frame.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+use serde_json::json;
+
+const SECRET: &str = "lab secret";
+
+/// A `vssh --listen 127.0.0.1:0` server, killed when dropped.
+struct Server {
+    child: Child,
+    addr: String,
+    log: Receiver<String>,
+}
+
+impl Server {
+    fn start() -> Server {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_vssh"))
+            .args(["--listen", "127.0.0.1:0"])
+            .env("VSSH_SECRET", SECRET)
+            .stdin(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("vssh should start");
+        let (send, log) = mpsc::channel();
+        let stderr = BufReader::new(child.stderr.take().unwrap());
+        std::thread::spawn(move || {
+            for line in stderr.lines().map_while(Result::ok) {
+                let _ = send.send(line);
+            }
+        });
+        let first = log.recv_timeout(Duration::from_secs(10)).expect("server should report its address");
+        let addr = first.strip_prefix("vssh: listening on ").expect(&first).to_string();
+        Server { child, addr, log }
+    }
+
+    /// Waits for a line of the server's log that contains `text`.
+    fn expect_log(&self, text: &str) -> String {
+        loop {
+            let line = self.log.recv_timeout(Duration::from_secs(10)).unwrap_or_else(|_| panic!("no log line with {text:?}"));
+            if line.contains(text) {
+                return line;
+            }
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn send(stream: &mut TcpStream, kind: u8, payload: &[u8]) {
+    let mut frame = vec![kind];
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    stream.write_all(&frame).unwrap();
+}
+
+fn receive(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+    let mut header = [0u8; 5];
+    stream.read_exact(&mut header).unwrap();
+    let mut payload = vec![0; u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize];
+    stream.read_exact(&mut payload).unwrap();
+    (header[0], payload)
+}
+
+/// Connects and authenticates as a client with a 24x80 window.
+fn open_session(server: &Server) -> TcpStream {
+    let mut stream = TcpStream::connect(&server.addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    let hello = json!({"secret": SECRET, "term": "dumb", "rows": 24, "cols": 80});
+    send(&mut stream, b'H', hello.to_string().as_bytes());
+    assert_eq!(receive(&mut stream).0, b'O');
+    stream
+}
+
+/// Reads terminal output until it contains `text`, and returns it all.
+fn read_until(stream: &mut TcpStream, text: &str) -> String {
+    let mut output = String::new();
+    while !output.contains(text) {
+        match receive(stream) {
+            (b'D', payload) => output.push_str(&String::from_utf8_lossy(&payload)),
+            (kind, _) => panic!("unexpected frame {} after {output:?}", kind as char),
+        }
+    }
+    output
+}
+
+fn connect(server: &Server, secret: &str, input: &str) -> (String, String, i32) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_vssh"))
+        .args(["--connect", &server.addr])
+        .env("VSSH_SECRET", secret)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("vssh should start");
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    (
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+        output.status.code().expect("vssh should exit normally"),
+    )
+}
+
+#[test]
+fn client_runs_commands_on_the_server() {
+    let server = Server::start();
+    let (stdout, stderr, status) = connect(&server, SECRET, "x=42; echo remote-$x; exit 3\n");
+    assert_eq!(status, 3, "{stderr}");
+    assert!(stdout.contains("remote-42"), "{stdout}");
+    server.expect_log("session ended with status 3");
+}
+
+#[test]
+fn wrong_secret_is_refused() {
+    let server = Server::start();
+    let (stdout, stderr, status) = connect(&server, "guess", "echo never\n");
+    assert_eq!(status, 1);
+    assert!(stderr.contains("authentication failed"), "{stderr}");
+    assert!(!stdout.contains("never"), "{stdout}");
+    server.expect_log("authentication failed");
+}
+
+#[test]
+fn resize_frames_reach_the_terminal() {
+    let server = Server::start();
+    let mut stream = open_session(&server);
+    send(&mut stream, b'W', &[0, 40, 0, 100]);
+    send(&mut stream, b'D', b"stty size\n");
+    read_until(&mut stream, "40 100");
+    send(&mut stream, b'D', b"exit 5\n");
+    loop {
+        match receive(&mut stream) {
+            (b'X', payload) => {
+                assert_eq!(payload, 5i32.to_be_bytes());
+                break;
+            }
+            (b'D', _) => {}
+            (kind, _) => panic!("unexpected frame {}", kind as char),
+        }
+    }
+}
+
+#[test]
+fn closing_the_connection_ends_the_session() {
+    let server = Server::start();
+    let mut stream = open_session(&server);
+    send(&mut stream, b'D', b"echo ready\n");
+    read_until(&mut stream, "ready");
+    drop(stream);
+    // The shell is left to its hangup signal.
+    server.expect_log("session ended with status 129");
+}
@@ -0,0 +1,562 @@
+//! Runs scripts through the `vssh` binary and checks what they print and
+//! how they exit.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use serde_json::{json, Value};
+
+struct Run {
+    stdout: String,
+    stderr: String,
+    status: i32,
+}
+
+fn vssh(args: &[&str], stdin: &str) -> Run {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_vssh"))
+        .args(args)
+        .env_remove("TIMEFORMAT")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("vssh should start");
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    Run {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        status: output.status.code().expect("vssh should exit normally"),
+    }
+}
+
+fn script(text: &str) -> Run {
+    vssh(&["-c", text], "")
+}
+
+#[track_caller]
+fn assert_output(text: &str, stdout: &str) {
+    let run = script(text);
+    assert_eq!(run.stdout, stdout, "stdout of {text:?}, stderr {:?}", run.stderr);
+    assert_eq!(run.status, 0, "status of {text:?}, stderr {:?}", run.stderr);
+}
+
+#[test]
+fn quoting_keeps_whitespace() {
+    assert_output(r#"echo "a  b" 'c  d' e\ \ f"#, "a  b c  d e  f\n");
+}
+
+#[test]
+fn parameter_expansion() {
+    assert_output("x=1; unset u; echo $x ${x} ${u:-def} ${u-} ${x:+alt} ${#x}", "1 1 def alt 1\n");
+    assert_output("echo ${u:=set}; echo $u", "set\nset\n");
+}
+
+#[test]
+fn unset_parameter_with_error_word() {
+    let run = script("echo ${missing:?is not set}; echo after");
+    assert_eq!(run.stdout, "");
+    assert!(run.stderr.contains("missing: is not set"), "{}", run.stderr);
+    assert_ne!(run.status, 0);
+}
+
+#[test]
+fn arrays() {
+    assert_output("arr=(a b c); echo ${arr[1]} ${#arr[@]}; arr+=(d); echo ${arr[@]}", "b 3\na b c d\n");
+    assert_output("declare -A m; m[k]=v; echo ${m[k]} ${!m[@]}", "v k\n");
+}
+
+#[test]
+fn and_or_lists_and_status() {
+    assert_output("true && echo a; false && echo b; false || echo c; echo $?", "a\nc\n0\n");
+    assert_output("false; echo $?; ! true; echo $?", "1\n1\n");
+}
+
+#[test]
+fn if_and_while() {
+    assert_output("if false; then echo a; elif true; then echo b; else echo c; fi", "b\n");
+    assert_output("i=; while [[ $i != xxx ]]; do i=${i}x; done; echo $i", "xxx\n");
+    assert_output("i=; until [[ $i == xx ]]; do i=${i}x; echo $i; done", "x\nxx\n");
+}
+
+#[test]
+fn conditional_expressions() {
+    assert_output("[[ abc == a* && -d / ]] && echo yes; [[ 2 -lt 10 ]] && echo lt", "yes\nlt\n");
+    assert_output("test -z '' && [ a = a ] && echo ok", "ok\n");
+}
+
+#[test]
+fn pipelines_run_external_commands() {
+    assert_output("printf 'b\\na\\n' | sort | head -n 1", "a\n");
+}
+
+#[test]
+fn redirections() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("out");
+    let file = file.display();
+    assert_output(&format!("echo one > {file}; echo two >> {file}; cat < {file}"), "one\ntwo\n");
+    let run = script("echo to-stderr >&2; echo to-stdout");
+    assert_eq!(run.stdout, "to-stdout\n");
+    assert_eq!(run.stderr, "to-stderr\n");
+}
+
+#[test]
+fn noclobber_refuses_to_overwrite() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("out");
+    let run = script(&format!("echo a > {0}; set -C; echo b > {0}; cat {0}", file.display()));
+    assert_eq!(run.stdout, "a\n");
+    assert!(!run.stderr.is_empty());
+}
+
+#[test]
+fn errexit_stops_the_script() {
+    let run = script("set -e; echo before; false; echo after");
+    assert_eq!(run.stdout, "before\n");
+    assert_eq!(run.status, 1);
+    assert_output("set -e; if false; then :; fi; false || true; echo still", "still\n");
+}
+
+#[test]
+fn exit_status_and_exit_trap() {
+    let run = script("trap 'echo bye' EXIT; echo hi; exit 3");
+    assert_eq!(run.stdout, "hi\nbye\n");
+    assert_eq!(run.status, 3);
+}
+
+#[test]
+fn command_not_found() {
+    let run = script("vssh-no-such-command");
+    assert_eq!(run.status, 127);
+    assert!(!run.stderr.is_empty());
+}
+
+#[test]
+fn command_not_found_suggests_close_names() {
+    let run = script("ehco hi; exprot x");
+    assert_eq!(run.status, 127);
+    assert!(run.stderr.contains("ehco: command not found"), "{}", run.stderr);
+    assert!(run.stderr.contains("did you mean: echo"), "{}", run.stderr);
+    assert!(run.stderr.contains("did you mean: export"), "{}", run.stderr);
+}
+
+#[test]
+fn command_not_found_handle_runs_instead() {
+    assert_output(
+        "command_not_found_handle() { echo \"missing $1 with $#\"; return 3; }; nope a b; echo $?; nope | cat",
+        "missing nope with 3\n3\nmissing nope with 1\n",
+    );
+}
+
+#[test]
+fn functions_and_return() {
+    assert_output(
+        "f() { echo \"$1-$#\"; return 4; echo no; }; f a b; echo $?; function g { { echo g; }; }; g | cat",
+        "a-2\n4\ng\n",
+    );
+    assert_output("f() { echo f; }; type f; unset -f f; type -t f || echo gone", "f is a function\ngone\n");
+}
+
+#[test]
+fn hash_remembers_programs_until_path_changes() {
+    assert_output(
+        "PATH=/usr/bin; hash cat; cat </dev/null; hash -t cat; type cat; PATH=/usr/bin:/bin; hash 2>&1",
+        "/usr/bin/cat\ncat is hashed (/usr/bin/cat)\nhash: hash table empty\n",
+    );
+}
+
+#[test]
+fn unrunnable_files_exit_126() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("plain"), "echo hi\n").unwrap();
+    let text = format!("cd {0}; ./plain; echo $?; ./missing; echo $?; PATH={0}; plain; echo $?", dir.path().display());
+    let run = script(&text);
+    assert_eq!(run.stdout, "126\n127\n126\n", "{}", run.stderr);
+    assert!(run.stderr.contains("./plain: Permission denied"), "{}", run.stderr);
+}
+
+#[test]
+fn exec_redirections_persist() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("log");
+    let text = format!(
+        "exec 3>{0}; echo one >&3; sh -c 'echo two >&3' | cat; exec 3>&-; echo three >&3 || echo closed; exec sh -c 'echo $0' replaced; echo not reached",
+        log.display()
+    );
+    let run = script(&text);
+    assert_eq!(run.stdout, "closed\nreplaced\n", "{}", run.stderr);
+    assert_eq!(std::fs::read_to_string(&log).unwrap(), "one\ntwo\n");
+
+    // Passing descriptors on works within a low limit on open files.
+    let text = format!("ulimit -n 20; exec 3>{0}; /bin/echo hi; sh -c 'echo there >&3'; cat {0}", log.display());
+    assert_output(&text, "hi\nthere\n");
+}
+
+#[test]
+fn coprocesses() {
+    assert_output(
+        "coproc UP { while read -r line; do echo \"$line\" | tr a-z A-Z; done; echo done; }; \
+         echo hi >&${UP[1]}; read -r r <&${UP[0]}; echo $r; \
+         fd=${UP[1]}; exec {fd}>&-; cat <&${UP[0]}; wait $UP_PID; echo $?",
+        "HI\ndone\n0\n",
+    );
+    // Neither a later coprocess nor a background job holds the shell's ends,
+    // so closing the input still ends the first one.
+    assert_output(
+        "coproc A { cat; }; coproc B { cat; }; sleep 60 >/dev/null 2>&1 & \
+         fd=${A[1]}; exec {fd}>&-; wait $A_PID; echo $?; kill $!",
+        "0\n",
+    );
+}
+
+#[test]
+fn named_pipes_connect_commands() {
+    let dir = tempfile::tempdir().unwrap();
+    let text = format!(
+        "TMPDIR={}; fifo p; test -p $p && echo pipe; echo through >$p & read -r line <$p; echo $line; \
+         fifo -d p; echo ${{p-unset}}; fifo q; echo $q",
+        dir.path().display()
+    );
+    let run = script(&text);
+    let lines: Vec<&str> = run.stdout.lines().collect();
+    assert_eq!(lines[..3], ["pipe", "through", "unset"], "{}", run.stderr);
+    // Pipes left over go when the shell exits, along with their directory.
+    let q = std::path::Path::new(lines[3]);
+    assert!(q.starts_with(dir.path()) && !q.exists() && !q.parent().unwrap().exists(), "{q:?}");
+
+    // So do they when exec replaces the shell.
+    let run = script(&format!("TMPDIR={}; fifo p; echo $p; exec true", dir.path().display()));
+    let p = std::path::Path::new(run.stdout.trim_end());
+    assert!(p.starts_with(dir.path()) && !p.parent().unwrap().exists(), "{p:?}");
+
+    let run = script("p=/etc/passwd; fifo -d p; echo $?; fifo 1x; echo $?");
+    assert_eq!(run.stdout, "1\n1\n");
+    assert!(run.stderr.contains("p: not a named pipe made by fifo"), "{}", run.stderr);
+}
+
+#[test]
+fn syntax_error_runs_nothing() {
+    let run = script("echo first; echo \"unterminated");
+    assert_eq!(run.stdout, "");
+    assert!(run.stderr.contains("syntax error"), "{}", run.stderr);
+    assert_eq!(run.status, 2);
+}
+
+#[test]
+fn printf_and_read() {
+    assert_output("printf '%s=%03d\\n' a 7 b 42", "a=007\nb=042\n");
+    let run = vssh(&["-c", "read a b; echo \"$b,$a\""], "one two three\n");
+    assert_eq!(run.stdout, "two three,one\n");
+}
+
+#[test]
+fn script_file_and_positional_parameters() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("args.sh");
+    std::fs::write(&path, "echo $# $1 $2\nexit $#\n").unwrap();
+    let run = vssh(&[path.to_str().unwrap(), "p", "q"], "");
+    assert_eq!(run.stdout, "2 p q\n");
+    assert_eq!(run.status, 2);
+
+    let run = vssh(&["-c", "echo $0 $1", "name", "arg"], "");
+    assert_eq!(run.stdout, "name arg\n");
+
+    let run = vssh(&["-c"], "echo interactive\n");
+    assert_eq!(run.status, 2);
+    assert_eq!(run.stdout, "");
+    assert_eq!(run.stderr, "vssh: -c: option requires an argument\n");
+}
+
+#[test]
+fn commands_from_stdin() {
+    let run = vssh(&[], "cd /\npwd\nif true\nthen echo multi\nfi\n");
+    assert_eq!(run.stdout, "/\nmulti\n");
+    assert_eq!(run.status, 0);
+}
+
+#[test]
+fn continued_lines_from_stdin() {
+    let run = vssh(&[], "echo one \\\ntwo\necho \"a\nb\" 'c\nd'\n# not continued \\\necho last \\\n");
+    assert_eq!(run.stdout, "one two\na\nb c\nd\nlast\n", "{}", run.stderr);
+    assert_eq!(run.status, 0);
+}
+
+#[test]
+fn ansi_c_quoting() {
+    assert_output(r#"echo $'a\tb\x41\u00e9\101' $'it\'s' $'\cA' | od -An -c"#, "   a  \\t   b   A 303 251   A       i   t   '   s     001  \\n\n");
+    assert_output(r#"x=$'1\n2'; echo "$x" $"q $x""#, "1\n2 q 1\n2\n");
+}
+
+#[test]
+fn audit_log_records_commands() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("audit.log");
+    let log_path = log.to_str().unwrap();
+    let text = format!("cd {}; x=out; echo hi >$x; sh -c 'kill -9 $$'", dir.path().display());
+    let run = vssh(&["--audit-log", log_path, "-c", &text], "");
+    assert_eq!(run.status, 137, "{}", run.stderr);
+
+    let records: Vec<Value> = std::fs::read_to_string(&log)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let argv: Vec<&Value> = records.iter().map(|r| &r["argv"]).collect();
+    assert_eq!(argv, [&json!(["cd", dir.path()]), &json!(["echo", "hi"]), &json!(["sh", "-c", "kill -9 $$"])]);
+    assert_eq!(records[1]["type"], "builtin");
+    assert_eq!(records[1]["redirections"], json!(["1>out"]));
+    assert_eq!(records[1]["cwd"], dir.path().to_str().unwrap());
+    assert_eq!(records[2]["type"], "external");
+    assert_eq!((&records[2]["status"], &records[2]["signal"]), (&json!(137), &json!("SIGKILL")));
+    assert!(records[2]["rusage"]["maxrss"].as_i64().unwrap() > 0);
+}
+
+#[test]
+fn audit_log_records_jobs_and_missing_commands() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("audit.log");
+    let run = vssh(&["--audit-log", log.to_str().unwrap(), "-c", "sh -c 'exit 3' & wait; no-such-command x"], "");
+    assert_eq!(run.status, 127, "{}", run.stderr);
+
+    let records: Vec<Value> = std::fs::read_to_string(&log)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let argv: Vec<&Value> = records.iter().map(|r| &r["argv"]).collect();
+    assert_eq!(argv, [&json!(["sh", "-c", "exit 3"]), &json!(["wait"]), &json!(["no-such-command", "x"])]);
+    assert_eq!((&records[0]["type"], &records[0]["status"]), (&json!("external"), &json!(3)));
+    assert!(records[0]["pid"].as_u64().is_some());
+    assert_eq!((&records[2]["status"], &records[2]["pid"]), (&json!(127), &Value::Null));
+}
+
+#[test]
+fn timeout_kills_slow_commands() {
+    assert_output("timeout 5 echo fast; timeout 0.1 sh -c 'sleep 5; echo late'; echo $?", "fast\n124\n");
+    assert_output("timeout -s KILL 0.1 sleep 5; echo $?; timeout --preserve-status 0.1 sleep 5; echo $?", "124\n143\n");
+    assert_output("timeout -k 0.1 0.1 sh -c 'trap \"\" TERM; sleep 5'; echo $?", "137\n");
+}
+
+#[test]
+fn timeout_passes_on_interrupts() {
+    let started = std::time::Instant::now();
+    assert_output("trap 'echo caught' INT; sh -c 'sleep 0.2; kill -INT $PPID' & timeout 5 sleep 3; echo $?", "caught\n130\n");
+    assert!(started.elapsed() < std::time::Duration::from_secs(2));
+}
+
+#[test]
+fn repeat_and_every_rerun_commands() {
+    assert_output("f() { echo a | tr a b; }; repeat -n 2 f; every -n 2 0.05 true; echo $?", "b\nb\n0\n");
+    assert_output("repeat -n 2 'echo a | tr a b'; every -n 2 0.05 echo c '|' tr c d", "b\nb\nd\nd\n");
+    // The text is parsed once, before the first run.
+    let run = script("repeat -n 2 'echo a; if'; echo $?");
+    assert_eq!(run.stdout, "2\n");
+    assert!(run.stderr.starts_with("repeat: "), "{}", run.stderr);
+    let dir = tempfile::tempdir().unwrap();
+    let text = format!("cd {}; f() {{ echo x >>f; cat f; ls; }}; repeat -d -n 3 f", dir.path().display());
+    assert_output(&text, "x\nf\n x\n+x\n f\n x\n x\n+x\n f\n");
+}
+
+#[test]
+fn restricted_mode_refuses_commands_by_path() {
+    let run = vssh(&["-r", "-c", "/bin/echo plain; echo $?"], "");
+    assert_eq!(run.stdout, "1\n");
+    assert!(run.stderr.contains("/bin/echo: restricted"), "{}", run.stderr);
+
+    let run = vssh(&["-r", "-c", "/bin/echo piped | cat; echo ${PIPESTATUS[0]}"], "");
+    assert_eq!(run.stdout, "1\n");
+    assert!(run.stderr.contains("/bin/echo: restricted"), "{}", run.stderr);
+
+    let run = vssh(&["-r", "-c", "/bin/echo background & wait $!; echo $?"], "");
+    assert_eq!(run.stdout, "1\n");
+    assert!(run.stderr.contains("/bin/echo: restricted"), "{}", run.stderr);
+}
+
+#[test]
+fn restricted_mode_refuses_writes_and_protected_variables() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("out");
+    let run = vssh(&["-r", "-c", &format!("echo x > {}; echo $?", file.display())], "");
+    assert_eq!(run.stdout, "1\n");
+    assert!(run.stderr.contains("restricted: cannot redirect output"), "{}", run.stderr);
+    assert!(!file.exists());
+
+    // As for any failed assignment, a script stops there.
+    let run = vssh(&["-r", "-c", "PATH=/tmp; echo not reached"], "");
+    assert_eq!((run.stdout.as_str(), run.status), ("", 1));
+    assert!(run.stderr.contains("PATH: readonly variable"), "{}", run.stderr);
+
+    let run = vssh(&["-r", "-c", "shopt -o -u restricted; echo $?; shopt -o -q restricted && echo still"], "");
+    assert_eq!(run.stdout, "1\nstill\n");
+    assert_eq!(run.stderr, "shopt: restricted: cannot turn off restricted mode\n");
+}
+
+#[test]
+fn record_writes_an_asciicast_and_replays_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let cast = dir.path().join("session.cast");
+    let run = script(&format!("record -o {} echo hi", cast.display()));
+    assert_eq!(run.status, 0, "{}", run.stderr);
+    assert!(run.stdout.contains("hi\r\n"), "{}", run.stdout);
+
+    let text = std::fs::read_to_string(&cast).unwrap();
+    let mut lines = text.lines();
+    let header: Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+    assert_eq!((&header["version"], &header["command"]), (&json!(2), &json!("echo hi")));
+    assert!(header["width"].as_u64().unwrap() > 0 && header["timestamp"].as_u64().is_some());
+    let event: Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+    assert!(event[0].as_f64().unwrap() >= 0.0);
+    assert_eq!((&event[1], &event[2]), (&json!("o"), &json!("hi\r\n")));
+
+    assert_output(&format!("record replay -s 100 {}", cast.display()), "hi\r\n");
+}
+
+#[test]
+fn restricted_mode_refuses_terminals() {
+    for command in ["pty /bin/sh -c 'echo escaped'", "pty sh -c 'echo escaped'", "record -o /dev/null"] {
+        let run = vssh(&["-r", "-c", command], "");
+        assert_eq!(run.status, 1, "{command}");
+        assert!(!run.stdout.contains("escaped"), "{command}: {}", run.stdout);
+        assert!(run.stderr.contains("restricted"), "{command}: {}", run.stderr);
+    }
+}
+
+#[test]
+fn exported_variables_reach_children() {
+    assert_output("export V=1; W=2; sh -c 'echo $V-$W'; W=3 sh -c 'echo $W'", "1-\n3\n");
+}
+
+#[test]
+fn isolate_reports_namespaces_it_cannot_create() {
+    let dir = tempfile::tempdir().unwrap();
+    let profile = dir.path().join("no-unshare");
+    std::fs::write(&profile, "deny unshare\naction errno EPERM\n").unwrap();
+    let inner = "isolate -h box hostname; echo status $?";
+    let run = script(&format!("sandbox {} {} -c '{inner}'", profile.display(), env!("CARGO_BIN_EXE_vssh")));
+    assert_eq!(run.stdout, "status 1\n");
+    assert!(run.stderr.contains("isolate: cannot create namespaces: Operation not permitted"), "{}", run.stderr);
+
+    let run = script(&format!("isolate -r {} true", dir.path().display()));
+    assert_eq!(run.status, 1);
+    assert!(run.stderr.contains("no proc directory to mount on"), "{}", run.stderr);
+    assert_eq!(script("isolate -h box").status, 2);
+}
+
+#[test]
+fn sandbox_profiles_filter_system_calls() {
+    let dir = tempfile::tempdir().unwrap();
+    let write = |name: &str, text: &str| {
+        let path = dir.path().join(name);
+        std::fs::write(&path, text).unwrap();
+        path.display().to_string()
+    };
+    let errno = write("errno", "# no new directories\ndeny mkdir mkdirat\naction errno EACCES\n");
+    let kill = write("kill", "deny mkdir mkdirat\n");
+    let text = format!(
+        "cd {}; sandbox {errno} mkdir a; echo $?; sandbox {kill} mkdir b; echo $?; sandbox {errno}; sandbox; sandbox -r; sandbox; mkdir c; ls",
+        dir.path().display()
+    );
+    let run = script(&text);
+    assert_eq!(run.stdout, format!("1\n159\n{errno}\nc\nerrno\nkill\n"), "{}", run.stderr);
+    assert!(run.stderr.contains("Permission denied"), "{}", run.stderr);
+    assert!(run.stderr.contains(&format!("forbidden by {kill}")), "{}", run.stderr);
+}
+
+#[test]
+fn sandbox_rejects_bad_profiles() {
+    let dir = tempfile::tempdir().unwrap();
+    for (text, error) in [
+        ("deny mkdir\ndeny no_such_call\n", ":2: unknown system call `no_such_call'"),
+        ("deny mkdir\naction explode\n", ":2: expected `action kill' or `action errno [ERRNO]'"),
+        ("deny mkdir\naction errno ENOTANERRNO\n", ":2: unknown errno `ENOTANERRNO'"),
+        ("allow read\ndeny write\n", ":2: a profile cannot both allow and deny"),
+        ("# nothing\n", ": no allow or deny rules"),
+    ] {
+        let profile = dir.path().join("profile");
+        std::fs::write(&profile, text).unwrap();
+        let run = script(&format!("sandbox {} echo ran", profile.display()));
+        assert_eq!((run.stdout.as_str(), run.status), ("", 1), "{text:?}");
+        assert!(run.stderr.contains(&format!("sandbox: {}{error}", profile.display())), "{text:?}: {}", run.stderr);
+    }
+}
+
+#[test]
+fn ulimit_sets_and_reads_back_limits() {
+    assert_output(
+        "ulimit -Sn 100; ulimit -n; ulimit -Hn 200; ulimit -Hn; ulimit -Sn; ulimit -Sn hard; ulimit -n",
+        "100\n200\n100\n200\n",
+    );
+    assert_output("ulimit -n 64; ulimit -Hn; ulimit -a | grep '^open files'", "64\nopen files                         (-n) 64\n");
+    assert_output("ulimit -n 100; limit -n 50 sh -c 'ulimit -n'; ulimit -n", "50\n100\n");
+
+    let run = script("ulimit -Hn 100; ulimit -Sn 200; echo $?; ulimit -Sn");
+    assert_eq!(run.stdout, "1\n100\n");
+    assert!(run.stderr.contains("ulimit: open files: cannot modify limit"), "{}", run.stderr);
+    assert_eq!(script("ulimit -z").status, 2);
+    assert_eq!(script("ulimit -n lots").status, 1);
+    assert_eq!(script("limit -n 10").status, 2);
+}
+
+#[test]
+fn time_reports_usage_in_timeformat() {
+    let run = script("TIMEFORMAT='real=%2R user=%1U sys=%lS rss=%M %%'; time sleep 0.2");
+    assert_eq!(run.status, 0);
+    let fields: Vec<&str> = run.stderr.trim_end().split(' ').collect();
+    let [real, user, sys, rss, percent] = fields[..] else {
+        panic!("{:?}", run.stderr);
+    };
+    let real = real.strip_prefix("real=").unwrap();
+    assert!(real.parse::<f64>().unwrap() >= 0.2 && real.split('.').nth(1).unwrap().len() == 2, "{real}");
+    let user = user.strip_prefix("user=").unwrap();
+    assert_eq!(user.split('.').nth(1).unwrap().len(), 1, "{user}");
+    let sys = sys.strip_prefix("sys=").unwrap();
+    assert!(sys.starts_with("0m0.") && sys.ends_with('s') && sys.len() == "0m0.000s".len(), "{sys}");
+    assert!(rss.strip_prefix("rss=").unwrap().parse::<u64>().unwrap() > 0, "{rss}");
+    assert_eq!(percent, "%");
+
+    let run = script("TIMEFORMAT=; time true; time -p sleep 0.1");
+    let lines: Vec<&str> = run.stderr.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", run.stderr);
+    assert!(lines[0].starts_with("real 0.1") && lines[1].starts_with("user 0.") && lines[2].starts_with("sys 0."), "{lines:?}");
+}
+
+#[test]
+fn directory_stack_and_cdpath() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir = dir.path().canonicalize().unwrap();
+    std::fs::create_dir_all(dir.join("a/x")).unwrap();
+    std::fs::create_dir(dir.join("b")).unwrap();
+    let d = dir.display();
+    assert_output(
+        &format!("HOME={d}; cd {d}; pushd a; pushd ../b; dirs -v; dirs -l +1; pushd +2; popd -0; dirs -p; popd"),
+        &format!("~/a ~\n~/b ~/a ~\n 0  ~/b\n 1  ~/a\n 2  ~\n{d}/a\n~ ~/b ~/a\n~ ~/b\n~\n~/b\n~/b\n"),
+    );
+    let run = script("popd; echo $?; pushd +1; echo $?");
+    assert_eq!(run.stdout, "1\n1\n");
+    assert!(run.stderr.contains("popd: directory stack empty"), "{}", run.stderr);
+    assert!(run.stderr.contains("pushd: +1: directory stack index out of range"), "{}", run.stderr);
+
+    // A CDPATH hit is announced; an explicitly relative path skips the search.
+    assert_output(&format!("CDPATH=:{d}/a; cd /; cd x; pwd; cd {d}; cd ./x 2>/dev/null; echo $?"), &format!("{d}/a/x\n{d}/a/x\n1\n"));
+}
+
+#[test]
+fn prompt_escapes_in_ps4() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir = dir.path().canonicalize().unwrap();
+    std::fs::create_dir(dir.join("work")).unwrap();
+    let text = format!(r"HOME={}; cd $HOME/work; PS4='\w|\W|\?|\101\102|\s|\\|'; set -x; false || true", dir.display());
+    let run = script(&text);
+    assert_eq!(run.stderr, "~/work|work|0|AB|vssh|\\|false\n~/work|work|1|AB|vssh|\\|true\n");
+
+    let run = script(r"PS4='\D{%Y}+\D{}|'; set -x; true");
+    let (year, rest) = run.stderr.split_once('+').unwrap();
+    assert!(year.len() == 4 && year.parse::<u32>().is_ok(), "{}", run.stderr);
+    assert!(rest.ends_with("|true\n") && rest.contains(':'), "{}", run.stderr);
+
+    // Assignment-only commands are traced too, one assignment per line.
+    let run = script("set -x; a=1 b='x y'; c=($a z); echo $b");
+    assert_eq!(run.stdout, "x y\n");
+    assert_eq!(run.stderr, "+ a=1\n+ b='x y'\n+ c=(1 z)\n+ echo x y\n");
+}